@@ -1,7 +1,17 @@
+use crate::util::parse_bounding_box;
+use crate::util::parse_class_distance_threshold;
 use crate::util::parse_duration;
+use crate::util::parse_frame_sensor_profile;
+use crate::util::parse_positive_number;
 use crate::util::parse_timestamp;
 use chrono::{DateTime, Utc};
-use clap::{Parser, Subcommand};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use sensor_data_analyzer::{
+    AssociationMode, BoundingBox, CopyFormat, DistanceThresholds, DownloadChunking, DownloadFormat,
+    RangeImageFormat, SensorProfileRegistry, StatisticsFormat, UploadMethod,
+    DEFAULT_DISTANCE_THRESHOLD,
+};
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None, propagate_version = true)]
@@ -13,7 +23,11 @@ pub struct Arguments {
 #[derive(Subcommand)]
 pub enum Commands {
     /// Clear database from sensor data
-    Clear {},
+    Clear {
+        /// Name of the dataset to be cleared, while keeping the others (default: all datasets)
+        #[clap(long)]
+        dataset: Option<String>,
+    },
 
     /// Upload ROS bag to the database
     UploadRosbag {
@@ -21,6 +35,18 @@ pub enum Commands {
         #[clap(short, long)]
         rosbag_directory_path: String,
 
+        /// Name of the dataset, which is created if it does not exist
+        #[clap(long, default_value = "default")]
+        dataset: String,
+
+        /// Identifier of the pointcloud format (default: reuse or register a matching format)
+        #[clap(long)]
+        pcid: Option<u32>,
+
+        /// Spatial reference system of the point cloud (default: srid of the 3DCityDB)
+        #[clap(long)]
+        srid: Option<i32>,
+
         /// Path to additional georeferencing
         #[clap(long)]
         ecoord_file_path: String,
@@ -51,28 +77,141 @@ pub enum Commands {
         #[clap(long, value_parser = parse_duration, default_value = "500ms")]
         step_duration: chrono::Duration,
 
+        /// Method for ingesting the point clouds into the database
+        #[clap(long, value_enum, default_value_t = UploadMethodArgument::Insert)]
+        upload_method: UploadMethodArgument,
+
+        /// Format of the data streamed via COPY, where binary is only supported for copy-beam
+        #[clap(long, value_enum, default_value_t = CopyFormatArgument::Text)]
+        copy_format: CopyFormatArgument,
+
+        /// Maximum number of steps held in memory while extracting, georeferencing and uploading
+        #[clap(long, default_value = "8")]
+        maximum_buffered_steps: usize,
+
+        /// Resume a previously interrupted upload of the same rosbag and time window
+        #[clap(long)]
+        resume: bool,
+
         /// Maximum number of connections to the database
         #[clap(long, default_value = "30")]
         maximum_number_connections: usize,
     },
 
+    /// Extract a mesh per lidar from the point clouds of a rosbag
+    ExtractMesh {
+        /// Path to the rosbag
+        #[clap(short, long)]
+        rosbag_directory_path: String,
+
+        /// Path to the output directory of the meshes
+        #[clap(short, long)]
+        output_directory_path: String,
+
+        /// Frame ids of the lidars (default: all lidars with assigned sensor profile)
+        #[clap(long, value_delimiter = ',')]
+        frame_ids: Vec<String>,
+
+        /// Sensor profile of a lidar
+        /// Example: lidar_front_center=VLP-16
+        #[clap(long, value_parser = parse_frame_sensor_profile)]
+        sensor_profile: Vec<(String, String)>,
+
+        /// Paths to TOML or JSON files with additional sensor profiles and frame assignments
+        #[clap(long)]
+        sensor_profile_file_path: Vec<String>,
+
+        /// Start time of the extraction
+        /// Example: 2020-04-12 22:10:57.123456789 +02:00
+        #[clap(long, value_parser = parse_timestamp)]
+        start_date_time: Option<DateTime<Utc>>,
+
+        /// Stop time of the extraction
+        /// Example: 2020-04-12 22:10:57.123456789 +02:00
+        #[clap(long, value_parser = parse_timestamp)]
+        stop_date_time: Option<DateTime<Utc>>,
+
+        /// Offset of the start time
+        #[clap(long, value_parser = parse_duration)]
+        start_time_offset: Option<chrono::Duration>,
+
+        /// Duration of the extraction (default: 100ms, a single sweep at 10 Hz)
+        #[clap(long, value_parser = parse_duration)]
+        total_duration: Option<chrono::Duration>,
+
+        /// Maximum length of a triangle edge in meters
+        #[clap(long, default_value_t = 2.0)]
+        maximum_edge_length: f64,
+
+        /// Maximum relative range difference of a triangle's points to connect them
+        #[clap(long, default_value_t = 0.1)]
+        maximum_depth_discontinuity: f64,
+    },
+
     /// Upload point cloud to the database
     UploadPointCloud {
         /// Path to the point cloud to be uploaded
         #[clap(short, long)]
         point_cloud_file_path: String,
+
+        /// Name of the dataset, which is created if it does not exist
+        #[clap(long, default_value = "default")]
+        dataset: String,
+
+        /// Identifier of the pointcloud format (default: reuse or register a matching format)
+        #[clap(long)]
+        pcid: Option<u32>,
+
+        /// Spatial reference system of the point cloud (default: srid of the 3DCityDB)
+        #[clap(long)]
+        srid: Option<i32>,
     },
 
     /// Associate sensor data with model
     Associate {
-        /// Distance between point and model threshold
-        #[clap(short, long, default_value = "0.2")]
-        distance_threshold: f32,
+        /// Name of the dataset
+        #[clap(long, default_value = "default")]
+        dataset: String,
+
+        /// Distance between point and model threshold for features without class threshold
+        /// (default: 0.2 if no class thresholds are given)
+        #[clap(short, long)]
+        distance_threshold: Option<f32>,
+
+        /// Distance threshold for the features of a class, e.g. SolitaryVegetationObject=0.5
+        #[clap(long, value_parser = parse_class_distance_threshold)]
+        class_distance_threshold: Vec<(String, f32)>,
+
+        /// Path to a JSON file with the default and class distance thresholds, which are
+        /// overridden by the other options
+        #[clap(long)]
+        distance_threshold_file_path: Option<String>,
+
+        /// Only geometries of these LoDs, e.g. 2,3
+        #[clap(long, value_delimiter = ',')]
+        lods: Vec<String>,
+
+        /// Only geometries of these geometry properties, e.g. lod2MultiSurface
+        #[clap(long, value_delimiter = ',')]
+        geometry_properties: Vec<String>,
+
+        /// Associate with the thematic surfaces and openings instead of the features bounded by them
+        #[clap(long, default_value = "false")]
+        thematic_surfaces: bool,
+
+        /// Features associated with a point within the distance threshold
+        #[clap(long, value_enum, default_value_t = AssociationModeArgument::AllCandidates)]
+        association_mode: AssociationModeArgument,
 
         /// Associate the points also intersecting the beams with the model surfaces
         #[clap(short, long, default_value = "false")]
         beam_intersection: bool,
 
+        /// Detect surfaces the beams pass through up to this distance in meters before their
+        /// reflection
+        #[clap(long)]
+        pass_through_tolerance: Option<f32>,
+
         /// Keep temporary table entries
         #[clap(short, long, default_value = "false")]
         keep_temporary_table_entries: bool,
@@ -82,12 +221,97 @@ pub enum Commands {
         maximum_number_connections: usize,
     },
 
+    /// Write a summary of the observations per city object as generic attributes into the 3DCityDB
+    Enrich {
+        /// Name of the dataset
+        #[clap(long, default_value = "default")]
+        dataset: String,
+
+        /// Only print the attributes that would be written
+        #[clap(long, default_value = "false")]
+        dry_run: bool,
+    },
+
+    /// Rasterize the surfaces of the associated city objects and count the reflections per cell
+    Coverage {
+        /// Name of the dataset
+        #[clap(long, default_value = "default")]
+        dataset: String,
+
+        /// Edge length of the square cells in meters
+        #[clap(long, default_value_t = 0.5, value_parser = parse_positive_number)]
+        cell_size: f64,
+
+        /// Path to the glTF file to which the cells are exported as colored mesh
+        #[clap(long)]
+        gltf_file_path: Option<String>,
+
+        /// Number of reflections per cell, from which on a cell is colored as fully covered
+        #[clap(long, default_value_t = 10)]
+        full_coverage_number_of_points: i32,
+
+        /// Only export the previously computed cells
+        #[clap(long, default_value = "false")]
+        export_only: bool,
+    },
+
     /// Download point clouds from the database
     Download {
+        /// Name of the dataset
+        #[clap(long, default_value = "default")]
+        dataset: String,
+
         /// Directory path to the files stored
         #[clap(short, long)]
         directory_path: String,
 
+        /// File format of the downloaded point clouds
+        #[clap(long, value_enum, default_value_t = DownloadFormatArgument::Xyz)]
+        format: DownloadFormatArgument,
+
+        /// Splitting of the downloaded points into files
+        #[clap(long, value_enum, default_value_t = DownloadChunkingArgument::Patch)]
+        chunking: DownloadChunkingArgument,
+
+        /// Duration of a chunk when splitting by time interval
+        #[clap(long, value_parser = parse_duration, default_value = "10s")]
+        chunk_duration: chrono::Duration,
+
+        /// Edge length of a chunk in meters when splitting by spatial tile
+        #[clap(long, default_value = "100.0")]
+        tile_size: f64,
+
+        /// Only points associated with these gml_ids
+        #[clap(long, value_delimiter = ',')]
+        gml_ids: Vec<String>,
+
+        /// Only points associated with features of these classes, e.g. WallSurface
+        #[clap(long, value_delimiter = ',')]
+        classnames: Vec<String>,
+
+        /// Only points observed at or after this time
+        /// Example: 2020-04-12 22:10:57.123456789 +02:00
+        #[clap(long, value_parser = parse_timestamp)]
+        start_date_time: Option<DateTime<Utc>>,
+
+        /// Only points observed before this time
+        /// Example: 2020-04-12 22:10:57.123456789 +02:00
+        #[clap(long, value_parser = parse_timestamp)]
+        stop_date_time: Option<DateTime<Utc>>,
+
+        /// Only points within this bounding box
+        /// Example: min_x,min_y,max_x,max_y or min_x,min_y,min_z,max_x,max_y,max_z
+        #[clap(long, value_parser = parse_bounding_box, allow_hyphen_values = true)]
+        bounding_box: Option<BoundingBox>,
+
+        /// Only points within this polygon given as well-known text
+        #[clap(long)]
+        polygon: Option<String>,
+
+        /// Only points not associated with any feature
+        #[clap(long, conflicts_with_all = ["gml_ids", "classnames"])]
+        unassociated_only: bool,
+
         /// Keep temporary table entries
         #[clap(short, long, default_value = "false")]
         keep_temporary_table_entries: bool,
@@ -97,6 +321,400 @@ pub enum Commands {
         maximum_number_connections: usize,
     },
 
+    /// Export the associated scans as SemanticKITTI sequence with labels by CityGML class
+    ExportSemanticKitti {
+        /// Path to the rosbag the dataset was uploaded from
+        #[clap(short, long)]
+        rosbag_directory_path: String,
+
+        /// Name of the dataset
+        #[clap(long, default_value = "default")]
+        dataset: String,
+
+        /// Directory path to which the sequence is written
+        #[clap(short, long)]
+        directory_path: String,
+
+        /// Number of the sequence
+        #[clap(long, default_value_t = 0)]
+        sequence: u32,
+
+        /// Path to the ecoord file with the transforms used for uploading
+        #[clap(long)]
+        ecoord_file_path: String,
+
+        /// Frame id of the lidar sensor to which the scans are resolved
+        #[clap(long)]
+        sensor_frame_id: String,
+
+        /// Start time of the export
+        /// Example: 2020-04-12 22:10:57.123456789 +02:00
+        #[clap(long, value_parser = parse_timestamp)]
+        start_date_time: Option<DateTime<Utc>>,
+
+        /// Stop time of the export
+        /// Example: 2020-04-12 22:10:57.123456789 +02:00
+        #[clap(long, value_parser = parse_timestamp)]
+        stop_date_time: Option<DateTime<Utc>>,
+
+        /// Offset of the start time
+        #[clap(long, value_parser = parse_duration)]
+        start_time_offset: Option<chrono::Duration>,
+
+        /// Duration of the export
+        #[clap(long, value_parser = parse_duration)]
+        total_duration: Option<chrono::Duration>,
+
+        /// Duration of a single step for reading the rosbag
+        #[clap(long, value_parser = parse_duration, default_value = "500ms")]
+        step_duration: chrono::Duration,
+    },
+
+    /// Export the scans of a rosbag as range images with the associations per lidar and message
+    ExportRangeImages {
+        /// Path to the rosbag the dataset was uploaded from
+        #[clap(short, long)]
+        rosbag_directory_path: String,
+
+        /// Name of the dataset
+        #[clap(long, default_value = "default")]
+        dataset: String,
+
+        /// Directory path to which the range images are written
+        #[clap(short, long)]
+        directory_path: String,
+
+        /// Formats of the range images
+        #[clap(long, value_enum, value_delimiter = ',', default_values_t = [RangeImageFormatArgument::Png, RangeImageFormatArgument::Npy])]
+        formats: Vec<RangeImageFormatArgument>,
+
+        /// Sensor profile of a lidar
+        /// Example: lidar_front_center=VLP-16
+        #[clap(long, value_parser = parse_frame_sensor_profile)]
+        sensor_profile: Vec<(String, String)>,
+
+        /// Paths to TOML or JSON files with additional sensor profiles and frame assignments
+        #[clap(long)]
+        sensor_profile_file_path: Vec<String>,
+
+        /// Start time of the export
+        /// Example: 2020-04-12 22:10:57.123456789 +02:00
+        #[clap(long, value_parser = parse_timestamp)]
+        start_date_time: Option<DateTime<Utc>>,
+
+        /// Stop time of the export
+        /// Example: 2020-04-12 22:10:57.123456789 +02:00
+        #[clap(long, value_parser = parse_timestamp)]
+        stop_date_time: Option<DateTime<Utc>>,
+
+        /// Offset of the start time
+        #[clap(long, value_parser = parse_duration)]
+        start_time_offset: Option<chrono::Duration>,
+
+        /// Duration of the export
+        #[clap(long, value_parser = parse_duration)]
+        total_duration: Option<chrono::Duration>,
+
+        /// Duration of a single step
+        #[clap(long, value_parser = parse_duration, default_value = "500ms")]
+        step_duration: chrono::Duration,
+    },
+
+    /// Write the associations as labelled point cloud topics back into the rosbag
+    LabelRosbag {
+        /// Path to the rosbag the dataset was uploaded from
+        #[clap(short, long)]
+        rosbag_directory_path: String,
+
+        /// Name of the dataset
+        #[clap(long, default_value = "default")]
+        dataset: String,
+
+        /// Prefix of the written topics, which is followed by the frame id of each lidar
+        #[clap(long, default_value = "/labelled")]
+        topic_prefix: String,
+
+        /// Start time of the labelling
+        /// Example: 2020-04-12 22:10:57.123456789 +02:00
+        #[clap(long, value_parser = parse_timestamp)]
+        start_date_time: Option<DateTime<Utc>>,
+
+        /// Stop time of the labelling
+        /// Example: 2020-04-12 22:10:57.123456789 +02:00
+        #[clap(long, value_parser = parse_timestamp)]
+        stop_date_time: Option<DateTime<Utc>>,
+
+        /// Offset of the start time
+        #[clap(long, value_parser = parse_duration)]
+        start_time_offset: Option<chrono::Duration>,
+
+        /// Duration of the labelling
+        #[clap(long, value_parser = parse_duration)]
+        total_duration: Option<chrono::Duration>,
+
+        /// Duration of a single step
+        #[clap(long, value_parser = parse_duration, default_value = "500ms")]
+        step_duration: chrono::Duration,
+    },
+
     /// Stats
-    Stats {},
+    Stats {
+        /// Name of the dataset (default: all datasets)
+        #[clap(long)]
+        dataset: Option<String>,
+
+        /// Output format of the statistics
+        #[clap(long, value_enum, default_value_t = StatisticsFormatArgument::Table)]
+        format: StatisticsFormatArgument,
+
+        /// Path to the output file (default: standard output)
+        #[clap(long)]
+        output_path: Option<String>,
+
+        /// Width of the surface distance histogram bins in meters
+        #[clap(long, default_value = "0.05", value_parser = parse_positive_number)]
+        distance_bin_width: f64,
+    },
+
+    /// Flag consistently hit points far from the city model and unobserved city objects
+    AnalyzeChanges {
+        /// Name of the dataset
+        #[clap(long, default_value = "default")]
+        dataset: String,
+
+        /// Output format of the report
+        #[clap(long, value_enum, default_value_t = StatisticsFormatArgument::Table)]
+        format: StatisticsFormatArgument,
+
+        /// Path to the output file (default: standard output)
+        #[clap(long)]
+        output_path: Option<String>,
+
+        /// Edge length in meters of the voxels into which unassociated points are aggregated
+        #[clap(long, default_value = "0.5")]
+        voxel_size: f64,
+
+        /// Duration of the intervals in which the hits of a voxel are counted
+        #[clap(long, value_parser = parse_duration, default_value = "1s")]
+        observation_interval: chrono::Duration,
+
+        /// Number of distinct intervals in which a voxel must be hit
+        #[clap(long, default_value = "3")]
+        minimum_observation_times: usize,
+
+        /// Number of points a cluster must contain
+        #[clap(long, default_value = "10")]
+        minimum_cluster_size: usize,
+
+        /// Distance in meters up to which surfaces facing the trajectory are expected to be hit
+        #[clap(long, default_value = "30.0")]
+        sensor_range: f64,
+
+        /// Number of associated points and beam intersections up to which a city object counts as
+        /// unobserved
+        #[clap(long, default_value = "0")]
+        maximum_unobserved_points: i64,
+
+        /// Path to the directory to which the points of each cluster are written
+        #[clap(long)]
+        cluster_directory_path: Option<String>,
+
+        /// Format of the cluster point clouds
+        #[clap(long, value_enum, default_value_t = DownloadFormatArgument::Xyz)]
+        cluster_format: DownloadFormatArgument,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum AssociationModeArgument {
+    /// Every feature within the distance threshold
+    AllCandidates,
+    /// Only the nearest feature, ties broken by the lowest feature id
+    NearestFeature,
+}
+
+impl From<AssociationModeArgument> for AssociationMode {
+    fn from(value: AssociationModeArgument) -> Self {
+        match value {
+            AssociationModeArgument::AllCandidates => AssociationMode::AllCandidates,
+            AssociationModeArgument::NearestFeature => AssociationMode::NearestFeature,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum UploadMethodArgument {
+    /// One INSERT statement per patch
+    Insert,
+    /// Stream patches into point_cloud_upload via COPY
+    CopyPatch,
+    /// Stream exploded beams into beam via COPY
+    CopyBeam,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CopyFormatArgument {
+    Text,
+    Binary,
+}
+
+impl From<CopyFormatArgument> for CopyFormat {
+    fn from(value: CopyFormatArgument) -> Self {
+        match value {
+            CopyFormatArgument::Text => CopyFormat::Text,
+            CopyFormatArgument::Binary => CopyFormat::Binary,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum DownloadFormatArgument {
+    /// Text file colorized by the associated feature
+    Xyz,
+    /// LAS with gml_id, classname and surface_distance as extra bytes
+    Las,
+    /// Compressed LAS with gml_id, classname and surface_distance as extra bytes
+    Laz,
+    /// Native epoint tar archive
+    Epoint,
+    /// Columnar Parquet file
+    Parquet,
+}
+
+impl From<DownloadFormatArgument> for DownloadFormat {
+    fn from(value: DownloadFormatArgument) -> Self {
+        match value {
+            DownloadFormatArgument::Xyz => DownloadFormat::Xyz,
+            DownloadFormatArgument::Las => DownloadFormat::Las,
+            DownloadFormatArgument::Laz => DownloadFormat::Laz,
+            DownloadFormatArgument::Epoint => DownloadFormat::Epoint,
+            DownloadFormatArgument::Parquet => DownloadFormat::Parquet,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum RangeImageFormatArgument {
+    /// 16-bit grayscale PNG
+    Png,
+    /// 16-bit grayscale TIFF
+    Tiff,
+    /// NumPy array of float32 values
+    Npy,
+}
+
+impl From<RangeImageFormatArgument> for RangeImageFormat {
+    fn from(value: RangeImageFormatArgument) -> Self {
+        match value {
+            RangeImageFormatArgument::Png => RangeImageFormat::Png,
+            RangeImageFormatArgument::Tiff => RangeImageFormat::Tiff,
+            RangeImageFormatArgument::Npy => RangeImageFormat::Npy,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum DownloadChunkingArgument {
+    /// One file per uploaded patch
+    Patch,
+    /// A single file for the whole dataset
+    Merged,
+    /// One file per time interval of the chunk duration
+    TimeInterval,
+    /// One file per square tile of the tile size
+    SpatialTile,
+    /// One file per associated feature named by its gml_id
+    Feature,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum StatisticsFormatArgument {
+    /// Human-readable table
+    Table,
+    /// JSON array with one object per dataset
+    Json,
+    /// CSV with one row per dataset, metric and key
+    Csv,
+}
+
+impl From<StatisticsFormatArgument> for StatisticsFormat {
+    fn from(value: StatisticsFormatArgument) -> Self {
+        match value {
+            StatisticsFormatArgument::Table => StatisticsFormat::Table,
+            StatisticsFormatArgument::Json => StatisticsFormat::Json,
+            StatisticsFormatArgument::Csv => StatisticsFormat::Csv,
+        }
+    }
+}
+
+pub fn derive_download_chunking(
+    chunking: DownloadChunkingArgument,
+    chunk_duration: chrono::Duration,
+    tile_size: f64,
+) -> DownloadChunking {
+    match chunking {
+        DownloadChunkingArgument::Patch => DownloadChunking::Patch,
+        DownloadChunkingArgument::Merged => DownloadChunking::Merged,
+        DownloadChunkingArgument::TimeInterval => DownloadChunking::TimeInterval(chunk_duration),
+        DownloadChunkingArgument::SpatialTile => DownloadChunking::SpatialTile(tile_size),
+        DownloadChunkingArgument::Feature => DownloadChunking::Feature,
+    }
+}
+
+/// Returns the upload method, where patches can only be streamed in the text format.
+pub fn derive_upload_method(
+    upload_method: UploadMethodArgument,
+    copy_format: CopyFormatArgument,
+) -> Result<UploadMethod, clap::Error> {
+    match (upload_method, copy_format) {
+        (UploadMethodArgument::Insert, _) => Ok(UploadMethod::Insert),
+        (UploadMethodArgument::CopyPatch, CopyFormatArgument::Binary) => Err(Arguments::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--copy-format binary is not supported with --upload-method copy-patch",
+            )),
+        (UploadMethodArgument::CopyPatch, _) => Ok(UploadMethod::CopyPatch(copy_format.into())),
+        (UploadMethodArgument::CopyBeam, _) => Ok(UploadMethod::CopyBeam(copy_format.into())),
+    }
+}
+
+pub fn derive_distance_thresholds(
+    distance_threshold: Option<f32>,
+    class_distance_thresholds: &[(String, f32)],
+    distance_threshold_file_path: Option<&str>,
+) -> DistanceThresholds {
+    let mut distance_thresholds = match distance_threshold_file_path {
+        Some(path) => DistanceThresholds::from_json_file(path).unwrap(),
+        None => DistanceThresholds {
+            default: None,
+            classes: Default::default(),
+        },
+    };
+    if distance_threshold.is_some() {
+        distance_thresholds.default = distance_threshold;
+    }
+    distance_thresholds
+        .classes
+        .extend(class_distance_thresholds.iter().cloned());
+
+    if distance_thresholds.maximum().is_none() {
+        distance_thresholds.default = Some(DEFAULT_DISTANCE_THRESHOLD);
+    }
+    distance_thresholds
+}
+
+pub fn derive_sensor_profile_registry(
+    sensor_profile_file_paths: &[String],
+    frame_sensor_profiles: &[(String, String)],
+) -> SensorProfileRegistry {
+    let mut sensor_profile_registry = SensorProfileRegistry::default();
+    for path in sensor_profile_file_paths {
+        sensor_profile_registry.add_file(path).unwrap();
+    }
+    for (frame_id, profile_name) in frame_sensor_profiles {
+        sensor_profile_registry
+            .assign_frame(frame_id.as_str(), profile_name)
+            .unwrap();
+    }
+    sensor_profile_registry
 }
@@ -0,0 +1,42 @@
+use crate::commands::ENV_VARIABLE_DATABASE_URL;
+use sensor_data_analyzer::{
+    write_change_report, ChangeDetectionParameters, DatabaseManager, DownloadFormat,
+    StatisticsFormat,
+};
+use std::fs::File;
+use std::path::PathBuf;
+use std::time::Instant;
+use std::{env, io};
+use tracing::info;
+
+#[tokio::main]
+pub async fn run(
+    dataset: &str,
+    parameters: ChangeDetectionParameters,
+    format: StatisticsFormat,
+    output_path: Option<PathBuf>,
+    cluster_directory_path: Option<PathBuf>,
+    cluster_format: DownloadFormat,
+) {
+    info!("Run analyze changes of dataset {dataset} with: {parameters:?}");
+
+    let database_url = env::var(ENV_VARIABLE_DATABASE_URL).unwrap();
+    let database_manager = DatabaseManager::new(&database_url, 10);
+
+    let start = Instant::now();
+    let report = database_manager
+        .analyze_changes(dataset, parameters, cluster_directory_path, cluster_format)
+        .await
+        .unwrap();
+    let duration = start.elapsed();
+    info!("Change analysis took {:?}.", duration);
+
+    match output_path {
+        Some(output_path) => {
+            let file = File::create(&output_path).unwrap();
+            write_change_report(&report, format, file).unwrap();
+            info!("Wrote change report to {}", output_path.display());
+        }
+        None => write_change_report(&report, format, io::stdout().lock()).unwrap(),
+    }
+}
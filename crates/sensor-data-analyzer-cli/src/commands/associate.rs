@@ -1,17 +1,24 @@
 use crate::commands::ENV_VARIABLE_DATABASE_URL;
-use sensor_data_analyzer::DatabaseManager;
+use sensor_data_analyzer::{
+    AssociationMode, DatabaseManager, DistanceThresholds, GeometrySelection,
+};
 use std::env;
 use std::time::Instant;
 use tracing::info;
 
+#[allow(clippy::too_many_arguments)]
 #[tokio::main]
 pub async fn run(
-    distance_threshold: f32,
+    dataset: &str,
+    distance_thresholds: DistanceThresholds,
+    geometry_selection: GeometrySelection,
+    association_mode: AssociationMode,
     beam_intersection: bool,
+    pass_through_tolerance: Option<f32>,
     keep_temporary_table_entries: bool,
     maximum_number_connections: usize,
 ) {
-    info!("Run associate with distance_threshold: {distance_threshold}");
+    info!("Run associate on dataset {dataset} with distance_thresholds: {distance_thresholds:?}, geometry_selection: {geometry_selection:?}, association_mode: {association_mode:?}");
 
     let database_url = env::var(ENV_VARIABLE_DATABASE_URL).unwrap();
     let database_manager = DatabaseManager::new(&database_url, maximum_number_connections);
@@ -19,8 +26,12 @@ pub async fn run(
     let start = Instant::now();
     database_manager
         .associate(
-            distance_threshold,
+            dataset,
+            &distance_thresholds,
+            &geometry_selection,
+            association_mode,
             beam_intersection,
+            pass_through_tolerance,
             keep_temporary_table_entries,
         )
         .await
@@ -5,15 +5,18 @@ use std::time::Instant;
 use tracing::info;
 
 #[tokio::main]
-pub async fn run() {
-    info!("Run stats");
+pub async fn run(dataset: Option<String>) {
+    info!("Run clear");
 
     let database_url = env::var(ENV_VARIABLE_DATABASE_URL).unwrap();
     let maximum_of_connections = 10;
     let database_manager = DatabaseManager::new(&database_url, maximum_of_connections);
 
     let start = Instant::now();
-    database_manager.clean().await.unwrap();
+    match &dataset {
+        Some(dataset) => database_manager.clean_dataset(dataset).await.unwrap(),
+        None => database_manager.clean().await.unwrap(),
+    }
     let duration = start.elapsed();
     info!(
         "Clearing process took {:?} with {maximum_of_connections}.",
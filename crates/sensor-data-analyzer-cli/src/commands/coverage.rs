@@ -0,0 +1,37 @@
+use crate::commands::ENV_VARIABLE_DATABASE_URL;
+use sensor_data_analyzer::DatabaseManager;
+use std::env;
+use std::path::PathBuf;
+use std::time::Instant;
+use tracing::info;
+
+#[tokio::main]
+pub async fn run(
+    dataset: &str,
+    cell_size: f64,
+    gltf_file_path: Option<PathBuf>,
+    full_coverage_number_of_points: i32,
+    export_only: bool,
+) {
+    info!("Run coverage of dataset {dataset} with cell size: {cell_size}");
+
+    let database_url = env::var(ENV_VARIABLE_DATABASE_URL).unwrap();
+    let database_manager = DatabaseManager::new(&database_url, 10);
+
+    let start = Instant::now();
+    if !export_only {
+        database_manager
+            .compute_surface_coverage(dataset, cell_size)
+            .await
+            .unwrap();
+    }
+    if let Some(gltf_file_path) = gltf_file_path {
+        info!("Export coverage to {}", gltf_file_path.display());
+        database_manager
+            .export_surface_coverage(dataset, gltf_file_path, full_coverage_number_of_points)
+            .await
+            .unwrap();
+    }
+    let duration = start.elapsed();
+    info!("Coverage process took {:?}.", duration);
+}
@@ -1,18 +1,22 @@
 use std::{env, fs};
 
 use crate::commands::ENV_VARIABLE_DATABASE_URL;
-use sensor_data_analyzer::DatabaseManager;
+use sensor_data_analyzer::{DatabaseManager, DownloadChunking, DownloadFilter, DownloadFormat};
 use std::path::Path;
 use std::time::Instant;
 use tracing::info;
 
 #[tokio::main]
 pub async fn run(
+    dataset: &str,
     directory_path: impl AsRef<Path>,
+    format: DownloadFormat,
+    chunking: DownloadChunking,
+    filter: DownloadFilter,
     keep_temporary_table_entries: bool,
     maximum_number_connections: usize,
 ) {
-    info!("Start download");
+    info!("Start download in format {format:?}");
 
     if directory_path.as_ref().exists() {
         fs::remove_dir_all(&directory_path).expect("TODO: panic message");
@@ -24,7 +28,14 @@ pub async fn run(
 
     let start = Instant::now();
     database_manager
-        .download(&directory_path, keep_temporary_table_entries)
+        .download(
+            dataset,
+            &directory_path,
+            format,
+            chunking,
+            &filter,
+            keep_temporary_table_entries,
+        )
         .await
         .unwrap();
     let duration = start.elapsed();
@@ -0,0 +1,45 @@
+use crate::commands::ENV_VARIABLE_DATABASE_URL;
+use sensor_data_analyzer::DatabaseManager;
+use std::env;
+use std::time::Instant;
+use tracing::info;
+
+#[tokio::main]
+pub async fn run(dataset: &str, dry_run: bool) {
+    info!("Run enrich with observations of dataset {dataset} (dry run: {dry_run})");
+
+    let database_url = env::var(ENV_VARIABLE_DATABASE_URL).unwrap();
+    let database_manager = DatabaseManager::new(&database_url, 10);
+
+    let start = Instant::now();
+    let summaries = database_manager
+        .enrich_city_model(dataset, dry_run)
+        .await
+        .unwrap();
+    let duration = start.elapsed();
+    info!("Enrichment process took {:?}.", duration);
+
+    if dry_run {
+        for summary in summaries {
+            println!(
+                "{} ({}): number_of_points={}, number_of_beam_intersections={}, first_observed={}, last_observed={}, mean_distance={}, coverage={}",
+                summary.gml_id,
+                summary.classname,
+                summary.number_of_points,
+                summary.number_of_beam_intersections,
+                summary
+                    .first_observed
+                    .map_or("-".to_string(), |d| d.to_rfc3339()),
+                summary
+                    .last_observed
+                    .map_or("-".to_string(), |d| d.to_rfc3339()),
+                summary
+                    .mean_distance
+                    .map_or("-".to_string(), |d| format!("{d:.4}")),
+                summary
+                    .coverage
+                    .map_or("-".to_string(), |c| format!("{c:.4}")),
+            );
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use crate::commands::upload_rosbag::derive_time_window;
+use crate::commands::ENV_VARIABLE_DATABASE_URL;
+use chrono::{DateTime, Duration, Utc};
+use ecoord::FrameId;
+use erosbag::RosbagOpenOptions;
+use sensor_data_analyzer::{DatabaseManager, SensorFrame};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tracing::info;
+
+#[allow(clippy::too_many_arguments)]
+#[tokio::main]
+pub async fn run(
+    rosbag_directory_path: impl AsRef<Path>,
+    dataset: &str,
+    directory_path: PathBuf,
+    sequence: u32,
+    ecoord_file_path: PathBuf,
+    sensor_frame_id: &str,
+    step_duration: Duration,
+    start_date_time: Option<DateTime<Utc>>,
+    stop_date_time: Option<DateTime<Utc>>,
+    start_time_offset: Option<Duration>,
+    total_duration: Option<Duration>,
+) {
+    info!(
+        "Export dataset {dataset} as SemanticKITTI sequence {sequence} to {}",
+        directory_path.display()
+    );
+
+    let rosbag = RosbagOpenOptions::new()
+        .read_write(false)
+        .open(rosbag_directory_path.as_ref())
+        .unwrap();
+    let (start_date_time, stop_date_time) = derive_time_window(
+        &rosbag,
+        start_date_time,
+        stop_date_time,
+        start_time_offset,
+        total_duration,
+    );
+
+    let reference_frames = ecoord::io::EcoordReader::from_path(ecoord_file_path)
+        .unwrap()
+        .finish()
+        .unwrap();
+    let sensor_frame = SensorFrame {
+        reference_frames,
+        frame_id: FrameId::from(sensor_frame_id),
+    };
+
+    let database_url = env::var(ENV_VARIABLE_DATABASE_URL).unwrap();
+    let database_manager = DatabaseManager::new(&database_url, 10);
+
+    let start = Instant::now();
+    let number_of_scans = database_manager
+        .export_semantic_kitti(
+            dataset,
+            rosbag,
+            step_duration,
+            start_date_time,
+            stop_date_time,
+            directory_path,
+            sequence,
+            sensor_frame,
+        )
+        .await
+        .unwrap();
+    let duration = start.elapsed();
+    info!("Export of {number_of_scans} scans took {:?}.", duration);
+}
@@ -0,0 +1,64 @@
+use crate::commands::upload_rosbag::derive_time_window;
+
+use chrono::{DateTime, Duration, Utc};
+use ecoord::FrameId;
+use erosbag::RosbagOpenOptions;
+use sensor_data_analyzer::{
+    extract_lidar_meshes, RangeImageMeshingParameters, SensorProfileRegistry,
+};
+use std::path::Path;
+use std::time::Instant;
+use tracing::info;
+
+/// Duration of the extraction if neither a stop time nor a duration is given.
+const DEFAULT_TOTAL_DURATION_MILLISECONDS: i64 = 100;
+
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    rosbag_directory_path: impl AsRef<Path>,
+    output_directory_path: impl AsRef<Path>,
+    frame_ids: &[String],
+    sensor_profile_registry: SensorProfileRegistry,
+    start_date_time: Option<DateTime<Utc>>,
+    stop_date_time: Option<DateTime<Utc>>,
+    start_time_offset: Option<Duration>,
+    total_duration: Option<Duration>,
+    meshing_parameters: RangeImageMeshingParameters,
+) {
+    info!("Start extracting meshes");
+
+    let rosbag = RosbagOpenOptions::new()
+        .read_write(false)
+        .open(rosbag_directory_path.as_ref())
+        .unwrap();
+    let total_duration = match (total_duration, stop_date_time) {
+        (None, None) => Some(Duration::milliseconds(DEFAULT_TOTAL_DURATION_MILLISECONDS)),
+        _ => total_duration,
+    };
+    let (start_date_time, stop_date_time) = derive_time_window(
+        &rosbag,
+        start_date_time,
+        stop_date_time,
+        start_time_offset,
+        total_duration,
+    );
+    let frame_ids: Vec<FrameId> = frame_ids
+        .iter()
+        .map(|f| FrameId::from(f.as_str()))
+        .collect();
+
+    let start = Instant::now();
+    let number_of_meshes = extract_lidar_meshes(
+        &rosbag,
+        &sensor_profile_registry,
+        &frame_ids,
+        start_date_time,
+        stop_date_time,
+        &meshing_parameters,
+        output_directory_path,
+    )
+    .unwrap();
+
+    let duration = start.elapsed();
+    info!("Extracting {number_of_meshes} meshes took {:?}.", duration);
+}
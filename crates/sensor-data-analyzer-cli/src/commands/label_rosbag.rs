@@ -0,0 +1,60 @@
+use crate::commands::upload_rosbag::derive_time_window;
+use crate::commands::ENV_VARIABLE_DATABASE_URL;
+
+use chrono::{DateTime, Duration, Utc};
+use erosbag::RosbagOpenOptions;
+use sensor_data_analyzer::DatabaseManager;
+use std::env;
+use std::path::Path;
+use std::time::Instant;
+use tracing::info;
+
+#[allow(clippy::too_many_arguments)]
+#[tokio::main]
+pub async fn run(
+    rosbag_directory_path: impl AsRef<Path>,
+    dataset: &str,
+    topic_prefix: &str,
+    step_duration: Duration,
+    start_date_time: Option<DateTime<Utc>>,
+    stop_date_time: Option<DateTime<Utc>>,
+    start_time_offset: Option<Duration>,
+    total_duration: Option<Duration>,
+) {
+    info!("Start labelling");
+
+    let rosbag = RosbagOpenOptions::new()
+        .read_write(true)
+        .open(rosbag_directory_path.as_ref())
+        .unwrap();
+    let (start_date_time, stop_date_time) = derive_time_window(
+        &rosbag,
+        start_date_time,
+        stop_date_time,
+        start_time_offset,
+        total_duration,
+    );
+
+    let database_url = env::var(ENV_VARIABLE_DATABASE_URL)
+        .expect("Environment variable ENV_VARIABLE_DATABASE_URL not set.");
+    let database_manager = DatabaseManager::new(&database_url, 10);
+
+    let start = Instant::now();
+    let number_of_messages = database_manager
+        .label_rosbag(
+            dataset,
+            rosbag,
+            topic_prefix,
+            step_duration,
+            start_date_time,
+            stop_date_time,
+        )
+        .await
+        .unwrap();
+
+    let duration = start.elapsed();
+    info!(
+        "Labelling {number_of_messages} messages took {:?}.",
+        duration
+    );
+}
@@ -1,6 +1,13 @@
+pub mod analyze_changes;
 pub mod associate;
 pub mod clear;
+pub mod coverage;
 pub mod download;
+pub mod enrich;
+pub mod export_range_images;
+pub mod export_semantic_kitti;
+pub mod extract_mesh;
+pub mod label_rosbag;
 pub mod stats;
 pub mod upload_point_cloud;
 pub mod upload_rosbag;
@@ -1,14 +1,33 @@
 use crate::commands::ENV_VARIABLE_DATABASE_URL;
-use sensor_data_analyzer::DatabaseManager;
-use std::env;
+use sensor_data_analyzer::{write_statistics, DatabaseManager, StatisticsFormat};
+use std::fs::File;
+use std::path::PathBuf;
+use std::{env, io};
 use tracing::info;
 
 #[tokio::main]
-pub async fn run() {
+pub async fn run(
+    dataset: Option<String>,
+    format: StatisticsFormat,
+    output_path: Option<PathBuf>,
+    distance_bin_width: f64,
+) {
     info!("Run stats");
 
     let database_url = env::var(ENV_VARIABLE_DATABASE_URL).unwrap();
     let database_manager = DatabaseManager::new(&database_url, 10);
 
-    database_manager.run_stats().await.unwrap();
+    let statistics = database_manager
+        .get_statistics(dataset.as_deref(), distance_bin_width)
+        .await
+        .unwrap();
+
+    match output_path {
+        Some(output_path) => {
+            let file = File::create(&output_path).unwrap();
+            write_statistics(&statistics, format, file).unwrap();
+            info!("Wrote statistics to {}", output_path.display());
+        }
+        None => write_statistics(&statistics, format, io::stdout().lock()).unwrap(),
+    }
 }
@@ -1,12 +1,17 @@
 use crate::commands::ENV_VARIABLE_DATABASE_URL;
 use epoint::io::LasReader;
-use sensor_data_analyzer::DatabaseManager;
+use sensor_data_analyzer::{DatabaseManager, UploadMethod};
 use std::env;
 use std::path::Path;
 use tracing::info;
 
 #[tokio::main]
-pub async fn run(point_cloud_file_path: impl AsRef<Path>) {
+pub async fn run(
+    point_cloud_file_path: impl AsRef<Path>,
+    dataset: &str,
+    pcid: Option<u32>,
+    srid: Option<i32>,
+) {
     info!("Start uploading");
 
     let point_cloud = LasReader::from_path(point_cloud_file_path)
@@ -19,9 +24,18 @@ pub async fn run(point_cloud_file_path: impl AsRef<Path>) {
     let database_url = env::var(ENV_VARIABLE_DATABASE_URL).unwrap();
     let database_manager = DatabaseManager::new(&database_url, 10);
 
-    database_manager.clean().await.unwrap();
+    let point_cloud_format = database_manager
+        .register_point_cloud_format(pcid, srid)
+        .await
+        .unwrap();
+    database_manager.clean_dataset(dataset).await.unwrap();
     database_manager
-        .upload_point_cloud(point_cloud)
+        .upload_point_cloud(
+            dataset,
+            point_cloud_format,
+            point_cloud,
+            UploadMethod::Insert,
+        )
         .await
         .unwrap();
 }
@@ -1,17 +1,19 @@
 use crate::commands::ENV_VARIABLE_DATABASE_URL;
 
 use chrono::{DateTime, Duration, Utc};
-use erosbag::RosbagOpenOptions;
-use sensor_data_analyzer::DatabaseManager;
+use erosbag::{Rosbag, RosbagOpenOptions};
+use sensor_data_analyzer::{DatabaseManager, UploadMethod};
 use std::env;
 use std::path::Path;
 use std::time::Instant;
-use sysinfo::System;
 use tracing::{info, warn};
 
 #[tokio::main]
 pub async fn run(
     rosbag_directory_path: impl AsRef<Path>,
+    dataset: &str,
+    pcid: Option<u32>,
+    srid: Option<i32>,
     ecoord_file_path: impl AsRef<Path>,
     artefact_directory_path: Option<impl AsRef<Path>>,
     step_duration: Duration,
@@ -19,6 +21,9 @@ pub async fn run(
     stop_date_time: Option<DateTime<Utc>>,
     start_time_offset: Option<Duration>,
     total_duration: Option<Duration>,
+    upload_method: UploadMethod,
+    maximum_buffered_steps: usize,
+    resume: bool,
     maximum_number_connections: usize,
 ) {
     info!("Start uploading");
@@ -27,24 +32,65 @@ pub async fn run(
         .read_write(true)
         .open(rosbag_directory_path.as_ref())
         .unwrap();
-    /*if let Some(artefact_directory_path) = &artefact_directory_path {
-        extract_lidar_text_mesh(&rosbag, artefact_directory_path).unwrap();
-    }*/
 
     let reference_frames = ecoord::io::EcoordReader::from_path(ecoord_file_path)
         .unwrap()
         .finish()
         .unwrap();
 
-    let mut sys = System::new_all();
-    sys.refresh_all();
-    // RAM and swap information:
-    //let free_memory = (sys.total_memory() - sys.used_memory()) as f32 / (1024 * 1024 * 1024) as f32;
-    //println!("free memory: {free_memory} gigabytes");
-    //let maximum_of_connections: usize = (free_memory / 10.0).floor() as usize; // some estimate
-
     let artefact_directory_path = artefact_directory_path.map(|p| p.as_ref().to_owned());
 
+    let (start_date_time, stop_date_time) = derive_time_window(
+        &rosbag,
+        start_date_time,
+        stop_date_time,
+        start_time_offset,
+        total_duration,
+    );
+
+    let database_url = env::var(ENV_VARIABLE_DATABASE_URL)
+        .expect("Environment variable ENV_VARIABLE_DATABASE_URL not set.");
+    let database_manager = DatabaseManager::new(&database_url, maximum_number_connections);
+    let point_cloud_format = database_manager
+        .register_point_cloud_format(pcid, srid)
+        .await
+        .unwrap();
+
+    let start = Instant::now();
+    database_manager
+        .upload_rosbag(
+            dataset,
+            point_cloud_format,
+            rosbag,
+            rosbag_directory_path,
+            reference_frames,
+            step_duration,
+            start_date_time,
+            stop_date_time,
+            artefact_directory_path,
+            upload_method,
+            maximum_buffered_steps,
+            resume,
+        )
+        .await
+        .unwrap();
+
+    let duration = start.elapsed();
+    info!(
+        "Upload process took {:?} with {:?} connections.",
+        duration, maximum_number_connections
+    );
+}
+
+/// Derives the processed time window from the optional user-defined bounds, clamped to the
+/// time span of the rosbag.
+pub fn derive_time_window(
+    rosbag: &Rosbag,
+    start_date_time: Option<DateTime<Utc>>,
+    stop_date_time: Option<DateTime<Utc>>,
+    start_time_offset: Option<Duration>,
+    total_duration: Option<Duration>,
+) -> (DateTime<Utc>, DateTime<Utc>) {
     let rosbag_start_date_time = match rosbag.get_start_date_time() {
         Ok(Some(date_time)) => date_time,
         Ok(None) => {
@@ -99,27 +145,5 @@ pub async fn run(
         rosbag_stop_date_time
     };
 
-    let database_url = env::var(ENV_VARIABLE_DATABASE_URL)
-        .expect("Environment variable ENV_VARIABLE_DATABASE_URL not set.");
-    let database_manager = DatabaseManager::new(&database_url, maximum_number_connections);
-    database_manager.clean().await.unwrap();
-
-    let start = Instant::now();
-    database_manager
-        .upload_rosbag(
-            rosbag,
-            reference_frames,
-            step_duration,
-            start_date_time,
-            stop_date_time,
-            artefact_directory_path,
-        )
-        .await
-        .unwrap();
-
-    let duration = start.elapsed();
-    info!(
-        "Upload process took {:?} with {:?} connections.",
-        duration, maximum_number_connections
-    );
+    (start_date_time, stop_date_time)
 }
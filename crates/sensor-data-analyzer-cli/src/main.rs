@@ -4,22 +4,77 @@ mod util;
 
 use std::path::{Path, PathBuf};
 
-use crate::arguments::{Arguments, Commands};
+use crate::arguments::{
+    derive_distance_thresholds, derive_download_chunking, derive_sensor_profile_registry,
+    derive_upload_method, Arguments, Commands,
+};
 use clap::Parser;
+use sensor_data_analyzer::{
+    ChangeDetectionParameters, DownloadFilter, GeometrySelection, RangeImageFormat,
+    RangeImageMeshingParameters,
+};
 
 fn main() {
     tracing_subscriber::fmt::init();
     let arguments = Arguments::parse();
 
     match &arguments.command {
-        Commands::Stats {} => {
-            commands::stats::run();
+        Commands::Stats {
+            dataset,
+            format,
+            output_path,
+            distance_bin_width,
+        } => {
+            let output_path = output_path.clone().map(PathBuf::from);
+
+            commands::stats::run(
+                dataset.clone(),
+                (*format).into(),
+                output_path,
+                *distance_bin_width,
+            );
+        }
+        Commands::AnalyzeChanges {
+            dataset,
+            format,
+            output_path,
+            voxel_size,
+            observation_interval,
+            minimum_observation_times,
+            minimum_cluster_size,
+            sensor_range,
+            maximum_unobserved_points,
+            cluster_directory_path,
+            cluster_format,
+        } => {
+            let output_path = output_path.clone().map(PathBuf::from);
+            let cluster_directory_path = cluster_directory_path.clone().map(PathBuf::from);
+            let parameters = ChangeDetectionParameters {
+                voxel_size: *voxel_size,
+                observation_interval: *observation_interval,
+                minimum_observation_times: *minimum_observation_times,
+                minimum_cluster_size: *minimum_cluster_size,
+                sensor_range: *sensor_range,
+                maximum_unobserved_points: *maximum_unobserved_points,
+            };
+
+            commands::analyze_changes::run(
+                dataset,
+                parameters,
+                (*format).into(),
+                output_path,
+                cluster_directory_path,
+                (*cluster_format).into(),
+            );
         }
-        Commands::Clear {} => {
-            commands::clear::run();
+        Commands::Clear { dataset } => {
+            commands::clear::run(dataset.clone());
         }
         Commands::UploadRosbag {
             rosbag_directory_path,
+            dataset,
+            pcid,
+            srid,
             ecoord_file_path,
             artefact_directory_path,
             start_date_time,
@@ -27,15 +82,24 @@ fn main() {
             start_time_offset,
             total_duration,
             step_duration,
+            upload_method,
+            copy_format,
+            maximum_buffered_steps,
+            resume,
             maximum_number_connections,
         } => {
             let rosbag_directory_path = Path::new(rosbag_directory_path).canonicalize().unwrap();
             let ecoord_file_path = PathBuf::from(ecoord_file_path);
             let temporary_artefact_directory_path =
                 artefact_directory_path.clone().map(PathBuf::from);
+            let upload_method =
+                derive_upload_method(*upload_method, *copy_format).unwrap_or_else(|e| e.exit());
 
             commands::upload_rosbag::run(
                 rosbag_directory_path,
+                dataset,
+                *pcid,
+                *srid,
                 ecoord_file_path,
                 temporary_artefact_directory_path,
                 *step_duration,
@@ -43,41 +107,236 @@ fn main() {
                 *stop_date_time,
                 *start_time_offset,
                 *total_duration,
+                upload_method,
+                *maximum_buffered_steps,
+                *resume,
                 *maximum_number_connections,
             );
         }
+        Commands::ExtractMesh {
+            rosbag_directory_path,
+            output_directory_path,
+            frame_ids,
+            sensor_profile,
+            sensor_profile_file_path,
+            start_date_time,
+            stop_date_time,
+            start_time_offset,
+            total_duration,
+            maximum_edge_length,
+            maximum_depth_discontinuity,
+        } => {
+            let rosbag_directory_path = Path::new(rosbag_directory_path).canonicalize().unwrap();
+            let output_directory_path = PathBuf::from(output_directory_path);
+            let sensor_profile_registry =
+                derive_sensor_profile_registry(sensor_profile_file_path, sensor_profile);
+            let meshing_parameters = RangeImageMeshingParameters {
+                maximum_edge_length: *maximum_edge_length,
+                maximum_depth_discontinuity: *maximum_depth_discontinuity,
+            };
+
+            commands::extract_mesh::run(
+                rosbag_directory_path,
+                output_directory_path,
+                frame_ids,
+                sensor_profile_registry,
+                *start_date_time,
+                *stop_date_time,
+                *start_time_offset,
+                *total_duration,
+                meshing_parameters,
+            );
+        }
         Commands::UploadPointCloud {
             point_cloud_file_path,
+            dataset,
+            pcid,
+            srid,
         } => {
             let point_cloud_file_path = Path::new(point_cloud_file_path).canonicalize().unwrap();
 
-            commands::upload_point_cloud::run(point_cloud_file_path);
+            commands::upload_point_cloud::run(point_cloud_file_path, dataset, *pcid, *srid);
         }
         Commands::Associate {
+            dataset,
             distance_threshold,
+            class_distance_threshold,
+            distance_threshold_file_path,
+            lods,
+            geometry_properties,
+            thematic_surfaces,
+            association_mode,
             beam_intersection,
+            pass_through_tolerance,
             keep_temporary_table_entries,
             maximum_number_connections,
         } => {
-            commands::associate::run(
+            let distance_thresholds = derive_distance_thresholds(
                 *distance_threshold,
+                class_distance_threshold,
+                distance_threshold_file_path.as_deref(),
+            );
+            let geometry_selection = GeometrySelection {
+                lods: lods.clone(),
+                properties: geometry_properties.clone(),
+                thematic_surfaces: *thematic_surfaces,
+            };
+
+            commands::associate::run(
+                dataset,
+                distance_thresholds,
+                geometry_selection,
+                (*association_mode).into(),
                 *beam_intersection,
+                *pass_through_tolerance,
                 *keep_temporary_table_entries,
                 *maximum_number_connections,
             );
         }
+        Commands::Enrich { dataset, dry_run } => {
+            commands::enrich::run(dataset, *dry_run);
+        }
+        Commands::Coverage {
+            dataset,
+            cell_size,
+            gltf_file_path,
+            full_coverage_number_of_points,
+            export_only,
+        } => {
+            let gltf_file_path = gltf_file_path.clone().map(PathBuf::from);
+
+            commands::coverage::run(
+                dataset,
+                *cell_size,
+                gltf_file_path,
+                *full_coverage_number_of_points,
+                *export_only,
+            );
+        }
         Commands::Download {
+            dataset,
             directory_path,
+            format,
+            chunking,
+            chunk_duration,
+            tile_size,
+            gml_ids,
+            classnames,
+            start_date_time,
+            stop_date_time,
+            bounding_box,
+            polygon,
+            unassociated_only,
             keep_temporary_table_entries,
             maximum_number_connections,
         } => {
             let directory_path = PathBuf::from(directory_path);
+            let chunking = derive_download_chunking(*chunking, *chunk_duration, *tile_size);
+            let filter = DownloadFilter {
+                gml_ids: gml_ids.clone(),
+                classnames: classnames.clone(),
+                start_date_time: *start_date_time,
+                stop_date_time: *stop_date_time,
+                bounding_box: *bounding_box,
+                polygon: polygon.clone(),
+                unassociated_only: *unassociated_only,
+            };
 
             commands::download::run(
+                dataset,
                 directory_path,
+                (*format).into(),
+                chunking,
+                filter,
                 *keep_temporary_table_entries,
                 *maximum_number_connections,
             );
         }
+        Commands::ExportSemanticKitti {
+            rosbag_directory_path,
+            dataset,
+            directory_path,
+            sequence,
+            ecoord_file_path,
+            sensor_frame_id,
+            start_date_time,
+            stop_date_time,
+            start_time_offset,
+            total_duration,
+            step_duration,
+        } => {
+            let rosbag_directory_path = Path::new(rosbag_directory_path).canonicalize().unwrap();
+            let directory_path = PathBuf::from(directory_path);
+            let ecoord_file_path = PathBuf::from(ecoord_file_path);
+
+            commands::export_semantic_kitti::run(
+                rosbag_directory_path,
+                dataset,
+                directory_path,
+                *sequence,
+                ecoord_file_path,
+                sensor_frame_id,
+                *step_duration,
+                *start_date_time,
+                *stop_date_time,
+                *start_time_offset,
+                *total_duration,
+            );
+        }
+        Commands::ExportRangeImages {
+            rosbag_directory_path,
+            dataset,
+            directory_path,
+            formats,
+            sensor_profile,
+            sensor_profile_file_path,
+            start_date_time,
+            stop_date_time,
+            start_time_offset,
+            total_duration,
+            step_duration,
+        } => {
+            let rosbag_directory_path = Path::new(rosbag_directory_path).canonicalize().unwrap();
+            let directory_path = PathBuf::from(directory_path);
+            let formats: Vec<RangeImageFormat> = formats.iter().map(|f| (*f).into()).collect();
+            let sensor_profile_registry =
+                derive_sensor_profile_registry(sensor_profile_file_path, sensor_profile);
+
+            commands::export_range_images::run(
+                rosbag_directory_path,
+                dataset,
+                directory_path,
+                &formats,
+                sensor_profile_registry,
+                *step_duration,
+                *start_date_time,
+                *stop_date_time,
+                *start_time_offset,
+                *total_duration,
+            );
+        }
+        Commands::LabelRosbag {
+            rosbag_directory_path,
+            dataset,
+            topic_prefix,
+            start_date_time,
+            stop_date_time,
+            start_time_offset,
+            total_duration,
+            step_duration,
+        } => {
+            let rosbag_directory_path = Path::new(rosbag_directory_path).canonicalize().unwrap();
+
+            commands::label_rosbag::run(
+                rosbag_directory_path,
+                dataset,
+                topic_prefix,
+                *step_duration,
+                *start_date_time,
+                *stop_date_time,
+                *start_time_offset,
+                *total_duration,
+            );
+        }
     };
 }
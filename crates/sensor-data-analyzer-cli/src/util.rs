@@ -1,4 +1,6 @@
 use chrono::Utc;
+use nalgebra::{Point2, Point3};
+use sensor_data_analyzer::BoundingBox;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -28,3 +30,87 @@ pub fn parse_timestamp(arg: &str) -> Result<chrono::DateTime<Utc>, TimestampPars
         chrono::DateTime::parse_from_str(arg, "%Y-%m-%d %H:%M:%S%.9f %z")?.into();
     Ok(chrono_datetime)
 }
+
+#[derive(Debug, Error)]
+pub enum BoundingBoxParseError {
+    #[error("failed to parse coordinate: {0}")]
+    FloatParseError(#[from] std::num::ParseFloatError),
+
+    #[error("expected 4 (min_x,min_y,max_x,max_y) or 6 (min_x,min_y,min_z,max_x,max_y,max_z) coordinates, but got {0}")]
+    InvalidNumberOfCoordinates(usize),
+}
+
+pub fn parse_bounding_box(arg: &str) -> Result<BoundingBox, BoundingBoxParseError> {
+    let values: Vec<f64> = arg
+        .split(',')
+        .map(|v| v.trim().parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()?;
+
+    match values[..] {
+        [min_x, min_y, max_x, max_y] => Ok(BoundingBox::TwoDimensional {
+            lower: Point2::new(min_x, min_y),
+            upper: Point2::new(max_x, max_y),
+        }),
+        [min_x, min_y, min_z, max_x, max_y, max_z] => Ok(BoundingBox::ThreeDimensional {
+            lower: Point3::new(min_x, min_y, min_z),
+            upper: Point3::new(max_x, max_y, max_z),
+        }),
+        _ => Err(BoundingBoxParseError::InvalidNumberOfCoordinates(
+            values.len(),
+        )),
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ClassDistanceThresholdParseError {
+    #[error("failed to parse distance threshold: {0}")]
+    FloatParseError(#[from] std::num::ParseFloatError),
+
+    #[error("expected CLASSNAME=METERS, but got {0}")]
+    MissingSeparator(String),
+}
+
+pub fn parse_class_distance_threshold(
+    arg: &str,
+) -> Result<(String, f32), ClassDistanceThresholdParseError> {
+    let (classname, distance_threshold) = arg
+        .split_once('=')
+        .ok_or_else(|| ClassDistanceThresholdParseError::MissingSeparator(arg.to_string()))?;
+    Ok((
+        classname.trim().to_string(),
+        distance_threshold.trim().parse::<f32>()?,
+    ))
+}
+
+#[derive(Debug, Error)]
+pub enum FrameSensorProfileParseError {
+    #[error("expected FRAME_ID=PROFILE, but got {0}")]
+    MissingSeparator(String),
+}
+
+pub fn parse_frame_sensor_profile(
+    arg: &str,
+) -> Result<(String, String), FrameSensorProfileParseError> {
+    let (frame_id, profile_name) = arg
+        .split_once('=')
+        .ok_or_else(|| FrameSensorProfileParseError::MissingSeparator(arg.to_string()))?;
+    Ok((frame_id.trim().to_string(), profile_name.trim().to_string()))
+}
+
+#[derive(Debug, Error)]
+pub enum PositiveNumberParseError {
+    #[error("failed to parse number: {0}")]
+    FloatParseError(#[from] std::num::ParseFloatError),
+
+    #[error("expected a positive number, but got {0}")]
+    NotPositive(f64),
+}
+
+pub fn parse_positive_number(arg: &str) -> Result<f64, PositiveNumberParseError> {
+    let value = arg.trim().parse::<f64>()?;
+    if value > 0.0 {
+        Ok(value)
+    } else {
+        Err(PositiveNumberParseError::NotPositive(value))
+    }
+}
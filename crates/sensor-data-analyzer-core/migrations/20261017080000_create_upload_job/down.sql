@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS sensor_data.upload_job_step;
+DROP TABLE IF EXISTS sensor_data.upload_job;
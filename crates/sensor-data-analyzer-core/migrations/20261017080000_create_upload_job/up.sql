@@ -0,0 +1,21 @@
+CREATE TABLE sensor_data.upload_job (
+    id SERIAL PRIMARY KEY,
+    rosbag_path VARCHAR(4096) NOT NULL,
+    start_date_time TIMESTAMPTZ NOT NULL,
+    stop_date_time TIMESTAMPTZ NOT NULL,
+    step_duration_ms BIGINT NOT NULL,
+    number_of_steps INT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (rosbag_path, start_date_time, stop_date_time, step_duration_ms)
+);
+
+
+CREATE TABLE sensor_data.upload_job_step (
+    id BIGSERIAL PRIMARY KEY,
+    upload_job_id INT NOT NULL REFERENCES sensor_data.upload_job(id) ON DELETE CASCADE,
+    step INT NOT NULL,
+    patch_id INT NOT NULL,
+    completed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (upload_job_id, step)
+);
+CREATE INDEX idx_upload_job_step_patch_id ON sensor_data.upload_job_step(patch_id);
@@ -0,0 +1,11 @@
+ALTER TABLE sensor_data.upload_job DROP CONSTRAINT IF EXISTS upload_job_dataset_id_key;
+ALTER TABLE sensor_data.upload_job DROP COLUMN IF EXISTS dataset_id;
+ALTER TABLE sensor_data.upload_job ADD UNIQUE (rosbag_path, start_date_time, stop_date_time, step_duration_ms);
+
+ALTER TABLE sensor_data.point_cloud_download DROP COLUMN IF EXISTS dataset_id;
+ALTER TABLE sensor_data.association_beam_model DROP COLUMN IF EXISTS dataset_id;
+ALTER TABLE sensor_data.association_point_model DROP COLUMN IF EXISTS dataset_id;
+ALTER TABLE sensor_data.beam DROP COLUMN IF EXISTS dataset_id;
+ALTER TABLE sensor_data.point_cloud_upload DROP COLUMN IF EXISTS dataset_id;
+
+DROP TABLE IF EXISTS sensor_data.dataset;
@@ -0,0 +1,48 @@
+CREATE TABLE sensor_data.dataset (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(256) NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- existing sensor data is assigned to the default dataset
+INSERT INTO sensor_data.dataset (name) VALUES ('default');
+
+ALTER TABLE sensor_data.point_cloud_upload ADD COLUMN dataset_id INT REFERENCES sensor_data.dataset(id);
+ALTER TABLE sensor_data.beam ADD COLUMN dataset_id INT REFERENCES sensor_data.dataset(id);
+ALTER TABLE sensor_data.association_point_model ADD COLUMN dataset_id INT REFERENCES sensor_data.dataset(id);
+ALTER TABLE sensor_data.association_beam_model ADD COLUMN dataset_id INT REFERENCES sensor_data.dataset(id);
+ALTER TABLE sensor_data.point_cloud_download ADD COLUMN dataset_id INT REFERENCES sensor_data.dataset(id);
+ALTER TABLE sensor_data.upload_job ADD COLUMN dataset_id INT REFERENCES sensor_data.dataset(id);
+
+UPDATE sensor_data.point_cloud_upload SET dataset_id = (SELECT id FROM sensor_data.dataset WHERE name = 'default');
+UPDATE sensor_data.beam SET dataset_id = (SELECT id FROM sensor_data.dataset WHERE name = 'default');
+UPDATE sensor_data.association_point_model SET dataset_id = (SELECT id FROM sensor_data.dataset WHERE name = 'default');
+UPDATE sensor_data.association_beam_model SET dataset_id = (SELECT id FROM sensor_data.dataset WHERE name = 'default');
+UPDATE sensor_data.point_cloud_download SET dataset_id = (SELECT id FROM sensor_data.dataset WHERE name = 'default');
+UPDATE sensor_data.upload_job SET dataset_id = (SELECT id FROM sensor_data.dataset WHERE name = 'default');
+
+ALTER TABLE sensor_data.point_cloud_upload ALTER COLUMN dataset_id SET NOT NULL;
+ALTER TABLE sensor_data.beam ALTER COLUMN dataset_id SET NOT NULL;
+ALTER TABLE sensor_data.association_point_model ALTER COLUMN dataset_id SET NOT NULL;
+ALTER TABLE sensor_data.association_beam_model ALTER COLUMN dataset_id SET NOT NULL;
+ALTER TABLE sensor_data.point_cloud_download ALTER COLUMN dataset_id SET NOT NULL;
+ALTER TABLE sensor_data.upload_job ALTER COLUMN dataset_id SET NOT NULL;
+
+CREATE INDEX idx_point_cloud_upload_dataset_id ON sensor_data.point_cloud_upload(dataset_id);
+CREATE INDEX idx_beam_dataset_id ON sensor_data.beam(dataset_id);
+CREATE INDEX idx_association_point_model_dataset_id ON sensor_data.association_point_model(dataset_id);
+CREATE INDEX idx_association_beam_model_dataset_id ON sensor_data.association_beam_model(dataset_id);
+CREATE INDEX idx_point_cloud_download_dataset_id ON sensor_data.point_cloud_download(dataset_id);
+
+-- an upload job is identified within its dataset
+DO $$
+DECLARE
+    constraint_name TEXT;
+BEGIN
+    SELECT conname INTO constraint_name
+    FROM pg_constraint
+    WHERE conrelid = 'sensor_data.upload_job'::regclass AND contype = 'u';
+    EXECUTE format('ALTER TABLE sensor_data.upload_job DROP CONSTRAINT %I', constraint_name);
+END $$;
+ALTER TABLE sensor_data.upload_job ADD CONSTRAINT upload_job_dataset_id_key
+    UNIQUE (dataset_id, rosbag_path, start_date_time, stop_date_time, step_duration_ms);
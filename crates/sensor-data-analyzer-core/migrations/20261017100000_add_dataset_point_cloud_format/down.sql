@@ -0,0 +1,4 @@
+ALTER TABLE sensor_data.point_cloud_upload ALTER COLUMN pa TYPE PCPATCH(1);
+
+ALTER TABLE sensor_data.dataset DROP COLUMN IF EXISTS srid;
+ALTER TABLE sensor_data.dataset DROP COLUMN IF EXISTS pcid;
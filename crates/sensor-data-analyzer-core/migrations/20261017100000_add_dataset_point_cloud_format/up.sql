@@ -0,0 +1,11 @@
+-- point cloud format of the dataset, which is assigned on the first upload
+ALTER TABLE sensor_data.dataset ADD COLUMN pcid INT;
+ALTER TABLE sensor_data.dataset ADD COLUMN srid INT;
+
+UPDATE sensor_data.dataset
+SET pcid = pointcloud_formats.pcid, srid = pointcloud_formats.srid
+FROM pointcloud_formats
+WHERE pointcloud_formats.pcid = 1 AND sensor_data.dataset.name = 'default';
+
+-- patches of any registered point cloud format are accepted
+ALTER TABLE sensor_data.point_cloud_upload ALTER COLUMN pa TYPE PCPATCH;
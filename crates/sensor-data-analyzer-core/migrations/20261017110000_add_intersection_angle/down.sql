@@ -0,0 +1,8 @@
+DROP FUNCTION IF EXISTS sensor_data.incidence_angle(geometry, DOUBLE PRECISION, DOUBLE PRECISION, DOUBLE PRECISION);
+DROP FUNCTION IF EXISTS sensor_data.polygon_normal(geometry);
+
+ALTER TABLE sensor_data.association_beam_model DROP COLUMN IF EXISTS intersection_angle;
+
+ALTER TABLE sensor_data.feature_geometry_data DROP COLUMN IF EXISTS normal_z;
+ALTER TABLE sensor_data.feature_geometry_data DROP COLUMN IF EXISTS normal_y;
+ALTER TABLE sensor_data.feature_geometry_data DROP COLUMN IF EXISTS normal_x;
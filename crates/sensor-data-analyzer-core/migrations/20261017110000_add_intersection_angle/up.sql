@@ -0,0 +1,62 @@
+-- unit normal of the surfaces derived with Newell's method
+ALTER TABLE sensor_data.feature_geometry_data ADD COLUMN normal_x DOUBLE PRECISION;
+ALTER TABLE sensor_data.feature_geometry_data ADD COLUMN normal_y DOUBLE PRECISION;
+ALTER TABLE sensor_data.feature_geometry_data ADD COLUMN normal_z DOUBLE PRECISION;
+
+-- incidence angle in radians between the beam direction and the surface normal within [0, pi/2]
+ALTER TABLE sensor_data.association_beam_model ADD COLUMN intersection_angle FLOAT;
+
+
+CREATE OR REPLACE FUNCTION sensor_data.polygon_normal(polygon geometry)
+RETURNS DOUBLE PRECISION[] AS $$
+DECLARE
+    ring geometry := ST_ExteriorRing(polygon);
+    number_of_points INTEGER := ST_NPoints(ring);
+    current_point geometry;
+    next_point geometry;
+    normal_x DOUBLE PRECISION := 0;
+    normal_y DOUBLE PRECISION := 0;
+    normal_z DOUBLE PRECISION := 0;
+    normal_length DOUBLE PRECISION;
+BEGIN
+    -- the ring is closed, so that the last point equals the first one
+    FOR i IN 1..number_of_points - 1 LOOP
+        current_point := ST_PointN(ring, i);
+        next_point := ST_PointN(ring, i + 1);
+        normal_x := normal_x + (ST_Y(current_point) - ST_Y(next_point)) * (ST_Z(current_point) + ST_Z(next_point));
+        normal_y := normal_y + (ST_Z(current_point) - ST_Z(next_point)) * (ST_X(current_point) + ST_X(next_point));
+        normal_z := normal_z + (ST_X(current_point) - ST_X(next_point)) * (ST_Y(current_point) + ST_Y(next_point));
+    END LOOP;
+
+    normal_length := sqrt(normal_x * normal_x + normal_y * normal_y + normal_z * normal_z);
+    IF normal_length = 0 THEN
+        RETURN NULL;
+    END IF;
+    RETURN ARRAY[normal_x / normal_length, normal_y / normal_length, normal_z / normal_length];
+END;
+$$ LANGUAGE plpgsql IMMUTABLE STRICT;
+
+
+CREATE OR REPLACE FUNCTION sensor_data.incidence_angle(
+    line geometry,
+    normal_x DOUBLE PRECISION,
+    normal_y DOUBLE PRECISION,
+    normal_z DOUBLE PRECISION)
+RETURNS DOUBLE PRECISION AS $$
+DECLARE
+    direction_x DOUBLE PRECISION := ST_X(ST_EndPoint(line)) - ST_X(ST_StartPoint(line));
+    direction_y DOUBLE PRECISION := ST_Y(ST_EndPoint(line)) - ST_Y(ST_StartPoint(line));
+    direction_z DOUBLE PRECISION := ST_Z(ST_EndPoint(line)) - ST_Z(ST_StartPoint(line));
+    direction_length DOUBLE PRECISION;
+    cosine DOUBLE PRECISION;
+BEGIN
+    direction_length := sqrt(direction_x * direction_x + direction_y * direction_y + direction_z * direction_z);
+    IF direction_length = 0 THEN
+        RETURN NULL;
+    END IF;
+
+    -- the orientation of the surface is not considered
+    cosine := abs(direction_x * normal_x + direction_y * normal_y + direction_z * normal_z) / direction_length;
+    RETURN acos(least(cosine, 1.0));
+END;
+$$ LANGUAGE plpgsql IMMUTABLE STRICT;
@@ -0,0 +1,3 @@
+DROP FUNCTION IF EXISTS sensor_data.polygon_area(geometry);
+
+ALTER TABLE sensor_data.feature_geometry_data DROP COLUMN IF EXISTS area;
@@ -0,0 +1,33 @@
+-- area of the surfaces in square meters
+ALTER TABLE sensor_data.feature_geometry_data ADD COLUMN area DOUBLE PRECISION;
+
+
+-- area of a planar 3D polygon without the area of its holes
+--
+-- The polygon is projected onto the coordinate plane most parallel to it, where the planar area
+-- including the holes is scaled by the corresponding component of the unit normal.
+CREATE OR REPLACE FUNCTION sensor_data.polygon_area(polygon geometry)
+RETURNS DOUBLE PRECISION AS $$
+DECLARE
+    normal DOUBLE PRECISION[] := sensor_data.polygon_normal(polygon);
+BEGIN
+    IF normal IS NULL THEN
+        RETURN 0;
+    END IF;
+
+    IF abs(normal[3]) >= abs(normal[1]) AND abs(normal[3]) >= abs(normal[2]) THEN
+        RETURN ST_Area(polygon) / abs(normal[3]);
+    ELSIF abs(normal[2]) >= abs(normal[1]) THEN
+        -- projection onto the xz-plane
+        RETURN ST_Area(ST_SwapOrdinates(polygon, 'yz')) / abs(normal[2]);
+    ELSE
+        -- projection onto the zy-plane
+        RETURN ST_Area(ST_SwapOrdinates(polygon, 'xz')) / abs(normal[1]);
+    END IF;
+END;
+$$ LANGUAGE plpgsql IMMUTABLE STRICT;
+
+
+UPDATE sensor_data.feature_geometry_data
+SET area = sensor_data.polygon_area(valid_geometry)
+WHERE valid_geometry IS NOT NULL;
@@ -0,0 +1,2 @@
+DROP VIEW IF EXISTS sensor_data.surface_coverage;
+DROP TABLE IF EXISTS sensor_data.surface_coverage_cell;
@@ -0,0 +1,29 @@
+-- cells of the rasterized surfaces of feature_geometry_data with the number of associated reflections
+CREATE TABLE sensor_data.surface_coverage_cell (
+    id BIGSERIAL PRIMARY KEY,
+    dataset_id INT NOT NULL REFERENCES sensor_data.dataset(id),
+    -- refers to the exploded surfaces at the time of the computation
+    feature_geometry_data_id BIGINT NOT NULL,
+    feature_id BIGINT NOT NULL,
+    column_index INT NOT NULL,
+    row_index INT NOT NULL,
+    cell_size DOUBLE PRECISION NOT NULL,
+    number_of_points INT NOT NULL,
+    geometry GEOMETRY(POLYGONZ) NOT NULL
+);
+
+CREATE INDEX idx_surface_coverage_cell_dataset_id ON sensor_data.surface_coverage_cell(dataset_id);
+CREATE INDEX idx_surface_coverage_cell_feature_id ON sensor_data.surface_coverage_cell(feature_id);
+
+-- share of observed cells per surface
+CREATE VIEW sensor_data.surface_coverage AS
+SELECT
+    dataset_id,
+    feature_geometry_data_id,
+    feature_id,
+    count(*) AS number_of_cells,
+    count(*) FILTER (WHERE number_of_points > 0) AS number_of_observed_cells,
+    sum(number_of_points) AS number_of_points,
+    count(*) FILTER (WHERE number_of_points > 0)::DOUBLE PRECISION / count(*) AS coverage
+FROM sensor_data.surface_coverage_cell
+GROUP BY dataset_id, feature_geometry_data_id, feature_id;
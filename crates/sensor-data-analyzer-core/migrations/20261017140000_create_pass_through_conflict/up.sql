@@ -0,0 +1,16 @@
+-- surfaces a beam passes through before reaching its reflection, which indicates a wrong model
+CREATE TABLE sensor_data.pass_through_conflict (
+    id BIGSERIAL PRIMARY KEY,
+    beam_id BIGINT NOT NULL REFERENCES sensor_data.beam(id),
+    feature_id BIGINT NOT NULL,
+    -- refers to the exploded surfaces at the time of the association
+    feature_geometry_data_id BIGINT NOT NULL,
+    intersection GEOMETRY NOT NULL,
+    -- distance in meters along the beam from the intersection to the reflection
+    distance_to_reflection DOUBLE PRECISION NOT NULL,
+    dataset_id INT NOT NULL REFERENCES sensor_data.dataset(id)
+);
+
+CREATE INDEX idx_pass_through_conflict_beam_id ON sensor_data.pass_through_conflict(beam_id);
+CREATE INDEX idx_pass_through_conflict_feature_id ON sensor_data.pass_through_conflict(feature_id);
+CREATE INDEX idx_pass_through_conflict_dataset_id ON sensor_data.pass_through_conflict(dataset_id);
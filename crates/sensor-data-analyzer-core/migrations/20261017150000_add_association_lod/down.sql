@@ -0,0 +1 @@
+ALTER TABLE sensor_data.association_point_model DROP COLUMN IF EXISTS lod;
@@ -0,0 +1,2 @@
+-- LoD of the geometry property referencing the geometry a point is associated with
+ALTER TABLE sensor_data.association_point_model ADD COLUMN lod TEXT;
@@ -0,0 +1,6 @@
+DROP FUNCTION IF EXISTS sensor_data.top_level_feature(BIGINT);
+
+ALTER TABLE sensor_data.point_cloud_download DROP COLUMN IF EXISTS surface_classname;
+ALTER TABLE sensor_data.point_cloud_download DROP COLUMN IF EXISTS parent_gml_id;
+
+ALTER TABLE sensor_data.association_point_model DROP COLUMN IF EXISTS parent_feature_id;
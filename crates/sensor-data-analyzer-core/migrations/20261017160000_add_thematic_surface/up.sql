@@ -0,0 +1,31 @@
+-- top-level feature, e.g. the building, of an associated thematic surface or opening
+ALTER TABLE sensor_data.association_point_model ADD COLUMN parent_feature_id BIGINT;
+
+ALTER TABLE sensor_data.point_cloud_download ADD COLUMN parent_gml_id VARCHAR(256);
+ALTER TABLE sensor_data.point_cloud_download ADD COLUMN surface_classname VARCHAR(256);
+
+
+-- follows the contains relations of citydb.property up to the top-level feature, which is null
+-- for a feature without parent
+CREATE OR REPLACE FUNCTION sensor_data.top_level_feature(child_feature_id BIGINT)
+RETURNS BIGINT AS $$
+DECLARE
+    current_feature_id BIGINT := child_feature_id;
+    parent_feature_id BIGINT;
+BEGIN
+    -- bounded depth in case of cyclic relations
+    FOR i IN 1..32 LOOP
+        SELECT p.feature_id INTO parent_feature_id
+        FROM citydb.property as p
+        WHERE p.val_feature_id = current_feature_id AND p.val_relation_type = 1
+        LIMIT 1;
+        EXIT WHEN parent_feature_id IS NULL;
+        current_feature_id := parent_feature_id;
+    END LOOP;
+
+    IF current_feature_id = child_feature_id THEN
+        RETURN NULL;
+    END IF;
+    RETURN current_feature_id;
+END;
+$$ LANGUAGE plpgsql STABLE STRICT;
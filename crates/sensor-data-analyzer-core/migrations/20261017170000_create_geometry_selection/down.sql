@@ -0,0 +1,4 @@
+ALTER TABLE sensor_data.dataset DROP COLUMN IF EXISTS geometry_selection_id;
+ALTER TABLE sensor_data.feature_geometry_data DROP COLUMN IF EXISTS geometry_selection_id;
+
+DROP TABLE IF EXISTS sensor_data.geometry_selection;
@@ -0,0 +1,22 @@
+-- geometry selections of the associations, for each of which the surfaces are exploded once into
+-- feature_geometry_data
+CREATE TABLE sensor_data.geometry_selection (
+    id SERIAL PRIMARY KEY,
+    key TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+ALTER TABLE sensor_data.feature_geometry_data ADD COLUMN geometry_selection_id INT REFERENCES sensor_data.geometry_selection(id);
+ALTER TABLE sensor_data.dataset ADD COLUMN geometry_selection_id INT REFERENCES sensor_data.geometry_selection(id);
+
+-- surfaces exploded before the selections were recorded remain available to the already associated datasets
+INSERT INTO sensor_data.geometry_selection (key)
+SELECT 'legacy' WHERE EXISTS (SELECT 1 FROM sensor_data.feature_geometry_data);
+UPDATE sensor_data.feature_geometry_data
+SET geometry_selection_id = (SELECT id FROM sensor_data.geometry_selection WHERE key = 'legacy');
+UPDATE sensor_data.dataset
+SET geometry_selection_id = (SELECT id FROM sensor_data.geometry_selection WHERE key = 'legacy')
+WHERE id IN (SELECT DISTINCT dataset_id FROM sensor_data.association_point_model);
+
+ALTER TABLE sensor_data.feature_geometry_data ALTER COLUMN geometry_selection_id SET NOT NULL;
+CREATE INDEX idx_feature_geometry_data_geometry_selection_id ON sensor_data.feature_geometry_data(geometry_selection_id);
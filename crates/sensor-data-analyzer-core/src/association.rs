@@ -0,0 +1,459 @@
+use crate::download_filter::{quote, quote_list};
+use crate::error::Error;
+use crate::models::exports::GeometrySelectionIdEntry;
+use diesel::sql_types::Text;
+use diesel::{OptionalExtension, PgConnection, RunQueryDsl};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Number of decimal places in meters up to which equal surface distances are considered a tie.
+const TIE_DISTANCE_DECIMAL_PLACES: i32 = 6;
+
+/// Name of the property in `citydb.property` linking a feature to its thematic surfaces.
+const BOUNDARY_PROPERTY: &str = "boundary";
+
+/// Distance threshold in meters applied if no other thresholds are given.
+pub const DEFAULT_DISTANCE_THRESHOLD: f32 = 0.2;
+
+/// Maximum distances in meters between a point and the features it is associated with.
+///
+/// The thresholds are keyed by the CityGML classname of `citydb.objectclass`, e.g.
+/// `SolitaryVegetationObject`. Features of other classes use the default threshold, or are not
+/// associated at all without a default.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DistanceThresholds {
+    #[serde(default)]
+    pub default: Option<f32>,
+    #[serde(default)]
+    pub classes: BTreeMap<String, f32>,
+}
+
+impl Default for DistanceThresholds {
+    fn default() -> Self {
+        Self {
+            default: Some(DEFAULT_DISTANCE_THRESHOLD),
+            classes: BTreeMap::new(),
+        }
+    }
+}
+
+impl DistanceThresholds {
+    /// Reads the thresholds from a JSON file of the form
+    /// `{"default": 0.2, "classes": {"SolitaryVegetationObject": 0.5}}`.
+    pub fn from_json_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let reader = BufReader::new(File::open(path)?);
+        let distance_thresholds: DistanceThresholds = serde_json::from_reader(reader)?;
+        Ok(distance_thresholds)
+    }
+
+    /// Returns the largest of all thresholds, which bounds the spatial join.
+    pub fn maximum(&self) -> Option<f32> {
+        self.default
+            .into_iter()
+            .chain(self.classes.values().copied())
+            .reduce(f32::max)
+    }
+
+    /// Returns the threshold of the feature as SQL expression, where `oc` is its object class.
+    fn sql_expression(&self) -> String {
+        let cases: String = self
+            .classes
+            .iter()
+            .map(|(classname, threshold)| format!(" WHEN {} THEN {threshold}", quote(classname)))
+            .collect();
+        let default = self.default.map(|d| d.to_string()).unwrap_or("NULL".into());
+        format!("CASE oc.classname{cases} ELSE {default} END")
+    }
+}
+
+/// Geometries of the city model participating in the association.
+///
+/// The geometries are selected by the level of detail and the name of the geometry property
+/// referencing them in `citydb.property`, e.g. `2` and `lod2MultiSurface`. An empty list does
+/// not restrict the selection, so that by default all geometries participate.
+///
+/// With `thematic_surfaces`, the geometries of features bounded by thematic surfaces, e.g. the
+/// solid of a building with wall and roof surfaces, are replaced by the geometries of these
+/// surfaces and their openings.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct GeometrySelection {
+    pub lods: Vec<String>,
+    pub properties: Vec<String>,
+    pub thematic_surfaces: bool,
+}
+
+impl GeometrySelection {
+    pub fn is_empty(&self) -> bool {
+        self.lods.is_empty() && self.properties.is_empty() && !self.thematic_surfaces
+    }
+
+    /// Returns the canonical representation of the selection identifying its surfaces in
+    /// `feature_geometry_data`.
+    pub(crate) fn key(&self) -> String {
+        let canonical_list = |values: &[String]| {
+            let mut values = values.to_vec();
+            values.sort();
+            values.dedup();
+            values.join(",")
+        };
+        format!(
+            "lods={};properties={};thematic_surfaces={}",
+            canonical_list(&self.lods),
+            canonical_list(&self.properties),
+            self.thematic_surfaces
+        )
+    }
+
+    /// Returns the condition restricting the rows of the given `citydb.geometry_data` alias to
+    /// the selection.
+    pub(crate) fn sql_condition(&self, geometry_data: &str) -> Option<String> {
+        let mut conditions: Vec<String> = Vec::new();
+        if !self.lods.is_empty() || !self.properties.is_empty() {
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM citydb.property as p WHERE p.val_geometry_id = {geometry_data}.id{})",
+                self.property_conditions()
+            ));
+        }
+        if self.thematic_surfaces {
+            conditions.push(format!(
+                "NOT EXISTS (SELECT 1 FROM citydb.property as s JOIN citydb.geometry_data as sg ON s.val_feature_id = sg.feature_id WHERE s.feature_id = {geometry_data}.feature_id AND s.name = '{BOUNDARY_PROPERTY}')"
+            ));
+        }
+
+        if conditions.is_empty() {
+            return None;
+        }
+        Some(conditions.join(" AND "))
+    }
+
+    /// Returns the LoD of a row of the given `citydb.geometry_data` alias as SQL expression.
+    ///
+    /// A geometry referenced by several properties gets the lowest of their selected LoDs.
+    pub(crate) fn lod_expression(&self, geometry_data: &str) -> String {
+        format!(
+            "(SELECT min(p.val_lod) FROM citydb.property as p WHERE p.val_geometry_id = {geometry_data}.id{})",
+            self.property_conditions()
+        )
+    }
+
+    /// Returns the top-level feature of a row of the given `citydb.geometry_data` alias as SQL
+    /// expression, which is only set for thematic surfaces.
+    pub(crate) fn parent_feature_expression(&self, geometry_data: &str) -> String {
+        if self.thematic_surfaces {
+            format!("sensor_data.top_level_feature({geometry_data}.feature_id)")
+        } else {
+            "NULL::BIGINT".to_string()
+        }
+    }
+
+    fn property_conditions(&self) -> String {
+        let mut conditions = String::new();
+        if !self.lods.is_empty() {
+            conditions.push_str(&format!(" AND p.val_lod IN ({})", quote_list(&self.lods)));
+        }
+        if !self.properties.is_empty() {
+            conditions.push_str(&format!(
+                " AND p.name IN ({})",
+                quote_list(&self.properties)
+            ));
+        }
+        conditions
+    }
+}
+
+/// Selection of the features associated with a point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AssociationMode {
+    /// Every feature within the distance threshold, so that points at the edges between
+    /// surfaces are associated multiple times.
+    #[default]
+    AllCandidates,
+    /// Only the feature closest to the point.
+    ///
+    /// Distances equal up to micrometers are ties, which are broken by the lowest feature id, so
+    /// that the association is deterministic.
+    NearestFeature,
+}
+
+impl AssociationMode {
+    /// Returns the statement associating the beams of a patch with the features within their
+    /// distance threshold, where only the selected geometries are considered and their LoD is
+    /// recorded.
+    pub(crate) fn point_association_query(
+        &self,
+        current_patch_id: i32,
+        distance_thresholds: &DistanceThresholds,
+        geometry_selection: &GeometrySelection,
+    ) -> Result<String, Error> {
+        let maximum_distance_threshold = distance_thresholds
+            .maximum()
+            .ok_or(Error::MissingDistanceThreshold)?;
+        let class_condition = if distance_thresholds.classes.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "
+    AND ST_3DDWithin(citydb.geometry_data.geometry, beam.reflection, {})",
+                distance_thresholds.sql_expression()
+            )
+        };
+        let class_join = if distance_thresholds.classes.is_empty() {
+            ""
+        } else {
+            "
+    JOIN
+        citydb.feature as f ON geometry_data.feature_id = f.id
+    LEFT JOIN
+        citydb.objectclass as oc ON f.objectclass_id = oc.id"
+        };
+        let geometry_condition = geometry_selection
+            .sql_condition("geometry_data")
+            .map(|c| format!("\n    AND {c}"))
+            .unwrap_or_default();
+        let lod = geometry_selection.lod_expression("geometry_data");
+        let parent_feature = geometry_selection.parent_feature_expression("geometry_data");
+        let candidates = format!(
+            "SELECT DISTINCT beam.id as beam_id, geometry_data.feature_id, ST_3DDistance(citydb.geometry_data.geometry, beam.reflection) as distance, {lod} as lod, {parent_feature} as parent_feature_id, beam.dataset_id
+    FROM
+        sensor_data.beam
+    JOIN
+        citydb.geometry_data
+    ON ST_3DDWithin(citydb.geometry_data.geometry, beam.reflection, {maximum_distance_threshold}){class_join}
+    WHERE
+        beam.patch_id = {current_patch_id}{class_condition}{geometry_condition}"
+        );
+
+        let selection = match self {
+            AssociationMode::AllCandidates => candidates,
+            AssociationMode::NearestFeature => format!(
+                "SELECT DISTINCT ON (c.beam_id) c.beam_id, c.feature_id, c.distance, c.lod, c.parent_feature_id, c.dataset_id
+FROM
+    ({candidates}) as c
+ORDER BY c.beam_id, round(c.distance::numeric, {TIE_DISTANCE_DECIMAL_PLACES}), c.feature_id"
+            ),
+        };
+
+        Ok(format!(
+            "INSERT INTO sensor_data.association_point_model (beam_id, feature_id, distance, lod, parent_feature_id, dataset_id)
+{selection};"
+        ))
+    }
+}
+
+/// Explodes the surfaces of the selected geometries of the city model into
+/// `feature_geometry_data`, unless they were already exploded for an equal selection.
+///
+/// The surfaces of each selection are kept separately, so that datasets associated with different
+/// selections do not interfere. Returns the id of the selection in `geometry_selection`.
+pub(crate) fn explode_feature_geometry_data(
+    connection: &mut PgConnection,
+    geometry_selection: &GeometrySelection,
+) -> Result<i32, Error> {
+    let key = geometry_selection.key();
+    // a concurrent insert of the same selection blocks until the surfaces are exploded
+    let inserted: Option<GeometrySelectionIdEntry> = diesel::sql_query(
+        "INSERT INTO sensor_data.geometry_selection (key) VALUES ($1) ON CONFLICT (key) DO NOTHING RETURNING id;",
+    )
+    .bind::<Text, _>(&key)
+    .get_result(connection)
+    .optional()?;
+    let Some(inserted) = inserted else {
+        let existing: GeometrySelectionIdEntry =
+            diesel::sql_query("SELECT id FROM sensor_data.geometry_selection WHERE key = $1;")
+                .bind::<Text, _>(&key)
+                .get_result(connection)?;
+        return Ok(existing.id);
+    };
+    let geometry_selection_id = inserted.id;
+
+    let geometry_condition = geometry_selection
+        .sql_condition("geometry_data")
+        .map(|c| format!(" AND\n        {c}"))
+        .unwrap_or_default();
+    let query = format!("INSERT INTO sensor_data.feature_geometry_data (geometry_data_id, feature_id, geometry, valid_geometry, normal_x, normal_y, normal_z, area, geometry_selection_id)
+SELECT
+    id,
+    feature_id,
+    geometry,
+    valid_geometry,
+    (sensor_data.polygon_normal(valid_geometry))[1],
+    (sensor_data.polygon_normal(valid_geometry))[2],
+    (sensor_data.polygon_normal(valid_geometry))[3],
+    sensor_data.polygon_area(valid_geometry),
+    {geometry_selection_id}
+FROM
+(SELECT
+    id,
+    feature_id,
+    geometry,
+    case when ST_GeometryType(valid_geometry) = 'ST_Polygon' AND ST_IsPlanar(valid_geometry) then valid_geometry else null end as valid_geometry
+FROM
+    (SELECT
+         id,
+         feature_id,
+         (ST_Dump(geometry_data.geometry)).geom::geometry(PolygonZ) as geometry,
+         ST_MakeValid((ST_Dump(geometry_data.geometry)).geom::geometry(PolygonZ)) as valid_geometry,
+         ST_AsText(ST_MakeValid((ST_Dump(geometry_data.geometry)).geom::geometry(PolygonZ)))
+    FROM geometry_data
+    WHERE
+        (ST_GeometryType(geometry_data.geometry) = 'ST_PolyhedralSurface' OR
+        ST_GeometryType(geometry_data.geometry) = 'ST_MultiPolygon'){geometry_condition}
+    ) as t) as v;");
+    diesel::sql_query(query).execute(connection)?;
+
+    Ok(geometry_selection_id)
+}
+
+/// Returns the condition restricting the rows of the given `feature_geometry_data` alias to the
+/// surfaces of the selection the dataset was associated with.
+pub(crate) fn dataset_surface_condition(feature_geometry_data: &str, dataset_id: i32) -> String {
+    format!("{feature_geometry_data}.geometry_selection_id = (SELECT geometry_selection_id FROM sensor_data.dataset WHERE id = {dataset_id})")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_candidates_query() {
+        let query = AssociationMode::AllCandidates
+            .point_association_query(
+                7,
+                &DistanceThresholds::default(),
+                &GeometrySelection::default(),
+            )
+            .unwrap();
+
+        assert!(query.contains("ST_3DDWithin(citydb.geometry_data.geometry, beam.reflection, 0.2)"));
+        assert!(query.contains("beam.patch_id = 7;"));
+        assert!(!query.contains("DISTINCT ON"));
+        assert!(!query.contains("citydb.objectclass"));
+        assert!(!query.contains("EXISTS"));
+    }
+
+    #[test]
+    fn test_nearest_feature_query() {
+        let query = AssociationMode::NearestFeature
+            .point_association_query(
+                7,
+                &DistanceThresholds::default(),
+                &GeometrySelection::default(),
+            )
+            .unwrap();
+
+        assert!(query.contains("SELECT DISTINCT ON (c.beam_id)"));
+        assert!(query.contains("ORDER BY c.beam_id, round(c.distance::numeric, 6), c.feature_id;"));
+    }
+
+    #[test]
+    fn test_class_distance_thresholds_query() {
+        let distance_thresholds = DistanceThresholds {
+            default: None,
+            classes: BTreeMap::from([
+                ("SolitaryVegetationObject".to_string(), 0.5),
+                ("WallSurface".to_string(), 0.1),
+            ]),
+        };
+
+        let query = AssociationMode::AllCandidates
+            .point_association_query(7, &distance_thresholds, &GeometrySelection::default())
+            .unwrap();
+
+        assert!(query.contains("ST_3DDWithin(citydb.geometry_data.geometry, beam.reflection, 0.5)"));
+        assert!(query.contains("AND ST_3DDWithin(citydb.geometry_data.geometry, beam.reflection, CASE oc.classname WHEN 'SolitaryVegetationObject' THEN 0.5 WHEN 'WallSurface' THEN 0.1 ELSE NULL END);"));
+    }
+
+    #[test]
+    fn test_missing_distance_threshold() {
+        let distance_thresholds = DistanceThresholds {
+            default: None,
+            classes: BTreeMap::new(),
+        };
+
+        assert!(AssociationMode::AllCandidates
+            .point_association_query(7, &distance_thresholds, &GeometrySelection::default())
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_distance_thresholds() {
+        let distance_thresholds: DistanceThresholds =
+            serde_json::from_str(r#"{"classes": {"WallSurface": 0.1}}"#).unwrap();
+
+        assert_eq!(distance_thresholds.default, None);
+        assert_eq!(distance_thresholds.maximum(), Some(0.1));
+    }
+
+    #[test]
+    fn test_geometry_selection_query() {
+        let geometry_selection = GeometrySelection {
+            lods: vec!["2".to_string(), "3".to_string()],
+            properties: vec!["lod2MultiSurface".to_string()],
+            thematic_surfaces: false,
+        };
+
+        let query = AssociationMode::NearestFeature
+            .point_association_query(7, &DistanceThresholds::default(), &geometry_selection)
+            .unwrap();
+
+        assert!(query.contains("AND EXISTS (SELECT 1 FROM citydb.property as p WHERE p.val_geometry_id = geometry_data.id AND p.val_lod IN ('2', '3') AND p.name IN ('lod2MultiSurface'))"));
+        assert!(query.contains("(SELECT min(p.val_lod) FROM citydb.property as p WHERE p.val_geometry_id = geometry_data.id AND p.val_lod IN ('2', '3') AND p.name IN ('lod2MultiSurface')) as lod"));
+        assert!(query.contains("c.lod, c.parent_feature_id, c.dataset_id"));
+        assert!(query.contains("NULL::BIGINT as parent_feature_id"));
+    }
+
+    #[test]
+    fn test_thematic_surfaces_query() {
+        let geometry_selection = GeometrySelection {
+            thematic_surfaces: true,
+            ..Default::default()
+        };
+
+        let query = AssociationMode::AllCandidates
+            .point_association_query(7, &DistanceThresholds::default(), &geometry_selection)
+            .unwrap();
+
+        assert!(query.contains(
+            "sensor_data.top_level_feature(geometry_data.feature_id) as parent_feature_id"
+        ));
+    }
+
+    #[test]
+    fn test_thematic_surfaces_condition() {
+        let geometry_selection = GeometrySelection {
+            thematic_surfaces: true,
+            ..Default::default()
+        };
+
+        let condition = geometry_selection.sql_condition("geometry_data").unwrap();
+
+        assert_eq!(condition, "NOT EXISTS (SELECT 1 FROM citydb.property as s JOIN citydb.geometry_data as sg ON s.val_feature_id = sg.feature_id WHERE s.feature_id = geometry_data.feature_id AND s.name = 'boundary')");
+        assert_eq!(
+            GeometrySelection::default().sql_condition("geometry_data"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_selection_key_is_canonical() {
+        let selection = GeometrySelection {
+            lods: vec!["2".into(), "1".into(), "2".into()],
+            properties: vec![],
+            thematic_surfaces: true,
+        };
+        let reordered = GeometrySelection {
+            lods: vec!["1".into(), "2".into()],
+            ..selection.clone()
+        };
+
+        assert_eq!(selection.key(), reordered.key());
+        assert_eq!(
+            selection.key(),
+            "lods=1,2;properties=;thematic_surfaces=true"
+        );
+        assert_ne!(selection.key(), GeometrySelection::default().key());
+    }
+}
@@ -0,0 +1,650 @@
+use crate::association::dataset_surface_condition;
+use crate::error::Error;
+use crate::models::exports::{
+    NearestFeatureEntry, PointCloudDownloadEntry, UnobservedFeatureEntry, VoxelEntry,
+};
+use crate::statistics::StatisticsFormat;
+use chrono::{DateTime, Duration, Utc};
+use diesel::sql_types::Double;
+use diesel::{PgConnection, RunQueryDsl};
+use nalgebra::Vector3;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::io::Write;
+
+/// Radius in meters around a cluster in which its nearest feature is searched.
+const NEAREST_FEATURE_SEARCH_RADIUS: f64 = 50.0;
+
+/// Grid size in meters to which the beam origins are snapped for deriving the trajectory.
+const TRAJECTORY_GRID_SIZE: f64 = 1.0;
+
+/// Parameters for detecting differences between the observations and the city model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChangeDetectionParameters {
+    /// Edge length in meters of the voxels into which the unassociated points are aggregated
+    pub voxel_size: f64,
+    /// Duration of the intervals, in which the observations of a voxel are counted
+    pub observation_interval: Duration,
+    /// Number of distinct intervals in which a voxel must be hit, so that moving objects are
+    /// discarded
+    pub minimum_observation_times: usize,
+    /// Number of points a cluster of consistently hit voxels must contain
+    pub minimum_cluster_size: usize,
+    /// Distance in meters up to which a surface facing the trajectory is expected to be hit
+    pub sensor_range: f64,
+    /// Number of associated points and beam intersections up to which a feature with expected hits
+    /// counts as unobserved
+    pub maximum_unobserved_points: i64,
+}
+
+impl Default for ChangeDetectionParameters {
+    fn default() -> Self {
+        Self {
+            voxel_size: 0.5,
+            observation_interval: Duration::seconds(1),
+            minimum_observation_times: 3,
+            minimum_cluster_size: 10,
+            sensor_range: 30.0,
+            maximum_unobserved_points: 0,
+        }
+    }
+}
+
+/// Differences between the observations of a dataset and the city model.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangeReport {
+    pub dataset: String,
+    /// Consistently hit points far from every surface, e.g. of missing annexes or new structures
+    pub clusters: Vec<UnexplainedCluster>,
+    /// Features facing the trajectory without hits, e.g. of demolished or incorrect geometry
+    pub unobserved_features: Vec<UnobservedFeature>,
+}
+
+/// Cluster of unassociated points, which were hit consistently over time.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UnexplainedCluster {
+    pub cluster_id: usize,
+    pub number_of_points: i64,
+    pub number_of_voxels: usize,
+    /// Number of distinct observation intervals, in which the cluster was hit
+    pub number_of_observation_times: usize,
+    pub first_observed: Option<DateTime<Utc>>,
+    pub last_observed: Option<DateTime<Utc>>,
+    pub centroid: [f64; 3],
+    pub lower_corner: [f64; 3],
+    pub upper_corner: [f64; 3],
+    pub nearest_gml_id: Option<String>,
+    pub nearest_classname: Option<String>,
+    /// Distance in meters between the centroid and the nearest feature
+    pub nearest_distance: Option<f64>,
+}
+
+/// Feature with surfaces facing the trajectory within sensor range, which received no hits.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UnobservedFeature {
+    pub gml_id: String,
+    pub classname: String,
+    pub number_of_visible_surfaces: i64,
+    /// Area in square meters of the surfaces facing the trajectory within sensor range
+    pub visible_surface_area: f64,
+    pub number_of_points: i64,
+    pub number_of_beam_intersections: i64,
+}
+
+/// Voxel of unassociated points.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Voxel {
+    pub index: [i32; 3],
+    pub number_of_points: i64,
+    /// Indices of the observation intervals, in which the voxel was hit
+    pub observation_times: Vec<i64>,
+    /// Seconds since the epoch of the first and last hit
+    pub first_timestamp: f64,
+    pub last_timestamp: f64,
+    pub coordinate_sum: Vector3<f64>,
+}
+
+impl From<VoxelEntry> for Voxel {
+    fn from(value: VoxelEntry) -> Self {
+        Self {
+            index: [value.i, value.j, value.k],
+            number_of_points: value.number_of_points,
+            observation_times: value.observation_times,
+            first_timestamp: value.first_timestamp,
+            last_timestamp: value.last_timestamp,
+            coordinate_sum: Vector3::new(value.sum_x, value.sum_y, value.sum_z),
+        }
+    }
+}
+
+/// Groups face, edge or corner adjacent voxels into clusters and keeps the ones with at least
+/// `minimum_cluster_size` points.
+///
+/// The clusters are ordered by their number of points and returned together with their voxel
+/// indices.
+pub(crate) fn cluster_voxels(
+    voxels: Vec<Voxel>,
+    voxel_size: f64,
+    minimum_cluster_size: usize,
+) -> Vec<(UnexplainedCluster, HashSet<[i32; 3]>)> {
+    let mut remaining_voxels: HashMap<[i32; 3], Voxel> =
+        voxels.into_iter().map(|v| (v.index, v)).collect();
+    let mut seed_indices: Vec<[i32; 3]> = remaining_voxels.keys().copied().collect();
+    seed_indices.sort();
+
+    let mut clusters: Vec<(UnexplainedCluster, HashSet<[i32; 3]>)> = Vec::new();
+    for seed_index in seed_indices {
+        let Some(seed_voxel) = remaining_voxels.remove(&seed_index) else {
+            continue;
+        };
+
+        let mut cluster_voxels: Vec<Voxel> = Vec::new();
+        let mut queue: VecDeque<Voxel> = VecDeque::from([seed_voxel]);
+        while let Some(voxel) = queue.pop_front() {
+            for di in -1..=1 {
+                for dj in -1..=1 {
+                    for dk in -1..=1 {
+                        let neighbour_index = [
+                            voxel.index[0] + di,
+                            voxel.index[1] + dj,
+                            voxel.index[2] + dk,
+                        ];
+                        if let Some(neighbour) = remaining_voxels.remove(&neighbour_index) {
+                            queue.push_back(neighbour);
+                        }
+                    }
+                }
+            }
+            cluster_voxels.push(voxel);
+        }
+
+        let number_of_points: i64 = cluster_voxels.iter().map(|v| v.number_of_points).sum();
+        if number_of_points < minimum_cluster_size as i64 {
+            continue;
+        }
+        clusters.push(derive_cluster(
+            &cluster_voxels,
+            voxel_size,
+            number_of_points,
+        ));
+    }
+
+    clusters.sort_by_key(|(c, _)| Reverse(c.number_of_points));
+    for (cluster_id, (cluster, _)) in clusters.iter_mut().enumerate() {
+        cluster.cluster_id = cluster_id;
+    }
+    clusters
+}
+
+fn derive_cluster(
+    voxels: &[Voxel],
+    voxel_size: f64,
+    number_of_points: i64,
+) -> (UnexplainedCluster, HashSet<[i32; 3]>) {
+    let observation_times: BTreeSet<i64> = voxels
+        .iter()
+        .flat_map(|v| v.observation_times.iter().copied())
+        .collect();
+    let first_timestamp = voxels
+        .iter()
+        .map(|v| v.first_timestamp)
+        .fold(f64::MAX, f64::min);
+    let last_timestamp = voxels
+        .iter()
+        .map(|v| v.last_timestamp)
+        .fold(f64::MIN, f64::max);
+    let centroid: Vector3<f64> = voxels
+        .iter()
+        .map(|v| v.coordinate_sum)
+        .sum::<Vector3<f64>>()
+        / number_of_points as f64;
+    let lower_index = voxels.iter().fold([i32::MAX; 3], |l, v| {
+        [0, 1, 2].map(|i| l[i].min(v.index[i]))
+    });
+    let upper_index = voxels.iter().fold([i32::MIN; 3], |u, v| {
+        [0, 1, 2].map(|i| u[i].max(v.index[i]))
+    });
+
+    let cluster = UnexplainedCluster {
+        cluster_id: 0,
+        number_of_points,
+        number_of_voxels: voxels.len(),
+        number_of_observation_times: observation_times.len(),
+        first_observed: date_time_from_seconds(first_timestamp),
+        last_observed: date_time_from_seconds(last_timestamp),
+        centroid: [centroid.x, centroid.y, centroid.z],
+        lower_corner: lower_index.map(|i| i as f64 * voxel_size),
+        upper_corner: upper_index.map(|i| (i + 1) as f64 * voxel_size),
+        nearest_gml_id: None,
+        nearest_classname: None,
+        nearest_distance: None,
+    };
+    (cluster, voxels.iter().map(|v| v.index).collect())
+}
+
+fn date_time_from_seconds(seconds: f64) -> Option<DateTime<Utc>> {
+    let whole_seconds = seconds.floor();
+    DateTime::from_timestamp(
+        whole_seconds as i64,
+        ((seconds - whole_seconds) * 1.0e9) as u32,
+    )
+}
+
+/// Clusters the unassociated points of a dataset, which were hit in at least
+/// `minimum_observation_times` intervals, and assigns the nearest feature to each cluster.
+///
+/// Since the points are unassociated, they are farther away from every surface than the distance
+/// threshold of the association.
+pub fn detect_unexplained_clusters(
+    connection: &mut PgConnection,
+    dataset_id: i32,
+    parameters: &ChangeDetectionParameters,
+) -> Result<Vec<(UnexplainedCluster, HashSet<[i32; 3]>)>, Error> {
+    let voxel_size = parameters.voxel_size;
+    let interval_seconds =
+        parameters.observation_interval.num_milliseconds().max(1) as f64 / 1000.0;
+    let minimum_observation_times = parameters.minimum_observation_times;
+    let query = format!(
+        "SELECT
+    floor(ST_X(b.reflection) / {voxel_size})::integer AS i,
+    floor(ST_Y(b.reflection) / {voxel_size})::integer AS j,
+    floor(ST_Z(b.reflection) / {voxel_size})::integer AS k,
+    count(*) AS number_of_points,
+    array_agg(DISTINCT floor((b.timestamp_sec + b.timestamp_nanosec * 1e-9) / {interval_seconds})::bigint) AS observation_times,
+    min(b.timestamp_sec + b.timestamp_nanosec * 1e-9)::DOUBLE PRECISION AS first_timestamp,
+    max(b.timestamp_sec + b.timestamp_nanosec * 1e-9)::DOUBLE PRECISION AS last_timestamp,
+    sum(ST_X(b.reflection)) AS sum_x,
+    sum(ST_Y(b.reflection)) AS sum_y,
+    sum(ST_Z(b.reflection)) AS sum_z
+FROM sensor_data.beam as b
+WHERE b.dataset_id = {dataset_id}
+  AND NOT EXISTS (SELECT 1 FROM sensor_data.association_point_model as apm WHERE apm.beam_id = b.id)
+GROUP BY 1, 2, 3
+HAVING count(DISTINCT floor((b.timestamp_sec + b.timestamp_nanosec * 1e-9) / {interval_seconds})) >= {minimum_observation_times};"
+    );
+    let voxels: Vec<VoxelEntry> = diesel::sql_query(query).load(connection)?;
+
+    let mut clusters = cluster_voxels(
+        voxels.into_iter().map(|v| v.into()).collect(),
+        voxel_size,
+        parameters.minimum_cluster_size,
+    );
+
+    for (cluster, _) in clusters.iter_mut() {
+        let query = format!(
+            "SELECT f.objectid AS gml_id, oc.classname, ST_3DDistance(gd.geometry, p.geom) AS distance
+FROM citydb.geometry_data as gd
+CROSS JOIN (SELECT ST_SetSRID(ST_MakePoint($1, $2, $3), (SELECT srid FROM citydb.database_srs LIMIT 1)) AS geom) as p
+JOIN citydb.feature as f ON gd.feature_id = f.id
+LEFT JOIN citydb.objectclass as oc ON f.objectclass_id = oc.id
+WHERE ST_DWithin(gd.geometry, p.geom, {NEAREST_FEATURE_SEARCH_RADIUS})
+ORDER BY distance
+LIMIT 1;"
+        );
+        let nearest_features: Vec<NearestFeatureEntry> = diesel::sql_query(query)
+            .bind::<Double, _>(cluster.centroid[0])
+            .bind::<Double, _>(cluster.centroid[1])
+            .bind::<Double, _>(cluster.centroid[2])
+            .load(connection)?;
+        if let Some(nearest_feature) = nearest_features.into_iter().next() {
+            cluster.nearest_gml_id = nearest_feature.gml_id;
+            cluster.nearest_classname = nearest_feature.classname;
+            cluster.nearest_distance = Some(nearest_feature.distance);
+        }
+    }
+
+    Ok(clusters)
+}
+
+/// Returns the unassociated points within the voxels of a cluster.
+pub fn load_cluster_points(
+    connection: &mut PgConnection,
+    dataset_id: i32,
+    voxel_size: f64,
+    cluster: &UnexplainedCluster,
+    voxel_indices: &HashSet<[i32; 3]>,
+) -> Result<Vec<PointCloudDownloadEntry>, Error> {
+    let [lower_x, lower_y, lower_z] = cluster.lower_corner;
+    let [upper_x, upper_y, upper_z] = cluster.upper_corner;
+    let query = format!(
+        "SELECT
+    b.id,
+    b.patch_id,
+    ST_X(b.reflection) AS x,
+    ST_Y(b.reflection) AS y,
+    ST_Z(b.reflection) AS z,
+    b.point_id,
+    b.timestamp_sec,
+    b.timestamp_nanosec,
+    b.intensity,
+    ST_X(b.origin) AS beam_origin_x,
+    ST_Y(b.origin) AS beam_origin_y,
+    ST_Z(b.origin) AS beam_origin_z,
+    b.length AS beam_length,
+    b.ros_message_id,
+    b.ros_point_id,
+    NULL::VARCHAR AS gml_id,
+    NULL::VARCHAR AS gml_name,
+    NULL::VARCHAR AS classname,
+    NULL::DOUBLE PRECISION AS surface_distance,
+    NULL::DOUBLE PRECISION AS intersection_angle,
+    b.dataset_id,
+    NULL::VARCHAR AS parent_gml_id,
+    NULL::VARCHAR AS surface_classname
+FROM sensor_data.beam as b
+WHERE b.dataset_id = {dataset_id}
+  AND ST_X(b.reflection) BETWEEN {lower_x} AND {upper_x}
+  AND ST_Y(b.reflection) BETWEEN {lower_y} AND {upper_y}
+  AND ST_Z(b.reflection) BETWEEN {lower_z} AND {upper_z}
+  AND NOT EXISTS (SELECT 1 FROM sensor_data.association_point_model as apm WHERE apm.beam_id = b.id)
+ORDER BY b.id;"
+    );
+    let mut points: Vec<PointCloudDownloadEntry> = diesel::sql_query(query).load(connection)?;
+
+    points.retain(|p| {
+        voxel_indices.contains(&[p.x, p.y, p.z].map(|c| (c / voxel_size).floor() as i32))
+    });
+    Ok(points)
+}
+
+/// Returns the features with surfaces facing the trajectory of a dataset within sensor range,
+/// which received at most `maximum_unobserved_points` associated points and beam intersections.
+///
+/// Occlusions are not considered, so that the features are only candidates for demolished or
+/// incorrect geometry. The surfaces are taken from `feature_geometry_data` of the geometry selection
+/// the dataset was associated with.
+pub fn detect_unobserved_features(
+    connection: &mut PgConnection,
+    dataset_id: i32,
+    parameters: &ChangeDetectionParameters,
+) -> Result<Vec<UnobservedFeature>, Error> {
+    let sensor_range = parameters.sensor_range;
+    let maximum_unobserved_points = parameters.maximum_unobserved_points;
+    let surface_condition = dataset_surface_condition("fgd", dataset_id);
+    let query = format!(
+        "WITH trajectory AS (
+    SELECT DISTINCT
+        round(ST_X(origin) / {TRAJECTORY_GRID_SIZE}) * {TRAJECTORY_GRID_SIZE} AS x,
+        round(ST_Y(origin) / {TRAJECTORY_GRID_SIZE}) * {TRAJECTORY_GRID_SIZE} AS y,
+        round(ST_Z(origin) / {TRAJECTORY_GRID_SIZE}) * {TRAJECTORY_GRID_SIZE} AS z
+    FROM sensor_data.beam
+    WHERE dataset_id = {dataset_id}),
+trajectory_extent AS (
+    SELECT ST_Expand(ST_SetSRID(ST_Extent(ST_MakePoint(x, y)), (SELECT srid FROM citydb.database_srs LIMIT 1)), {sensor_range}) AS envelope
+    FROM trajectory),
+visible_surface AS (
+    SELECT fgd.feature_id, fgd.area
+    FROM sensor_data.feature_geometry_data as fgd, trajectory_extent as e
+    WHERE fgd.valid_geometry IS NOT NULL
+      AND {surface_condition}
+      AND fgd.valid_geometry && e.envelope
+      AND EXISTS (
+        SELECT 1
+        FROM trajectory as t
+        WHERE ST_3DDWithin(fgd.valid_geometry, ST_SetSRID(ST_MakePoint(t.x, t.y, t.z), ST_SRID(fgd.valid_geometry)), {sensor_range})
+          AND fgd.normal_x * (t.x - ST_X(ST_PointN(ST_ExteriorRing(fgd.valid_geometry), 1)))
+            + fgd.normal_y * (t.y - ST_Y(ST_PointN(ST_ExteriorRing(fgd.valid_geometry), 1)))
+            + fgd.normal_z * (t.z - ST_Z(ST_PointN(ST_ExteriorRing(fgd.valid_geometry), 1))) > 0)),
+point_summary AS (
+    SELECT feature_id, count(DISTINCT beam_id) AS number_of_points
+    FROM sensor_data.association_point_model
+    WHERE dataset_id = {dataset_id}
+    GROUP BY feature_id),
+beam_summary AS (
+    SELECT feature_id, count(DISTINCT beam_id) AS number_of_beam_intersections
+    FROM sensor_data.association_beam_model
+    WHERE dataset_id = {dataset_id}
+    GROUP BY feature_id)
+SELECT
+    f.objectid AS gml_id,
+    oc.classname,
+    count(*) AS number_of_visible_surfaces,
+    coalesce(sum(vs.area), 0) AS visible_surface_area,
+    coalesce(max(ps.number_of_points), 0) AS number_of_points,
+    coalesce(max(bs.number_of_beam_intersections), 0) AS number_of_beam_intersections
+FROM visible_surface as vs
+JOIN citydb.feature as f ON vs.feature_id = f.id
+LEFT JOIN citydb.objectclass as oc ON f.objectclass_id = oc.id
+LEFT JOIN point_summary as ps ON vs.feature_id = ps.feature_id
+LEFT JOIN beam_summary as bs ON vs.feature_id = bs.feature_id
+GROUP BY f.id, f.objectid, oc.classname
+HAVING coalesce(max(ps.number_of_points), 0) <= {maximum_unobserved_points}
+   AND coalesce(max(bs.number_of_beam_intersections), 0) <= {maximum_unobserved_points}
+ORDER BY visible_surface_area DESC;"
+    );
+    let features: Vec<UnobservedFeatureEntry> = diesel::sql_query(query).load(connection)?;
+
+    Ok(features
+        .into_iter()
+        .map(|f| UnobservedFeature {
+            gml_id: f.gml_id.unwrap_or_default(),
+            classname: f.classname.unwrap_or_default(),
+            number_of_visible_surfaces: f.number_of_visible_surfaces,
+            visible_surface_area: f.visible_surface_area,
+            number_of_points: f.number_of_points,
+            number_of_beam_intersections: f.number_of_beam_intersections,
+        })
+        .collect())
+}
+
+/// Writes the change report in the given format.
+///
+/// The CSV format contains one row per cluster and unobserved feature distinguished by the
+/// column `kind`.
+pub fn write_change_report(
+    report: &ChangeReport,
+    format: StatisticsFormat,
+    mut writer: impl Write,
+) -> Result<(), Error> {
+    match format {
+        StatisticsFormat::Table => write!(writer, "{}", report.to_table())?,
+        StatisticsFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, report)?;
+            writeln!(writer)?;
+        }
+        StatisticsFormat::Csv => {
+            let mut csv_writer = csv::Writer::from_writer(writer);
+            csv_writer.write_record([
+                "dataset",
+                "kind",
+                "id",
+                "gml_id",
+                "classname",
+                "number_of_points",
+                "number_of_beam_intersections",
+                "number_of_observation_times",
+                "distance",
+                "surface_area",
+                "x",
+                "y",
+                "z",
+            ])?;
+            for record in report.to_records() {
+                csv_writer.write_record(&record)?;
+            }
+            csv_writer.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+impl ChangeReport {
+    fn to_records(&self) -> Vec<[String; 13]> {
+        let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+
+        let mut records: Vec<[String; 13]> = Vec::new();
+        for cluster in &self.clusters {
+            records.push([
+                self.dataset.clone(),
+                "cluster".to_string(),
+                cluster.cluster_id.to_string(),
+                optional(&cluster.nearest_gml_id),
+                optional(&cluster.nearest_classname),
+                cluster.number_of_points.to_string(),
+                String::new(),
+                cluster.number_of_observation_times.to_string(),
+                cluster
+                    .nearest_distance
+                    .map(|d| d.to_string())
+                    .unwrap_or_default(),
+                String::new(),
+                cluster.centroid[0].to_string(),
+                cluster.centroid[1].to_string(),
+                cluster.centroid[2].to_string(),
+            ]);
+        }
+        for feature in &self.unobserved_features {
+            records.push([
+                self.dataset.clone(),
+                "unobserved_feature".to_string(),
+                String::new(),
+                feature.gml_id.clone(),
+                feature.classname.clone(),
+                feature.number_of_points.to_string(),
+                feature.number_of_beam_intersections.to_string(),
+                String::new(),
+                String::new(),
+                feature.visible_surface_area.to_string(),
+                String::new(),
+                String::new(),
+                String::new(),
+            ]);
+        }
+
+        records
+    }
+
+    fn to_table(&self) -> String {
+        let mut table = format!("Dataset {}\n", self.dataset);
+
+        table.push_str(&format!(
+            "  Unexplained clusters ({})\n",
+            self.clusters.len()
+        ));
+        for cluster in &self.clusters {
+            let nearest_feature = match (&cluster.nearest_gml_id, cluster.nearest_distance) {
+                (Some(gml_id), Some(distance)) => format!(
+                    "{gml_id} ({}) at {distance:.2} m",
+                    cluster.nearest_classname.as_deref().unwrap_or("-")
+                ),
+                _ => "-".to_string(),
+            };
+            table.push_str(&format!(
+                "    #{:<4}{} points in {} observation times at ({:.2}, {:.2}, {:.2}), nearest feature: {}\n",
+                cluster.cluster_id,
+                cluster.number_of_points,
+                cluster.number_of_observation_times,
+                cluster.centroid[0],
+                cluster.centroid[1],
+                cluster.centroid[2],
+                nearest_feature
+            ));
+        }
+
+        table.push_str(&format!(
+            "  Unobserved features ({})\n",
+            self.unobserved_features.len()
+        ));
+        for feature in &self.unobserved_features {
+            table.push_str(&format!(
+                "    {} ({}): {} points and {} beam intersections on {} visible surfaces with {:.2} m²\n",
+                feature.gml_id,
+                feature.classname,
+                feature.number_of_points,
+                feature.number_of_beam_intersections,
+                feature.number_of_visible_surfaces,
+                feature.visible_surface_area
+            ));
+        }
+
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voxel(index: [i32; 3], number_of_points: i64, observation_times: Vec<i64>) -> Voxel {
+        let center = Vector3::new(
+            index[0] as f64 + 0.5,
+            index[1] as f64 + 0.5,
+            index[2] as f64 + 0.5,
+        );
+        Voxel {
+            index,
+            number_of_points,
+            observation_times,
+            first_timestamp: 1605702866.0,
+            last_timestamp: 1605702870.5,
+            coordinate_sum: center * number_of_points as f64,
+        }
+    }
+
+    #[test]
+    fn test_cluster_voxels() {
+        let voxels = vec![
+            voxel([0, 0, 0], 4, vec![1, 2, 3]),
+            voxel([1, 1, 1], 4, vec![3, 4]),
+            voxel([2, 1, 1], 2, vec![5]),
+            voxel([10, 0, 0], 20, vec![1, 2, 3]),
+            voxel([20, 0, 0], 2, vec![1, 2, 3]),
+        ];
+
+        let clusters = cluster_voxels(voxels, 1.0, 5);
+
+        assert_eq!(clusters.len(), 2);
+        let (largest_cluster, largest_voxels) = &clusters[0];
+        assert_eq!(largest_cluster.cluster_id, 0);
+        assert_eq!(largest_cluster.number_of_points, 20);
+        assert_eq!(largest_voxels.len(), 1);
+        let (adjacent_cluster, adjacent_voxels) = &clusters[1];
+        assert_eq!(adjacent_cluster.cluster_id, 1);
+        assert_eq!(adjacent_cluster.number_of_points, 10);
+        assert_eq!(adjacent_voxels.len(), 3);
+        assert_eq!(adjacent_cluster.number_of_observation_times, 5);
+        assert_eq!(adjacent_cluster.lower_corner, [0.0, 0.0, 0.0]);
+        assert_eq!(adjacent_cluster.upper_corner, [3.0, 2.0, 2.0]);
+        assert!((adjacent_cluster.centroid[0] - 1.3).abs() < 1.0e-9);
+    }
+
+    #[test]
+    fn test_date_time_from_seconds() {
+        let date_time = date_time_from_seconds(1605702866.25).unwrap();
+
+        assert_eq!(date_time.timestamp(), 1605702866);
+        assert_eq!(date_time.timestamp_subsec_millis(), 250);
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let report = ChangeReport {
+            dataset: "default".to_string(),
+            clusters: cluster_voxels(vec![voxel([0, 0, 0], 12, vec![1, 2, 3])], 1.0, 10)
+                .into_iter()
+                .map(|(c, _)| c)
+                .collect(),
+            unobserved_features: vec![UnobservedFeature {
+                gml_id: "DEBY_LOD2_4959457".to_string(),
+                classname: "Building".to_string(),
+                number_of_visible_surfaces: 3,
+                visible_surface_area: 42.5,
+                number_of_points: 0,
+                number_of_beam_intersections: 1,
+            }],
+        };
+        let mut buffer: Vec<u8> = Vec::new();
+        write_change_report(&report, StatisticsFormat::Csv, &mut buffer).unwrap();
+        let csv = String::from_utf8(buffer).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1], "default,cluster,0,,,12,,3,,,0.5,0.5,0.5");
+        assert_eq!(
+            lines[2],
+            "default,unobserved_feature,,DEBY_LOD2_4959457,Building,0,1,,,42.5,,,"
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use crate::association::dataset_surface_condition;
+use crate::error::Error;
+use crate::models::exports::FeatureObservationSummaryEntry;
+use chrono::{DateTime, Utc};
+use diesel::sql_types::Text;
+use diesel::{PgConnection, RunQueryDsl};
+
+/// Tolerance in meters for assigning an associated point to the surfaces of its feature, which
+/// are closest to the point.
+pub(crate) const SURFACE_DISTANCE_TOLERANCE: f64 = 0.001;
+
+/// Names of the generic attributes written to `citydb.property`.
+const ATTRIBUTE_DATASET: &str = "sensor_data_dataset";
+const ATTRIBUTE_NUMBER_OF_POINTS: &str = "sensor_data_number_of_points";
+const ATTRIBUTE_NUMBER_OF_BEAM_INTERSECTIONS: &str = "sensor_data_number_of_beam_intersections";
+const ATTRIBUTE_FIRST_OBSERVED: &str = "sensor_data_first_observed";
+const ATTRIBUTE_LAST_OBSERVED: &str = "sensor_data_last_observed";
+const ATTRIBUTE_MEAN_DISTANCE: &str = "sensor_data_mean_distance";
+const ATTRIBUTE_COVERAGE: &str = "sensor_data_coverage";
+
+/// Observations of a city object aggregated from the associations of a dataset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureObservationSummary {
+    pub feature_id: i64,
+    pub gml_id: String,
+    pub classname: String,
+    /// Number of beams associated with the feature by the point-model association
+    pub number_of_points: i64,
+    /// Number of intersections of beams with the surfaces of the feature
+    pub number_of_beam_intersections: i64,
+    pub first_observed: Option<DateTime<Utc>>,
+    pub last_observed: Option<DateTime<Utc>>,
+    /// Mean distance in meters between the associated points and the feature
+    pub mean_distance: Option<f64>,
+    /// Share of the surface area of the feature closest to at least one associated point
+    pub coverage: Option<f64>,
+}
+
+impl From<FeatureObservationSummaryEntry> for FeatureObservationSummary {
+    fn from(value: FeatureObservationSummaryEntry) -> Self {
+        Self {
+            feature_id: value.feature_id,
+            gml_id: value.gml_id.unwrap_or_default(),
+            classname: value.classname.unwrap_or_default(),
+            number_of_points: value.number_of_points,
+            number_of_beam_intersections: value.number_of_beam_intersections,
+            first_observed: value.first_observed,
+            last_observed: value.last_observed,
+            mean_distance: value.mean_distance,
+            coverage: value.coverage,
+        }
+    }
+}
+
+/// Aggregates the associations of a dataset by feature into the temporary table
+/// `observation_summary`, which is dropped at the end of the transaction.
+pub fn create_observation_summary(
+    connection: &mut PgConnection,
+    dataset_id: i32,
+) -> Result<Vec<FeatureObservationSummary>, Error> {
+    let surface_condition = dataset_surface_condition("fgd", dataset_id);
+    let query = format!(
+        "CREATE TEMPORARY TABLE observation_summary ON COMMIT DROP AS
+WITH point_summary AS (
+    SELECT
+        apm.feature_id,
+        count(DISTINCT apm.beam_id) AS number_of_points,
+        avg(apm.distance) AS mean_distance,
+        to_timestamp(min(b.timestamp_sec + b.timestamp_nanosec * 1e-9)) AS first_observed,
+        to_timestamp(max(b.timestamp_sec + b.timestamp_nanosec * 1e-9)) AS last_observed
+    FROM sensor_data.association_point_model as apm
+    JOIN sensor_data.beam as b ON apm.beam_id = b.id
+    WHERE apm.dataset_id = {dataset_id}
+    GROUP BY apm.feature_id),
+beam_summary AS (
+    SELECT feature_id, count(*) AS number_of_beam_intersections
+    FROM sensor_data.association_beam_model
+    WHERE dataset_id = {dataset_id}
+    GROUP BY feature_id),
+coverage_summary AS (
+    SELECT
+        fgd.feature_id,
+        coalesce(sum(fgd.area) FILTER (WHERE EXISTS (
+            SELECT 1
+            FROM sensor_data.association_point_model as apm
+            JOIN sensor_data.beam as b ON apm.beam_id = b.id
+            WHERE apm.dataset_id = {dataset_id}
+              AND apm.feature_id = fgd.feature_id
+              AND ST_3DDWithin(fgd.geometry, b.reflection, apm.distance + {SURFACE_DISTANCE_TOLERANCE}))), 0)
+            / nullif(sum(fgd.area), 0) AS coverage
+    FROM sensor_data.feature_geometry_data as fgd
+    WHERE {surface_condition}
+      AND fgd.feature_id IN (SELECT feature_id FROM point_summary)
+    GROUP BY fgd.feature_id),
+observed_feature AS (
+    SELECT feature_id FROM point_summary
+    UNION
+    SELECT feature_id FROM beam_summary)
+SELECT
+    o.feature_id,
+    f.objectid AS gml_id,
+    oc.classname AS classname,
+    coalesce(ps.number_of_points, 0) AS number_of_points,
+    coalesce(bs.number_of_beam_intersections, 0) AS number_of_beam_intersections,
+    ps.first_observed,
+    ps.last_observed,
+    ps.mean_distance,
+    cs.coverage
+FROM observed_feature as o
+JOIN citydb.feature as f ON o.feature_id = f.id
+LEFT JOIN citydb.objectclass as oc ON f.objectclass_id = oc.id
+LEFT JOIN point_summary as ps ON o.feature_id = ps.feature_id
+LEFT JOIN beam_summary as bs ON o.feature_id = bs.feature_id
+LEFT JOIN coverage_summary as cs ON o.feature_id = cs.feature_id;"
+    );
+    diesel::sql_query(query).execute(connection)?;
+
+    let summaries: Vec<FeatureObservationSummaryEntry> =
+        diesel::sql_query("SELECT * FROM observation_summary ORDER BY gml_id;").load(connection)?;
+    Ok(summaries.into_iter().map(|s| s.into()).collect())
+}
+
+/// Writes the generic attributes of `observation_summary`.
+///
+/// The attributes of a previous run for the dataset and the ones of the summarized features are
+/// replaced, while features enriched by other datasets are kept.
+/// Returns the number of written attributes.
+pub fn write_observation_summary(
+    connection: &mut PgConnection,
+    dataset: &str,
+) -> Result<usize, Error> {
+    let query = format!(
+        "DELETE FROM citydb.property
+WHERE namespace_id = (SELECT id FROM citydb.namespace WHERE alias = 'gen')
+  AND name IN ('{ATTRIBUTE_DATASET}', '{ATTRIBUTE_NUMBER_OF_POINTS}', '{ATTRIBUTE_NUMBER_OF_BEAM_INTERSECTIONS}', '{ATTRIBUTE_FIRST_OBSERVED}', '{ATTRIBUTE_LAST_OBSERVED}', '{ATTRIBUTE_MEAN_DISTANCE}', '{ATTRIBUTE_COVERAGE}')
+  AND (feature_id IN (
+        SELECT feature_id
+        FROM citydb.property
+        WHERE namespace_id = (SELECT id FROM citydb.namespace WHERE alias = 'gen')
+          AND name = '{ATTRIBUTE_DATASET}'
+          AND val_string = $1)
+    OR feature_id IN (SELECT feature_id FROM observation_summary));"
+    );
+    diesel::sql_query(query)
+        .bind::<Text, _>(dataset)
+        .execute(connection)?;
+
+    let query = format!(
+        "INSERT INTO citydb.property (feature_id, namespace_id, datatype_id, name, val_int, val_double, val_string, val_timestamp)
+SELECT s.feature_id, gen.id, dt.id, a.name, a.val_int, a.val_double, a.val_string, a.val_timestamp
+FROM observation_summary as s
+CROSS JOIN LATERAL (VALUES
+    ('{ATTRIBUTE_DATASET}', 'StringAttribute', NULL::BIGINT, NULL::DOUBLE PRECISION, $1::TEXT, NULL::TIMESTAMPTZ),
+    ('{ATTRIBUTE_NUMBER_OF_POINTS}', 'IntAttribute', s.number_of_points, NULL, NULL, NULL),
+    ('{ATTRIBUTE_NUMBER_OF_BEAM_INTERSECTIONS}', 'IntAttribute', s.number_of_beam_intersections, NULL, NULL, NULL),
+    ('{ATTRIBUTE_FIRST_OBSERVED}', 'DateAttribute', NULL, NULL, NULL, s.first_observed),
+    ('{ATTRIBUTE_LAST_OBSERVED}', 'DateAttribute', NULL, NULL, NULL, s.last_observed),
+    ('{ATTRIBUTE_MEAN_DISTANCE}', 'DoubleAttribute', NULL, s.mean_distance, NULL, NULL),
+    ('{ATTRIBUTE_COVERAGE}', 'DoubleAttribute', NULL, s.coverage, NULL, NULL)
+) as a(name, typename, val_int, val_double, val_string, val_timestamp)
+CROSS JOIN (SELECT id FROM citydb.namespace WHERE alias = 'gen') as gen
+JOIN citydb.datatype as dt ON dt.typename = a.typename AND dt.namespace_id = gen.id
+WHERE a.val_int IS NOT NULL
+   OR a.val_double IS NOT NULL
+   OR a.val_string IS NOT NULL
+   OR a.val_timestamp IS NOT NULL;"
+    );
+    let number_of_attributes = diesel::sql_query(query)
+        .bind::<Text, _>(dataset)
+        .execute(connection)?;
+    Ok(number_of_attributes)
+}
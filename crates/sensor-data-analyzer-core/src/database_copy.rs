@@ -0,0 +1,503 @@
+use crate::database_utils::UploadJobStep;
+use crate::error::Error;
+use crate::patch::{encode_hex, PatchPoint, PatchUncompressedBinary};
+use crate::schema::sensor_data::{beam, point_cloud_upload, surface_coverage_cell};
+use crate::surface_coverage::SurfaceCell;
+use diesel::pg::CopyFormat as DieselCopyFormat;
+use diesel::prelude::ExecuteCopyFromDsl;
+use diesel::sql_types::Integer;
+use diesel::{PgConnection, RunQueryDsl};
+use nalgebra::Point3;
+use std::fmt::Display;
+use std::io::Write;
+
+/// Method for ingesting point clouds into the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum UploadMethod {
+    /// Issues one `INSERT` statement per patch.
+    #[default]
+    Insert,
+    /// Streams the patches into `point_cloud_upload` via `COPY ... FROM STDIN`.
+    CopyPatch(CopyFormat),
+    /// Streams the already exploded beams into `beam` via `COPY ... FROM STDIN`.
+    CopyBeam(CopyFormat),
+}
+
+/// Format of the data sent via `COPY ... FROM STDIN`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CopyFormat {
+    #[default]
+    Text,
+    Binary,
+}
+
+impl From<CopyFormat> for DieselCopyFormat {
+    fn from(value: CopyFormat) -> Self {
+        match value {
+            CopyFormat::Text => DieselCopyFormat::Text,
+            CopyFormat::Binary => DieselCopyFormat::Binary,
+        }
+    }
+}
+
+/// Header of the PostgreSQL binary copy format consisting of the signature, flags field and header
+/// extension length.
+///
+/// See: https://www.postgresql.org/docs/current/sql-copy.html#id-1.9.3.55.9.4
+const BINARY_COPY_HEADER: &[u8; 19] = b"PGCOPY\n\xff\r\n\0\0\0\0\0\0\0\0\0";
+const BINARY_COPY_TRAILER: i16 = -1;
+const BEAM_COLUMN_COUNT: i16 = 13;
+
+/// Streams a point cloud as a single patch into `point_cloud_upload`.
+///
+/// pgPointCloud provides no binary receive function for `pcpatch`, so only the text format is
+/// supported, in which each patch is sent as hex encoded well-known binary.
+pub fn copy_point_cloud_patch(
+    connection: &mut PgConnection,
+    dataset_id: i32,
+    patch_id: i32,
+    pcid: u32,
+    point_cloud: &epoint::PointCloud,
+    format: CopyFormat,
+) -> Result<usize, Error> {
+    if format != CopyFormat::Text {
+        return Err(Error::UnsupportedCopyFormat(format));
+    }
+    let patch_hex = PatchUncompressedBinary::from_point_cloud(pcid, point_cloud)?.to_hex();
+
+    let number_of_rows = diesel::copy_from(point_cloud_upload::table)
+        .from_raw_data(
+            (
+                point_cloud_upload::id,
+                point_cloud_upload::dataset_id,
+                point_cloud_upload::pa,
+            ),
+            |copy: &mut dyn Write| -> Result<(), Error> {
+                writeln!(copy, "{}\t{}\t{}", patch_id, dataset_id, patch_hex)?;
+                Ok(())
+            },
+        )
+        .with_format(format.into())
+        .execute(connection)?;
+    Ok(number_of_rows)
+}
+
+/// Streams the individual points of a point cloud as beams into `beam`.
+///
+/// The `reflection_line` depends on the distance threshold of the association and is therefore
+/// initialized with the complete beam line and derived during the association.
+pub fn copy_point_cloud_beams(
+    connection: &mut PgConnection,
+    dataset_id: i32,
+    patch_id: i32,
+    srid: i32,
+    point_cloud: &epoint::PointCloud,
+    format: CopyFormat,
+) -> Result<usize, Error> {
+    let patch = PatchUncompressedBinary::from_point_cloud(0, point_cloud)?;
+
+    let number_of_rows = diesel::copy_from(beam::table)
+        .from_raw_data(
+            (
+                beam::patch_id,
+                beam::point_id,
+                beam::timestamp_sec,
+                beam::timestamp_nanosec,
+                beam::intensity,
+                beam::origin,
+                beam::reflection,
+                beam::line,
+                beam::length,
+                beam::reflection_line,
+                beam::ros_message_id,
+                beam::ros_point_id,
+                beam::dataset_id,
+            ),
+            |copy: &mut dyn Write| -> Result<(), Error> {
+                match format {
+                    CopyFormat::Text => write_beams_text(copy, dataset_id, patch_id, srid, &patch),
+                    CopyFormat::Binary => {
+                        write_beams_binary(copy, dataset_id, patch_id, srid, &patch)
+                    }
+                }
+            },
+        )
+        .with_format(format.into())
+        .execute(connection)?;
+    Ok(number_of_rows)
+}
+
+/// Streams the coverage cells of surfaces into `surface_coverage_cell`.
+///
+/// The cells are given together with the ids of their `feature_geometry_data` entry and feature.
+pub fn copy_surface_coverage_cells(
+    connection: &mut PgConnection,
+    dataset_id: i32,
+    srid: i32,
+    cell_size: f64,
+    cells: &[(i64, i64, SurfaceCell)],
+) -> Result<usize, Error> {
+    let number_of_rows = diesel::copy_from(surface_coverage_cell::table)
+        .from_raw_data(
+            (
+                surface_coverage_cell::dataset_id,
+                surface_coverage_cell::feature_geometry_data_id,
+                surface_coverage_cell::feature_id,
+                surface_coverage_cell::column_index,
+                surface_coverage_cell::row_index,
+                surface_coverage_cell::cell_size,
+                surface_coverage_cell::number_of_points,
+                surface_coverage_cell::geometry,
+            ),
+            |copy: &mut dyn Write| -> Result<(), Error> {
+                for (feature_geometry_data_id, feature_id, cell) in cells {
+                    writeln!(
+                        copy,
+                        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                        dataset_id,
+                        feature_geometry_data_id,
+                        feature_id,
+                        cell.column_index,
+                        cell.row_index,
+                        cell_size,
+                        cell.number_of_points,
+                        encode_hex(&ewkb_polygon_z(srid, &cell.corners)),
+                    )?;
+                }
+                Ok(())
+            },
+        )
+        .with_format(DieselCopyFormat::Text)
+        .execute(connection)?;
+    Ok(number_of_rows)
+}
+
+/// Marks the step of an upload job as completed.
+pub fn insert_upload_job_step(
+    connection: &mut PgConnection,
+    upload_job_step: &UploadJobStep,
+    patch_id: i32,
+) -> Result<(), Error> {
+    diesel::sql_query(
+        "INSERT INTO sensor_data.upload_job_step (upload_job_id, step, patch_id) VALUES ($1, $2, $3);",
+    )
+    .bind::<Integer, _>(upload_job_step.upload_job_id)
+    .bind::<Integer, _>(upload_job_step.step)
+    .bind::<Integer, _>(patch_id)
+    .execute(connection)?;
+    Ok(())
+}
+
+/// Deletes the steps of an upload job together with their patches, beams and all rows
+/// referencing these beams.
+pub fn delete_upload_job_steps(
+    connection: &mut PgConnection,
+    upload_job_id: i32,
+) -> Result<(), Error> {
+    let patch_ids = format!(
+        "SELECT patch_id FROM sensor_data.upload_job_step WHERE upload_job_id = {upload_job_id}"
+    );
+    for table in [
+        "association_point_model",
+        "association_beam_model",
+        "pass_through_conflict",
+    ] {
+        let query = format!(
+            "DELETE FROM sensor_data.{table}
+WHERE beam_id IN (SELECT id FROM sensor_data.beam WHERE patch_id IN ({patch_ids}));"
+        );
+        diesel::sql_query(query).execute(connection)?;
+    }
+    for (table, column) in [
+        ("point_cloud_download", "patch_id"),
+        ("beam", "patch_id"),
+        ("point_cloud_upload", "id"),
+    ] {
+        let query = format!("DELETE FROM sensor_data.{table} WHERE {column} IN ({patch_ids});");
+        diesel::sql_query(query).execute(connection)?;
+    }
+
+    diesel::sql_query("DELETE FROM sensor_data.upload_job_step WHERE upload_job_id = $1;")
+        .bind::<Integer, _>(upload_job_id)
+        .execute(connection)?;
+    Ok(())
+}
+
+/// Values of a point for the `integer` columns of `beam`.
+struct BeamIntegerColumns {
+    point_id: i32,
+    timestamp_sec: i32,
+    timestamp_nanosec: i32,
+    ros_message_id: i32,
+    ros_point_id: i32,
+}
+
+impl BeamIntegerColumns {
+    fn new(point: &PatchPoint) -> Result<Self, Error> {
+        Ok(Self {
+            point_id: to_integer_column("point_id", point.id)?,
+            timestamp_sec: to_integer_column("timestamp_sec", point.timestamp_sec)?,
+            timestamp_nanosec: to_integer_column("timestamp_nanosec", point.timestamp_nanosec)?,
+            ros_message_id: to_integer_column("ros_message_id", point.ros_message_id)?,
+            ros_point_id: to_integer_column("ros_point_id", point.ros_point_id)?,
+        })
+    }
+}
+
+fn to_integer_column<T>(column: &'static str, value: T) -> Result<i32, Error>
+where
+    T: Copy + Display + TryInto<i32>,
+{
+    value
+        .try_into()
+        .map_err(|_| Error::IntegerColumnOutOfRange {
+            column,
+            value: value.to_string(),
+        })
+}
+
+fn write_beams_text(
+    copy: &mut dyn Write,
+    dataset_id: i32,
+    patch_id: i32,
+    srid: i32,
+    patch: &PatchUncompressedBinary,
+) -> Result<(), Error> {
+    for point in patch.points() {
+        let origin = Point3::new(
+            point.beam_origin_x,
+            point.beam_origin_y,
+            point.beam_origin_z,
+        );
+        let reflection = Point3::new(point.x, point.y, point.z);
+        let line = encode_hex(&ewkb_line_string_z(srid, &[origin, reflection]));
+        let columns = BeamIntegerColumns::new(point)?;
+
+        writeln!(
+            copy,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            patch_id,
+            columns.point_id,
+            columns.timestamp_sec,
+            columns.timestamp_nanosec,
+            point.intensity as f64,
+            encode_hex(&ewkb_point_z(srid, &origin)),
+            encode_hex(&ewkb_point_z(srid, &reflection)),
+            line,
+            nalgebra::distance(&origin, &reflection),
+            line,
+            columns.ros_message_id,
+            columns.ros_point_id,
+            dataset_id,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn write_beams_binary(
+    copy: &mut dyn Write,
+    dataset_id: i32,
+    patch_id: i32,
+    srid: i32,
+    patch: &PatchUncompressedBinary,
+) -> Result<(), Error> {
+    copy.write_all(BINARY_COPY_HEADER)?;
+
+    for point in patch.points() {
+        let origin = Point3::new(
+            point.beam_origin_x,
+            point.beam_origin_y,
+            point.beam_origin_z,
+        );
+        let reflection = Point3::new(point.x, point.y, point.z);
+        let line = ewkb_line_string_z(srid, &[origin, reflection]);
+        let columns = BeamIntegerColumns::new(point)?;
+
+        copy.write_all(&BEAM_COLUMN_COUNT.to_be_bytes())?;
+        write_binary_field(copy, &patch_id.to_be_bytes())?;
+        write_binary_field(copy, &columns.point_id.to_be_bytes())?;
+        write_binary_field(copy, &columns.timestamp_sec.to_be_bytes())?;
+        write_binary_field(copy, &columns.timestamp_nanosec.to_be_bytes())?;
+        write_binary_field(copy, &(point.intensity as f64).to_be_bytes())?;
+        write_binary_field(copy, &ewkb_point_z(srid, &origin))?;
+        write_binary_field(copy, &ewkb_point_z(srid, &reflection))?;
+        write_binary_field(copy, &line)?;
+        write_binary_field(
+            copy,
+            &nalgebra::distance(&origin, &reflection).to_be_bytes(),
+        )?;
+        write_binary_field(copy, &line)?;
+        write_binary_field(copy, &columns.ros_message_id.to_be_bytes())?;
+        write_binary_field(copy, &columns.ros_point_id.to_be_bytes())?;
+        write_binary_field(copy, &dataset_id.to_be_bytes())?;
+    }
+
+    copy.write_all(&BINARY_COPY_TRAILER.to_be_bytes())?;
+    Ok(())
+}
+
+fn write_binary_field(copy: &mut dyn Write, value: &[u8]) -> Result<(), Error> {
+    copy.write_all(&(value.len() as i32).to_be_bytes())?;
+    copy.write_all(value)?;
+    Ok(())
+}
+
+const EWKB_POINT_TYPE: u32 = 1;
+const EWKB_LINE_STRING_TYPE: u32 = 2;
+const EWKB_POLYGON_TYPE: u32 = 3;
+const EWKB_Z_FLAG: u32 = 0x80000000;
+const EWKB_SRID_FLAG: u32 = 0x20000000;
+
+/// Encodes a 3D point as little endian extended well-known binary.
+fn ewkb_point_z(srid: i32, point: &Point3<f64>) -> Vec<u8> {
+    let mut buffer: Vec<u8> = Vec::with_capacity(1 + 4 + 4 + 3 * 8);
+    write_ewkb_header(&mut buffer, EWKB_POINT_TYPE, srid);
+    write_ewkb_coordinates(&mut buffer, point);
+    buffer
+}
+
+/// Encodes a 3D line string as little endian extended well-known binary.
+fn ewkb_line_string_z(srid: i32, points: &[Point3<f64>]) -> Vec<u8> {
+    let mut buffer: Vec<u8> = Vec::with_capacity(1 + 4 + 4 + 4 + points.len() * 3 * 8);
+    write_ewkb_header(&mut buffer, EWKB_LINE_STRING_TYPE, srid);
+    buffer.extend_from_slice(&(points.len() as u32).to_le_bytes());
+    points
+        .iter()
+        .for_each(|p| write_ewkb_coordinates(&mut buffer, p));
+    buffer
+}
+
+/// Encodes a 3D polygon without interior rings as little endian extended well-known binary.
+///
+/// The exterior ring is closed by repeating its first point.
+fn ewkb_polygon_z(srid: i32, exterior_ring: &[Point3<f64>]) -> Vec<u8> {
+    let number_of_points = exterior_ring.len() + 1;
+    let mut buffer: Vec<u8> = Vec::with_capacity(1 + 4 + 4 + 4 + 4 + number_of_points * 3 * 8);
+    write_ewkb_header(&mut buffer, EWKB_POLYGON_TYPE, srid);
+    buffer.extend_from_slice(&1u32.to_le_bytes());
+    buffer.extend_from_slice(&(number_of_points as u32).to_le_bytes());
+    exterior_ring
+        .iter()
+        .chain(exterior_ring.first())
+        .for_each(|p| write_ewkb_coordinates(&mut buffer, p));
+    buffer
+}
+
+fn write_ewkb_header(buffer: &mut Vec<u8>, geometry_type: u32, srid: i32) {
+    buffer.push(1);
+    buffer.extend_from_slice(&(geometry_type | EWKB_Z_FLAG | EWKB_SRID_FLAG).to_le_bytes());
+    buffer.extend_from_slice(&srid.to_le_bytes());
+}
+
+fn write_ewkb_coordinates(buffer: &mut Vec<u8>, point: &Point3<f64>) {
+    buffer.extend_from_slice(&point.x.to_le_bytes());
+    buffer.extend_from_slice(&point.y.to_le_bytes());
+    buffer.extend_from_slice(&point.z.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_point() -> PatchPoint {
+        PatchPoint {
+            x: 4.0,
+            y: 5.0,
+            z: 6.0,
+            id: 42,
+            timestamp_sec: 1605702866,
+            timestamp_nanosec: 123456789,
+            intensity: 0.75,
+            beam_origin_x: 1.0,
+            beam_origin_y: 2.0,
+            beam_origin_z: 3.0,
+            ros_message_id: 7,
+            ros_point_id: 1024,
+        }
+    }
+
+    #[test]
+    fn test_ewkb_point_z() {
+        let ewkb = ewkb_point_z(4326, &Point3::new(1.0, 2.0, 3.0));
+
+        assert_eq!(
+            encode_hex(&ewkb),
+            "01010000A0E6100000000000000000F03F00000000000000400000000000000840"
+        );
+    }
+
+    #[test]
+    fn test_ewkb_line_string_z() {
+        let ewkb = ewkb_line_string_z(
+            25832,
+            &[Point3::new(1.0, 2.0, 3.0), Point3::new(4.0, 5.0, 6.0)],
+        );
+
+        assert_eq!(
+            encode_hex(&ewkb),
+            "01020000A0E864000002000000\
+             000000000000F03F00000000000000400000000000000840\
+             000000000000104000000000000014400000000000001840"
+        );
+    }
+
+    #[test]
+    fn test_ewkb_polygon_z_is_closed() {
+        let ewkb = ewkb_polygon_z(
+            25832,
+            &[
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(1.0, 1.0, 0.0),
+            ],
+        );
+
+        assert_eq!(
+            &ewkb[..17],
+            b"\x01\x03\x00\x00\xa0\xe8\x64\x00\x00\x01\x00\x00\x00\x04\x00\x00\x00"
+        );
+        assert_eq!(ewkb.len(), 17 + 4 * 3 * 8);
+        assert_eq!(&ewkb[17..41], &ewkb[ewkb.len() - 24..]);
+    }
+
+    #[test]
+    fn test_write_beams_binary() {
+        let patch = PatchUncompressedBinary::new(1, vec![example_point()]);
+        let mut copy: Vec<u8> = Vec::new();
+        write_beams_binary(&mut copy, 3, 9, 25832, &patch).unwrap();
+
+        assert_eq!(
+            &copy[..19],
+            b"PGCOPY\n\xff\r\n\x00\x00\x00\x00\x00\x00\x00\x00\x00"
+        );
+        // number of fields followed by the length and value of patch_id and point_id
+        assert_eq!(&copy[19..21], &[0x00, 0x0d]);
+        assert_eq!(&copy[21..29], &[0, 0, 0, 4, 0, 0, 0, 9]);
+        assert_eq!(&copy[29..37], &[0, 0, 0, 4, 0, 0, 0, 42]);
+        // dataset_id as last field and the trailer
+        assert_eq!(
+            &copy[copy.len() - 10..],
+            &[0, 0, 0, 4, 0, 0, 0, 3, 0xff, 0xff]
+        );
+    }
+
+    #[test]
+    fn test_write_beams_rejects_out_of_range_values() {
+        let point = PatchPoint {
+            timestamp_sec: i64::from(i32::MAX) + 1,
+            ..example_point()
+        };
+        let patch = PatchUncompressedBinary::new(1, vec![point]);
+
+        let mut copy: Vec<u8> = Vec::new();
+        assert!(matches!(
+            write_beams_text(&mut copy, 3, 9, 25832, &patch),
+            Err(Error::IntegerColumnOutOfRange {
+                column: "timestamp_sec",
+                ..
+            })
+        ));
+        assert!(write_beams_binary(&mut copy, 3, 9, 25832, &patch).is_err());
+    }
+}
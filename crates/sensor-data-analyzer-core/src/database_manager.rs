@@ -1,12 +1,51 @@
-use crate::database_utils::{derive_point_cloud, insert_point_cloud};
+use crate::association::{
+    dataset_surface_condition, explode_feature_geometry_data, AssociationMode, DistanceThresholds,
+    GeometrySelection,
+};
+use crate::change_detection::{
+    detect_unexplained_clusters, detect_unobserved_features, load_cluster_points,
+    ChangeDetectionParameters, ChangeReport,
+};
+use crate::city_model_enrichment::{
+    create_observation_summary, write_observation_summary, FeatureObservationSummary,
+};
+use crate::database_copy::{
+    copy_point_cloud_beams, copy_point_cloud_patch, delete_upload_job_steps,
+    insert_upload_job_step, UploadMethod,
+};
+use crate::database_utils::{derive_point_cloud, UploadJobStep};
 use crate::diesel::ExpressionMethods;
+use crate::download_chunking::DownloadChunking;
+use crate::download_filter::DownloadFilter;
 use crate::error::Error;
-use crate::models::exports::PointCloudDownloadEntry;
+use crate::models::exports::{
+    BeamIntersectionCountEntry, ClassPointCountEntry, CoordinateExtentEntry, CountEntry,
+    CoverageCellVertexEntry, Dataset, DatasetIdEntry, DistanceBinEntry, FeaturePointCountEntry,
+    PatchIdEntry, PointCloudDownloadEntry, PointCloudFormatEntry, PointCloudFormatIdEntry,
+    SridEntry, SurfaceAreaEntry, TemporalCoverageEntry, UploadJobEntry,
+};
+use crate::patch::PatchUncompressedBinary;
+use crate::point_cloud_export::{write_point_cloud, ChunkSinks, CoordinateExtent, DownloadFormat};
+use crate::point_cloud_format::{is_compatible_schema, PointCloudFormat, POINT_CLOUD_SCHEMA};
+use crate::range_image_export::{write_range_images, RangeImageFormat};
+use crate::rosbag_labels::{
+    add_label_columns, append_labelled_point_clouds, check_ros_message_sizes,
+    get_ros_message_id_range, get_ros_message_sizes, load_point_labels, load_ros_message_sizes,
+    remove_labelled_points,
+};
 use crate::schema;
 use crate::schema::sensor_data::beam::patch_id;
+use crate::semantic_kitti::{
+    load_associated_features, write_semantic_kitti_sequence, LabelMapping, SensorFrame,
+};
+use crate::sensor_profile::SensorProfileRegistry;
+use crate::statistics::{ClassStatistics, DatasetStatistics, FeatureStatistics};
+use crate::surface_coverage::{coverage_cells_to_mesh, create_surface_coverage_cells};
 use chrono::Duration as ChronoDuration;
 use chrono::{DateTime, Utc};
-use diesel::QueryDsl;
+use diesel::r2d2::ConnectionManager;
+use diesel::sql_types::{BigInt, Integer, Text, Timestamptz, Varchar};
+use diesel::{Connection, PgConnection, QueryDsl};
 use diesel_async::pooled_connection::deadpool::Object;
 use diesel_async::pooled_connection::deadpool::Pool;
 use diesel_async::pooled_connection::AsyncDieselConnectionManager;
@@ -15,16 +54,23 @@ use epoint::io::{EpointWriter, XyzWriter};
 use epoint::transform::deterministic_downsample;
 use epoint::{PointCloud, PointDataColumnType};
 use erosbag::RosPointCloudColumnType;
-use rayon::prelude::*;
+use nalgebra::Point3;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::fs::create_dir_all;
 use std::path::{Path, PathBuf};
-use tokio::task::JoinHandle;
-use tracing::info;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::{info, warn};
 
 /// Manages the database
+#[derive(Clone)]
 pub struct DatabaseManager {
     pub(crate) connection_pool: Pool<AsyncPgConnection>,
+    /// Synchronous connections used for streaming data via `COPY ... FROM STDIN` and transactions
+    /// spanning multiple statements
+    pub(crate) copy_connection_pool: diesel::r2d2::Pool<ConnectionManager<PgConnection>>,
 }
 
 impl DatabaseManager {
@@ -37,109 +83,843 @@ impl DatabaseManager {
         let mut builder = Pool::builder(config).max_size(maximum_number_connections);
         let connection_pool = builder.build().unwrap();
 
-        Self { connection_pool }
+        let copy_connection_pool = diesel::r2d2::Pool::builder()
+            .max_size(maximum_number_connections as u32)
+            .min_idle(Some(0))
+            .build_unchecked(ConnectionManager::<PgConnection>::new(database_url));
+
+        Self {
+            connection_pool,
+            copy_connection_pool,
+        }
     }
 
+    /// Deletes the sensor data of all datasets.
     pub async fn clean(&self) -> Result<(), Error> {
         let mut connection: Object<AsyncPgConnection> = self.connection_pool.get().await.unwrap();
 
-        self.clean_download_tables().await?;
-        self.clean_association_tables().await?;
-
-        info!("Deleting entries in table point_cloud_upload");
+        info!("Deleting entries in all tables of schema sensor_data");
         let query = "TRUNCATE TABLE \
-        sensor_data.point_cloud_upload CASCADE;"
+        sensor_data.point_cloud_download,\
+        sensor_data.surface_coverage_cell,\
+        sensor_data.feature_geometry_data,\
+        sensor_data.geometry_selection,\
+        sensor_data.association_beam_model,\
+        sensor_data.association_point_model,\
+        sensor_data.pass_through_conflict,\
+        sensor_data.point_cloud_upload,\
+        sensor_data.beam,\
+        sensor_data.upload_job,\
+        sensor_data.upload_job_step,\
+        sensor_data.dataset CASCADE;"
             .to_string();
         diesel::sql_query(query).execute(&mut connection).await?;
 
         Ok(())
     }
 
-    pub async fn clean_association_tables(&self) -> Result<(), Error> {
+    /// Deletes a dataset together with its sensor data, while the other datasets are kept.
+    pub async fn clean_dataset(&self, dataset: &str) -> Result<(), Error> {
+        let dataset_id = match self.get_dataset_id(dataset).await {
+            Ok(dataset_id) => dataset_id,
+            Err(Error::DatasetNotFound(_)) => {
+                info!("Dataset {dataset} does not exist, nothing to delete");
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+
+        self.clean_download_tables(dataset_id).await?;
+        self.clean_association_tables(dataset_id).await?;
+
+        let mut connection: Object<AsyncPgConnection> = self.connection_pool.get().await.unwrap();
+        info!(
+            "Deleting entries of dataset {dataset} in table point_cloud_upload, beam, upload_job"
+        );
+        let query = format!("DELETE FROM sensor_data.beam WHERE dataset_id = {dataset_id};");
+        diesel::sql_query(query).execute(&mut connection).await?;
+        let query =
+            format!("DELETE FROM sensor_data.point_cloud_upload WHERE dataset_id = {dataset_id};");
+        diesel::sql_query(query).execute(&mut connection).await?;
+        let query = format!("DELETE FROM sensor_data.upload_job WHERE dataset_id = {dataset_id};");
+        diesel::sql_query(query).execute(&mut connection).await?;
+
+        diesel::delete(
+            schema::sensor_data::dataset::dsl::dataset
+                .filter(schema::sensor_data::dataset::id.eq(dataset_id)),
+        )
+        .execute(&mut connection)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn clean_association_tables(&self, dataset_id: i32) -> Result<(), Error> {
         let mut connection = self.connection_pool.get().await.unwrap();
 
         info!(
-            "Deleting entries in table feature_geometry_data, association_beam_model, association_point_model, beam"
+            "Deleting entries of dataset {dataset_id} in table association_beam_model, association_point_model, pass_through_conflict, surface_coverage_cell"
+        );
+        let query = format!(
+            "DELETE FROM sensor_data.pass_through_conflict WHERE dataset_id = {dataset_id};"
+        );
+        diesel::sql_query(query).execute(&mut connection).await?;
+        let query = format!(
+            "DELETE FROM sensor_data.surface_coverage_cell WHERE dataset_id = {dataset_id};"
+        );
+        diesel::sql_query(query).execute(&mut connection).await?;
+        let query = format!(
+            "DELETE FROM sensor_data.association_beam_model WHERE dataset_id = {dataset_id};"
+        );
+        diesel::sql_query(query).execute(&mut connection).await?;
+        let query = format!(
+            "DELETE FROM sensor_data.association_point_model WHERE dataset_id = {dataset_id};"
         );
+        diesel::sql_query(query).execute(&mut connection).await?;
 
-        let query = "TRUNCATE TABLE \
-                sensor_data.feature_geometry_data,\
-                sensor_data.association_beam_model,\
-                sensor_data.association_point_model,\
-                sensor_data.beam CASCADE;"
-            .to_string();
+        // beams uploaded directly have no patch and are kept
+        info!("Deleting entries of dataset {dataset_id} in table beam derived from point_cloud_upload");
+        let query = format!(
+            "DELETE FROM sensor_data.beam \
+                WHERE dataset_id = {dataset_id} \
+                AND patch_id IN (SELECT id FROM sensor_data.point_cloud_upload WHERE dataset_id = {dataset_id});"
+        );
         diesel::sql_query(query).execute(&mut connection).await?;
 
         Ok(())
     }
 
-    pub async fn clean_download_tables(&self) -> Result<(), Error> {
+    pub async fn clean_download_tables(&self, dataset_id: i32) -> Result<(), Error> {
         let mut connection = self.connection_pool.get().await.unwrap();
 
-        info!("Deleting entries in table point_cloud_download");
-        let query = "TRUNCATE TABLE \
-        sensor_data.point_cloud_download  CASCADE;"
-            .to_string();
+        info!("Deleting entries of dataset {dataset_id} in table point_cloud_download");
+        let query = format!(
+            "DELETE FROM sensor_data.point_cloud_download WHERE dataset_id = {dataset_id};"
+        );
         diesel::sql_query(query).execute(&mut connection).await?;
 
         Ok(())
     }
 
-    pub async fn run_stats(&self) -> Result<(), Error> {
+    /// Returns all datasets ordered by their creation.
+    pub async fn get_datasets(&self) -> Result<Vec<Dataset>, Error> {
         let mut connection = self.connection_pool.get().await.unwrap();
 
-        //let res: Vec<models::sensor_data::Patches> = schema::sensor_data::patches::dsl::patches
-        //    .load::<models::sensor_data::Patches>(&mut connection)
-        //    .expect("Error loading messages");
+        let datasets: Vec<Dataset> = schema::sensor_data::dataset::dsl::dataset
+            .order(schema::sensor_data::dataset::id)
+            .load::<Dataset>(&mut connection)
+            .await?;
+        Ok(datasets)
+    }
 
-        let res: Vec<i32> = schema::sensor_data::point_cloud_upload::dsl::point_cloud_upload
-            .select(schema::sensor_data::point_cloud_upload::id)
+    pub async fn get_dataset(&self, dataset: &str) -> Result<Dataset, Error> {
+        let mut connection = self.connection_pool.get().await.unwrap();
+
+        let datasets: Vec<Dataset> = schema::sensor_data::dataset::dsl::dataset
+            .filter(schema::sensor_data::dataset::name.eq(dataset))
+            .load::<Dataset>(&mut connection)
+            .await?;
+        datasets
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::DatasetNotFound(dataset.to_string()))
+    }
+
+    pub async fn get_dataset_id(&self, dataset: &str) -> Result<i32, Error> {
+        let mut connection = self.connection_pool.get().await.unwrap();
+
+        let dataset_ids: Vec<i32> = schema::sensor_data::dataset::dsl::dataset
+            .filter(schema::sensor_data::dataset::name.eq(dataset))
+            .select(schema::sensor_data::dataset::id)
             .load(&mut connection)
             .await?;
+        dataset_ids
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::DatasetNotFound(dataset.to_string()))
+    }
+
+    /// Creates a dataset or returns the id of the already existing one.
+    pub async fn create_dataset(&self, dataset: &str) -> Result<i32, Error> {
+        let mut connection = self.connection_pool.get().await.unwrap();
+
+        let entry: DatasetIdEntry = diesel::sql_query(
+            "INSERT INTO sensor_data.dataset (name) VALUES ($1)
+ON CONFLICT (name) DO UPDATE SET name = EXCLUDED.name
+RETURNING id;",
+        )
+        .bind::<Varchar, _>(dataset)
+        .get_result(&mut connection)
+        .await?;
+        Ok(entry.id)
+    }
+
+    /// Writes a summary of the observations of each associated city object as generic attributes
+    /// into `citydb.property`.
+    ///
+    /// The attributes of a previous enrichment are replaced, so that they always refer to the
+    /// latest enriched dataset. With `dry_run`, the summaries are only derived without modifying
+    /// the city model.
+    pub async fn enrich_city_model(
+        &self,
+        dataset: &str,
+        dry_run: bool,
+    ) -> Result<Vec<FeatureObservationSummary>, Error> {
+        let dataset_id = self.get_dataset_id(dataset).await?;
+        let dataset = dataset.to_string();
+
+        let copy_connection_pool = self.copy_connection_pool.clone();
+        let summaries = tokio::task::spawn_blocking(
+            move || -> Result<Vec<FeatureObservationSummary>, Error> {
+                let mut connection = copy_connection_pool.get()?;
+                connection.transaction::<_, Error, _>(|connection| {
+                    let summaries = create_observation_summary(connection, dataset_id)?;
+                    info!("Summarized observations of {} features", summaries.len());
+
+                    if !dry_run {
+                        let number_of_attributes = write_observation_summary(connection, &dataset)?;
+                        info!("Wrote {number_of_attributes} generic attributes to citydb.property");
+                    }
+                    Ok(summaries)
+                })
+            },
+        )
+        .await
+        .unwrap()?;
+
+        Ok(summaries)
+    }
+
+    /// Rasterizes the surfaces of the associated features of a dataset into square cells with an
+    /// edge length of `cell_size` meters and counts the associated reflections per cell.
+    ///
+    /// The cells of a previous computation for the dataset are replaced. Returns the number of
+    /// cells.
+    pub async fn compute_surface_coverage(
+        &self,
+        dataset: &str,
+        cell_size: f64,
+    ) -> Result<usize, Error> {
+        if cell_size.is_nan() || cell_size <= 0.0 {
+            return Err(Error::InvalidCellSize(cell_size));
+        }
+        let dataset_id = self.get_dataset_id(dataset).await?;
+        let srid = self.get_city_model_srid().await?;
+
+        let copy_connection_pool = self.copy_connection_pool.clone();
+        let number_of_cells = tokio::task::spawn_blocking(move || -> Result<usize, Error> {
+            let mut connection = copy_connection_pool.get()?;
+            connection.transaction::<_, Error, _>(|connection| {
+                create_surface_coverage_cells(connection, dataset_id, srid, cell_size)
+            })
+        })
+        .await
+        .unwrap()?;
+        info!("Wrote {number_of_cells} cells to surface_coverage_cell");
+
+        Ok(number_of_cells)
+    }
+
+    /// Exports the surface coverage cells of a dataset as a glTF mesh colored by the number of
+    /// reflections per cell, where `full_coverage_number_of_points` reflections count as fully
+    /// covered.
+    pub async fn export_surface_coverage(
+        &self,
+        dataset: &str,
+        file_path: impl AsRef<Path>,
+        full_coverage_number_of_points: i32,
+    ) -> Result<(), Error> {
+        let dataset_id = self.get_dataset_id(dataset).await?;
+        let mut connection = self.connection_pool.get().await.unwrap();
+
+        let vertices: Vec<CoverageCellVertexEntry> = diesel::sql_query(format!(
+            "SELECT
+    c.id,
+    c.number_of_points,
+    ST_X((d.dump).geom) AS x,
+    ST_Y((d.dump).geom) AS y,
+    ST_Z((d.dump).geom) AS z
+FROM sensor_data.surface_coverage_cell as c
+CROSS JOIN LATERAL (SELECT ST_DumpPoints(c.geometry) AS dump) as d
+WHERE c.dataset_id = {dataset_id}
+  AND (d.dump).path[2] <= 4
+ORDER BY c.id, (d.dump).path[2];"
+        ))
+        .load(&mut connection)
+        .await?;
+        let cells: Vec<([Point3<f64>; 4], i32)> = vertices
+            .chunks_exact(4)
+            .map(|v| {
+                (
+                    [0, 1, 2, 3].map(|i| Point3::new(v[i].x, v[i].y, v[i].z)),
+                    v[0].number_of_points,
+                )
+            })
+            .collect();
+        if cells.is_empty() {
+            info!("No surface coverage cells of dataset {dataset}, compute them first");
+            return Ok(());
+        }
+
+        let (mesh, offset) = coverage_cells_to_mesh(&cells, full_coverage_number_of_points)?;
+        info!(
+            "Export {} cells translated by offset ({}, {}, {})",
+            cells.len(),
+            offset.x,
+            offset.y,
+            offset.z
+        );
+        egraphics::io::EgraphicsExporter::new(file_path)
+            .with_create_parent_directories(true)
+            .finish(mesh)?;
 
-        info!("Length: {}", res.len());
         Ok(())
     }
 
+    /// Flags differences between the observations of a dataset and the city model.
+    ///
+    /// Unassociated points, which were hit consistently over time, are clustered as indication of
+    /// missing geometry, and features facing the trajectory without hits are reported as
+    /// indication of demolished or incorrect geometry. With `cluster_directory_path`, the points
+    /// of each cluster are written to a file named by its cluster id.
+    pub async fn analyze_changes(
+        &self,
+        dataset: &str,
+        parameters: ChangeDetectionParameters,
+        cluster_directory_path: Option<PathBuf>,
+        format: DownloadFormat,
+    ) -> Result<ChangeReport, Error> {
+        let dataset_id = self.get_dataset_id(dataset).await?;
+        let dataset = dataset.to_string();
+
+        let copy_connection_pool = self.copy_connection_pool.clone();
+        let report = tokio::task::spawn_blocking(move || -> Result<ChangeReport, Error> {
+            let mut connection = copy_connection_pool.get()?;
+
+            let clusters = detect_unexplained_clusters(&mut connection, dataset_id, &parameters)?;
+            info!("Detected {} unexplained clusters", clusters.len());
+            let unobserved_features =
+                detect_unobserved_features(&mut connection, dataset_id, &parameters)?;
+            info!("Detected {} unobserved features", unobserved_features.len());
+
+            if let Some(cluster_directory_path) = cluster_directory_path {
+                create_dir_all(&cluster_directory_path)?;
+                for (cluster, voxel_indices) in &clusters {
+                    let points = load_cluster_points(
+                        &mut connection,
+                        dataset_id,
+                        parameters.voxel_size,
+                        cluster,
+                        voxel_indices,
+                    )?;
+                    let path = format.file_path(
+                        &cluster_directory_path,
+                        &format!("cluster_{}", cluster.cluster_id),
+                    );
+                    write_point_cloud(derive_point_cloud(points)?, format, path)?;
+                }
+            }
+
+            Ok(ChangeReport {
+                dataset,
+                clusters: clusters.into_iter().map(|(c, _)| c).collect(),
+                unobserved_features,
+            })
+        })
+        .await
+        .unwrap()?;
+
+        Ok(report)
+    }
+
+    /// Exports the associated scans of a dataset as SemanticKITTI sequence for training point
+    /// cloud segmentation networks.
+    ///
+    /// Each ROS point cloud message of the sensor frame is written as scan numbered in the order of
+    /// the `ros_message_id`s, whose points keep the order of their `ros_point_id`, and the labels
+    /// are numbered by the CityGML classnames. The scans are resolved to the sensor frame and
+    /// padded to the number of points of their message in the rosbag the dataset was uploaded
+    /// from.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn export_semantic_kitti(
+        &self,
+        dataset: &str,
+        rosbag: erosbag::Rosbag,
+        step_duration: ChronoDuration,
+        start_date_time: DateTime<Utc>,
+        stop_date_time: DateTime<Utc>,
+        directory_path: impl AsRef<Path>,
+        sequence: u32,
+        sensor_frame: SensorFrame,
+    ) -> Result<usize, Error> {
+        let dataset_id = self.get_dataset_id(dataset).await?;
+        let directory_path = directory_path.as_ref().to_path_buf();
+        let total_duration = stop_date_time - start_date_time;
+        let number_of_steps: i32 = (total_duration.num_milliseconds() as f64
+            / step_duration.num_milliseconds() as f64)
+            .ceil() as i32;
+
+        let mut ros_message_sizes: HashMap<u32, u32> = HashMap::new();
+        for step in 0..number_of_steps {
+            info!("Reading message sizes: {}/{}", step, number_of_steps);
+            let step_start_time = start_date_time + step_duration * step;
+            let step_stop_time = stop_date_time.min(step_start_time + step_duration);
+            let mut point_cloud = tokio::task::block_in_place(|| {
+                rosbag.get_point_clouds(&Some(step_start_time), &Some(step_stop_time))
+            })?;
+            remove_labelled_points(&mut point_cloud)?;
+            if point_cloud.size() == 0
+                || !point_cloud
+                    .get_distinct_frame_ids()
+                    .contains(&sensor_frame.frame_id)
+            {
+                continue;
+            }
+            // only the messages of the sensor frame are exported, so that lidars are not mixed
+            let sensor_point_cloud = point_cloud.filter_by_frame_id(&sensor_frame.frame_id)?;
+            for (ros_message_id, size) in get_ros_message_sizes(&sensor_point_cloud)? {
+                let current_size = ros_message_sizes.entry(ros_message_id).or_default();
+                *current_size = (*current_size).max(size);
+            }
+        }
+
+        let copy_connection_pool = self.copy_connection_pool.clone();
+        let number_of_scans = tokio::task::spawn_blocking(move || -> Result<usize, Error> {
+            let mut connection = copy_connection_pool.get()?;
+            write_semantic_kitti_sequence(
+                &mut connection,
+                dataset_id,
+                &directory_path,
+                sequence,
+                &sensor_frame,
+                &ros_message_sizes,
+            )
+        })
+        .await
+        .unwrap()?;
+
+        Ok(number_of_scans)
+    }
+
+    /// Writes the associations of a dataset back into the rosbag it was uploaded from.
+    ///
+    /// For each lidar, a PointCloud2 topic `{topic_prefix}/{frame_id}` is appended, whose
+    /// messages contain the original points extended by the `feature_id`, `class_id` and
+    /// `surface_distance` of their nearest associated feature. The points are matched by their
+    /// `ros_message_id` and `ros_point_id`, where unassociated points get the `feature_id` and
+    /// `class_id` `0` and a NaN distance. The class ids follow the SemanticKITTI export.
+    ///
+    /// Points of previously labelled topics are not read again, and the uploaded messages must be
+    /// contained in the rosbag with at least as many points, so that a rosbag differing from the
+    /// upload is rejected.
+    ///
+    /// Returns the number of written messages.
+    pub async fn label_rosbag(
+        &self,
+        dataset: &str,
+        mut rosbag: erosbag::Rosbag,
+        topic_prefix: &str,
+        step_duration: ChronoDuration,
+        start_date_time: DateTime<Utc>,
+        stop_date_time: DateTime<Utc>,
+    ) -> Result<usize, Error> {
+        let dataset_id = self.get_dataset_id(dataset).await?;
+        let total_duration = stop_date_time - start_date_time;
+        let number_of_steps: i32 = (total_duration.num_milliseconds() as f64
+            / step_duration.num_milliseconds() as f64)
+            .ceil() as i32;
+
+        let copy_connection_pool = self.copy_connection_pool.clone();
+        let label_mapping = tokio::task::spawn_blocking(move || -> Result<LabelMapping, Error> {
+            let mut connection = copy_connection_pool.get()?;
+            let features = load_associated_features(&mut connection, dataset_id)?;
+            Ok(LabelMapping::from_classnames(
+                features.into_iter().filter_map(|f| f.classname),
+            ))
+        })
+        .await
+        .unwrap()?;
+        let label_mapping = Arc::new(label_mapping);
+
+        let mut number_of_messages: usize = 0;
+        for step in 0..number_of_steps {
+            info!("Labelling point clouds: {}/{}", step, number_of_steps);
+            let step_start_time = start_date_time + step_duration * step;
+            let step_stop_time = stop_date_time.min(step_start_time + step_duration);
+            let mut point_cloud = tokio::task::block_in_place(|| {
+                rosbag.get_point_clouds(&Some(step_start_time), &Some(step_stop_time))
+            })?;
+            remove_labelled_points(&mut point_cloud)?;
+            if point_cloud.size() == 0 {
+                continue;
+            }
+
+            let ros_message_id_range = get_ros_message_id_range(&point_cloud)?;
+            let copy_connection_pool = self.copy_connection_pool.clone();
+            let label_mapping = label_mapping.clone();
+            let (labels, uploaded_sizes) = tokio::task::spawn_blocking(move || {
+                let mut connection = copy_connection_pool.get()?;
+                let labels = load_point_labels(
+                    &mut connection,
+                    dataset_id,
+                    ros_message_id_range,
+                    &label_mapping,
+                )?;
+                let uploaded_sizes =
+                    load_ros_message_sizes(&mut connection, dataset_id, ros_message_id_range)?;
+                Ok::<_, Error>((labels, uploaded_sizes))
+            })
+            .await
+            .unwrap()?;
+            check_ros_message_sizes(&uploaded_sizes, &get_ros_message_sizes(&point_cloud)?)?;
+
+            add_label_columns(&mut point_cloud, &labels)?;
+            number_of_messages += tokio::task::block_in_place(|| {
+                append_labelled_point_clouds(&mut rosbag, topic_prefix, &point_cloud)
+            })?;
+        }
+
+        info!("Finished labelling {number_of_messages} messages");
+        Ok(number_of_messages)
+    }
+
+    /// Exports the scans of the rosbag as range images per lidar and ROS message.
+    ///
+    /// Each point cloud message is rasterized by the sensor profile assigned to its frame id and
+    /// written to `{frame_id}/{channel}/{ros_message_id}` with the channels `range`,
+    /// `intensity`, `class` and `surface_distance` of the nearest associated feature. Lidars
+    /// without assigned profile are skipped.
+    ///
+    /// Returns the number of exported scans.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn export_range_images(
+        &self,
+        dataset: &str,
+        rosbag: erosbag::Rosbag,
+        sensor_profiles: &SensorProfileRegistry,
+        step_duration: ChronoDuration,
+        start_date_time: DateTime<Utc>,
+        stop_date_time: DateTime<Utc>,
+        directory_path: impl AsRef<Path>,
+        formats: &[RangeImageFormat],
+    ) -> Result<usize, Error> {
+        let dataset_id = self.get_dataset_id(dataset).await?;
+        let directory_path = directory_path.as_ref();
+        let total_duration = stop_date_time - start_date_time;
+        let number_of_steps: i32 = (total_duration.num_milliseconds() as f64
+            / step_duration.num_milliseconds() as f64)
+            .ceil() as i32;
+
+        let copy_connection_pool = self.copy_connection_pool.clone();
+        let label_mapping = tokio::task::spawn_blocking(move || -> Result<LabelMapping, Error> {
+            let mut connection = copy_connection_pool.get()?;
+            let features = load_associated_features(&mut connection, dataset_id)?;
+            Ok(LabelMapping::from_classnames(
+                features.into_iter().filter_map(|f| f.classname),
+            ))
+        })
+        .await
+        .unwrap()?;
+        let label_mapping = Arc::new(label_mapping);
+
+        let mut skipped_frame_ids: HashSet<String> = HashSet::new();
+        let mut number_of_scans: usize = 0;
+        for step in 0..number_of_steps {
+            info!("Exporting range images: {}/{}", step, number_of_steps);
+            let step_start_time = start_date_time + step_duration * step;
+            let step_stop_time = stop_date_time.min(step_start_time + step_duration);
+            let mut point_cloud = tokio::task::block_in_place(|| {
+                rosbag.get_point_clouds(&Some(step_start_time), &Some(step_stop_time))
+            })?;
+            remove_labelled_points(&mut point_cloud)?;
+            if point_cloud.size() == 0 {
+                continue;
+            }
+
+            let ros_message_id_range = get_ros_message_id_range(&point_cloud)?;
+            let copy_connection_pool = self.copy_connection_pool.clone();
+            let label_mapping = label_mapping.clone();
+            let labels = tokio::task::spawn_blocking(move || {
+                let mut connection = copy_connection_pool.get()?;
+                load_point_labels(
+                    &mut connection,
+                    dataset_id,
+                    ros_message_id_range,
+                    &label_mapping,
+                )
+            })
+            .await
+            .unwrap()?;
+            add_label_columns(&mut point_cloud, &labels)?;
+            point_cloud.derive_spherical_points()?;
+
+            for frame_id in point_cloud.get_distinct_frame_ids() {
+                let Some(profile) = sensor_profiles.get_frame_profile(&frame_id) else {
+                    if skipped_frame_ids.insert(frame_id.to_string()) {
+                        warn!("Skipping frame {frame_id} without assigned sensor profile");
+                    }
+                    continue;
+                };
+                let lidar_point_cloud = point_cloud.filter_by_frame_id(&frame_id)?;
+                let lidar_directory_path =
+                    directory_path.join(frame_id.to_string().replace('/', "_"));
+                number_of_scans += tokio::task::block_in_place(|| {
+                    write_range_images(
+                        &lidar_point_cloud,
+                        &profile.rasterization_transform(),
+                        &lidar_directory_path,
+                        formats,
+                    )
+                })?;
+            }
+        }
+
+        info!("Finished exporting {number_of_scans} scans");
+        Ok(number_of_scans)
+    }
+
+    /// Derives statistics on the uploaded and associated sensor data of a single or all datasets.
+    ///
+    /// The surface distances of the point-model association are aggregated into a histogram with
+    /// bins of `distance_bin_width` meters.
+    pub async fn get_statistics(
+        &self,
+        dataset: Option<&str>,
+        distance_bin_width: f64,
+    ) -> Result<Vec<DatasetStatistics>, Error> {
+        if distance_bin_width.is_nan() || distance_bin_width <= 0.0 {
+            return Err(Error::InvalidDistanceBinWidth(distance_bin_width));
+        }
+        let mut connection = self.connection_pool.get().await.unwrap();
+
+        let mut datasets: Vec<Dataset> = self.get_datasets().await?;
+        if let Some(dataset) = dataset {
+            datasets.retain(|d| d.name == dataset);
+            if datasets.is_empty() {
+                return Err(Error::DatasetNotFound(dataset.to_string()));
+            }
+        }
+
+        let mut statistics: Vec<DatasetStatistics> = Vec::with_capacity(datasets.len());
+        for current_dataset in datasets {
+            let dataset_id = current_dataset.id;
+            info!("Derive statistics of dataset {}", current_dataset.name);
+
+            let number_of_patches: i64 =
+                schema::sensor_data::point_cloud_upload::dsl::point_cloud_upload
+                    .filter(schema::sensor_data::point_cloud_upload::dataset_id.eq(dataset_id))
+                    .count()
+                    .get_result(&mut connection)
+                    .await?;
+            let number_of_beams: i64 = schema::sensor_data::beam::dsl::beam
+                .filter(schema::sensor_data::beam::dataset_id.eq(dataset_id))
+                .count()
+                .get_result(&mut connection)
+                .await?;
+
+            let number_of_associated_beams: i64 = diesel::sql_query(format!(
+                "SELECT count(DISTINCT beam_id) AS count
+FROM sensor_data.association_point_model
+WHERE dataset_id = {dataset_id};"
+            ))
+            .get_result::<CountEntry>(&mut connection)
+            .await?
+            .count;
+
+            let beam_intersection_count: BeamIntersectionCountEntry =
+                diesel::sql_query(format!(
+                    "SELECT count(*) AS number_of_intersections, count(DISTINCT beam_id) AS number_of_beams
+FROM sensor_data.association_beam_model
+WHERE dataset_id = {dataset_id};"
+                ))
+                .get_result(&mut connection)
+                .await?;
+
+            let pass_through_count: BeamIntersectionCountEntry = diesel::sql_query(format!(
+                "SELECT count(*) AS number_of_intersections, count(DISTINCT beam_id) AS number_of_beams
+FROM sensor_data.pass_through_conflict
+WHERE dataset_id = {dataset_id};"
+            ))
+            .get_result(&mut connection)
+            .await?;
+
+            let temporal_coverage: TemporalCoverageEntry = diesel::sql_query(format!(
+                "SELECT
+    min(timestamp_sec::BIGINT * 1000000000 + timestamp_nanosec) AS start_timestamp,
+    max(timestamp_sec::BIGINT * 1000000000 + timestamp_nanosec) AS stop_timestamp
+FROM sensor_data.beam
+WHERE dataset_id = {dataset_id};"
+            ))
+            .get_result(&mut connection)
+            .await?;
+
+            let surface_condition = dataset_surface_condition("fgd", dataset_id);
+            let surface_area: SurfaceAreaEntry = diesel::sql_query(format!(
+                "SELECT
+    (SELECT sum(cell_size * cell_size)
+        FROM sensor_data.surface_coverage_cell
+        WHERE dataset_id = {dataset_id} AND number_of_points > 0) AS observed_surface_area,
+    (SELECT sum(fgd.area)
+        FROM sensor_data.feature_geometry_data as fgd,
+            (SELECT ST_SetSRID(ST_Extent(reflection)::geometry, min(ST_SRID(reflection))) AS extent
+            FROM sensor_data.beam
+            WHERE dataset_id = {dataset_id}) as dataset_extent
+        WHERE {surface_condition} AND fgd.valid_geometry && dataset_extent.extent) AS total_surface_area;"
+            ))
+            .get_result(&mut connection)
+            .await?;
+
+            let class_point_counts: Vec<ClassPointCountEntry> = diesel::sql_query(format!(
+                "SELECT oc.classname AS classname, count(DISTINCT apm.feature_id) AS number_of_features, count(DISTINCT apm.beam_id) AS number_of_points
+FROM sensor_data.association_point_model as apm
+JOIN citydb.feature as f ON apm.feature_id = f.id
+LEFT JOIN citydb.objectclass as oc ON f.objectclass_id = oc.id
+WHERE apm.dataset_id = {dataset_id}
+GROUP BY oc.classname
+ORDER BY number_of_points DESC;"
+            ))
+            .load(&mut connection)
+            .await?;
+
+            let feature_point_counts: Vec<FeaturePointCountEntry> = diesel::sql_query(format!(
+                "SELECT f.objectid AS gml_id, oc.classname AS classname, count(DISTINCT apm.beam_id) AS number_of_points
+FROM sensor_data.association_point_model as apm
+JOIN citydb.feature as f ON apm.feature_id = f.id
+LEFT JOIN citydb.objectclass as oc ON f.objectclass_id = oc.id
+WHERE apm.dataset_id = {dataset_id}
+GROUP BY f.objectid, oc.classname
+ORDER BY number_of_points DESC;"
+            ))
+            .load(&mut connection)
+            .await?;
+
+            let distance_bins: Vec<DistanceBinEntry> = diesel::sql_query(format!(
+                "SELECT floor(distance / {distance_bin_width})::INTEGER AS bin, count(*) AS number_of_points
+FROM sensor_data.association_point_model
+WHERE dataset_id = {dataset_id}
+GROUP BY bin
+ORDER BY bin;"
+            ))
+            .load(&mut connection)
+            .await?;
+
+            statistics.push(DatasetStatistics {
+                dataset: current_dataset.name,
+                number_of_patches,
+                number_of_beams,
+                number_of_associated_beams,
+                association_ratio: if number_of_beams == 0 {
+                    0.0
+                } else {
+                    number_of_associated_beams as f64 / number_of_beams as f64
+                },
+                number_of_beam_intersections: beam_intersection_count.number_of_intersections,
+                number_of_intersected_beams: beam_intersection_count.number_of_beams,
+                start_date_time: temporal_coverage
+                    .start_timestamp
+                    .map(DateTime::from_timestamp_nanos),
+                stop_date_time: temporal_coverage
+                    .stop_timestamp
+                    .map(DateTime::from_timestamp_nanos),
+                observed_surface_area: surface_area.observed_surface_area.unwrap_or_default(),
+                total_surface_area: surface_area.total_surface_area.unwrap_or_default(),
+                number_of_pass_through_conflicts: pass_through_count.number_of_intersections,
+                number_of_pass_through_beams: pass_through_count.number_of_beams,
+                classes: class_point_counts
+                    .into_iter()
+                    .map(|c| ClassStatistics {
+                        classname: c.classname.unwrap_or_default(),
+                        number_of_features: c.number_of_features,
+                        number_of_points: c.number_of_points,
+                    })
+                    .collect(),
+                features: feature_point_counts
+                    .into_iter()
+                    .map(|f| FeatureStatistics {
+                        gml_id: f.gml_id.unwrap_or_default(),
+                        classname: f.classname.unwrap_or_default(),
+                        number_of_points: f.number_of_points,
+                    })
+                    .collect(),
+                distance_histogram: DatasetStatistics::derive_distance_histogram(
+                    distance_bin_width,
+                    &distance_bins
+                        .into_iter()
+                        .map(|b| (b.bin, b.number_of_points))
+                        .collect::<Vec<_>>(),
+                ),
+            });
+        }
+
+        Ok(statistics)
+    }
+
+    /// Uploads the point clouds of a rosbag in steps of `step_duration`.
+    ///
+    /// The steps pass through a bounded pipeline of extraction, georeferencing and upload, where at
+    /// most `maximum_buffered_steps` point clouds are held in memory at the same time. Requires a
+    /// multi-threaded runtime, since the rosbag is read in place.
+    ///
+    /// The upload is tracked as job of the dataset identified by the rosbag path, time window and
+    /// step duration. With `resume`, steps already completed by a previous run of the same job are
+    /// skipped. Otherwise, the previously uploaded patches of the job are replaced.
+    ///
+    /// The patches are uploaded with the given point cloud format, which must have been
+    /// registered, see [DatabaseManager::register_point_cloud_format].
+    #[allow(clippy::too_many_arguments)]
     pub async fn upload_rosbag(
         &self,
+        dataset: &str,
+        point_cloud_format: PointCloudFormat,
         rosbag: erosbag::Rosbag,
+        rosbag_directory_path: impl AsRef<Path>,
         reference_frames: ecoord::ReferenceFrames,
         step_duration: ChronoDuration,
         start_date_time: DateTime<Utc>,
         stop_date_time: DateTime<Utc>,
         artefact_directory_path: Option<PathBuf>,
+        upload_method: UploadMethod,
+        maximum_buffered_steps: usize,
+        resume: bool,
     ) -> Result<(), Error> {
         let total_duration = stop_date_time - start_date_time;
-        //let stop_time: DateTime<Utc> = rosbag.get_stop_date_time()?.unwrap();
-        let total_steps: i32 =
+        let number_of_steps: i32 =
             (total_duration.num_milliseconds() / step_duration.num_milliseconds()) as i32;
         info!(
             "rosbag duration: {} ({} - {})",
             total_duration, start_date_time, stop_date_time
         );
-        //rayon::ThreadPoolBuilder::new().num_threads(4).build_global().unwrap();
-        let number_of_steps = total_steps;
-
-        // check: https://docs.rs/diesel-async/0.2.0/diesel_async/
-        // into_par_iter()
-        let point_clouds: Vec<PointCloud> = (0..number_of_steps)
-            .map(|step| {
-                info!("Extracting point clouds: {}/{}", step, number_of_steps);
-                let step_start_time = start_date_time + step_duration * step;
-                let step_stop_time = step_start_time + step_duration;
-
-                rosbag
-                    .get_point_clouds(&Some(step_start_time), &Some(step_stop_time))
-                    .unwrap()
-            })
-            .collect();
+
+        let dataset_id = self.create_dataset(dataset).await?;
+        self.assign_point_cloud_format(dataset, dataset_id, point_cloud_format)
+            .await?;
+        info!("Uploading into dataset {dataset} (id: {dataset_id})");
+        let upload_job_id = self
+            .register_upload_job(
+                dataset_id,
+                rosbag_directory_path,
+                start_date_time,
+                stop_date_time,
+                step_duration,
+                number_of_steps,
+            )
+            .await?;
+        let completed_steps: HashSet<i32> = if resume {
+            self.get_completed_upload_job_steps(upload_job_id).await?
+        } else {
+            self.discard_upload_job_steps(upload_job_id).await?;
+            HashSet::new()
+        };
+        info!(
+            "Upload job {upload_job_id}: {} of {number_of_steps} steps already completed",
+            completed_steps.len()
+        );
 
         if let Some(artefact_directory_path) = &artefact_directory_path {
-            if artefact_directory_path.exists() {
-                fs::remove_dir_all(&artefact_directory_path)?;
+            if !resume && artefact_directory_path.exists() {
+                fs::remove_dir_all(artefact_directory_path)?;
             }
-            create_dir_all(&artefact_directory_path)?;
+            create_dir_all(artefact_directory_path)?;
         }
 
         let database_point_cloud_base_path: Option<PathBuf> = artefact_directory_path
@@ -155,88 +935,78 @@ impl DatabaseManager {
             create_dir_all(p)?;
         }
 
-        info!("Georeferencing point clouds");
-        let georeferenced_point_clouds: Vec<PointCloud> = point_clouds
-            .into_par_iter()
-            .enumerate()
-            .map(|(step, mut point_cloud)| {
-                // let p = point_cloud.reference_frames().
-                // let stop_time: DateTime<Utc> = Utc.timestamp_opt(1579007201, 173003500).unwrap();
-                //let c = point_cloud.reference_frames().get_channel_ids();
-                //dbg!("c: {}", c);
-
-                // point_cloud.resolve_to_frame("slam_map".into()).unwrap();
-
-                let merged_reference_frames = ecoord::merge(&[
-                    point_cloud.reference_frames().clone(),
-                    reference_frames.clone(),
-                ])
-                .unwrap();
-
-                point_cloud.set_reference_frames(merged_reference_frames);
-                point_cloud
-                    .point_data
-                    .add_sequential_id()
-                    .expect("should work");
-                point_cloud
-                    .resolve_to_frame("world".into())
-                    .expect("resolving should work");
-
-                if let Some(database_point_cloud_base_path) = database_point_cloud_base_path.clone()
-                {
-                    let p =
-                        database_point_cloud_base_path.join(PathBuf::from(format!("{step}.tar")));
-                    EpointWriter::from_path(p)
-                        .unwrap()
-                        .with_compressed(false)
-                        .finish(point_cloud.clone())
-                        .expect("Writing should work");
-                }
-
-                if let Some(database_point_cloud_xyz_base_path) =
-                    database_point_cloud_xyz_base_path.clone()
-                {
-                    let downsampled_point_cloud =
-                        deterministic_downsample(&point_cloud, 100000, Some(123)).unwrap();
-
-                    let p = database_point_cloud_xyz_base_path
-                        .join(PathBuf::from(format!("{step}.xyz")));
-                    XyzWriter::new(p)
-                        //.with_frame_id("slam_map".into())
-                        .finish(&downsampled_point_cloud)
-                        .expect("Writing should work");
-                }
-
-                point_cloud
-            })
-            .collect();
-
-        info!("Start uploading");
-        let mut handles: Vec<JoinHandle<()>> = vec![];
-
-        for current_point_cloud in georeferenced_point_clouds {
-            let connection = self.connection_pool.get().await.unwrap();
+        info!(
+            "Start uploading with method {:?} and at most {} buffered steps",
+            upload_method, maximum_buffered_steps
+        );
+        let reference_frames = Arc::new(reference_frames);
+        let buffered_steps = Arc::new(Semaphore::new(maximum_buffered_steps.max(1)));
+        let mut handles: JoinSet<Result<(), Error>> = JoinSet::new();
+
+        for step in (0..number_of_steps).filter(|s| !completed_steps.contains(s)) {
+            // backpressure: only extract the next step once a buffered step has been uploaded
+            let permit = buffered_steps.clone().acquire_owned().await.unwrap();
+            while let Some(result) = handles.try_join_next() {
+                result.unwrap()?;
+            }
 
-            let current_handle = tokio::spawn(async move {
-                upload_point_cloud_direct(connection, &current_point_cloud)
-                    .await
-                    .unwrap();
+            info!("Extracting point clouds: {}/{}", step, number_of_steps);
+            let step_start_time = start_date_time + step_duration * step;
+            let step_stop_time = step_start_time + step_duration;
+            let point_cloud = tokio::task::block_in_place(|| {
+                rosbag.get_point_clouds(&Some(step_start_time), &Some(step_stop_time))
+            })?;
+
+            let database_manager = self.clone();
+            let reference_frames = reference_frames.clone();
+            let database_point_cloud_base_path = database_point_cloud_base_path.clone();
+            let database_point_cloud_xyz_base_path = database_point_cloud_xyz_base_path.clone();
+            handles.spawn(async move {
+                let georeferenced_point_cloud = tokio::task::spawn_blocking(move || {
+                    georeference_point_cloud(
+                        step,
+                        point_cloud,
+                        &reference_frames,
+                        database_point_cloud_base_path,
+                        database_point_cloud_xyz_base_path,
+                    )
+                })
+                .await
+                .unwrap()?;
+
+                let upload_job_step = UploadJobStep {
+                    upload_job_id,
+                    step,
+                };
+                database_manager
+                    .upload_single_point_cloud(
+                        dataset_id,
+                        georeferenced_point_cloud,
+                        upload_method,
+                        point_cloud_format,
+                        Some(upload_job_step),
+                    )
+                    .await?;
+                drop(permit);
+                Ok(())
             });
-            handles.push(current_handle);
         }
 
-        for current_handle in handles {
-            current_handle.await.unwrap();
+        while let Some(result) = handles.join_next().await {
+            result.unwrap()?;
         }
 
-        /*point_clouds.iter().for_each(|c| {
-            self.upload_point_cloud(c).await?;
-        });*/
         info!("Finished uploading");
         Ok(())
     }
 
-    pub async fn upload_point_cloud(&self, mut point_cloud: PointCloud) -> Result<(), Error> {
+    pub async fn upload_point_cloud(
+        &self,
+        dataset: &str,
+        point_cloud_format: PointCloudFormat,
+        mut point_cloud: PointCloud,
+        upload_method: UploadMethod,
+    ) -> Result<(), Error> {
         point_cloud
             .point_data
             .add_sequential_id()
@@ -283,6 +1053,9 @@ impl DatabaseManager {
             (id_max - id_min) / step_size as u64
         );
 
+        let dataset_id = self.create_dataset(dataset).await?;
+        self.assign_point_cloud_format(dataset, dataset_id, point_cloud_format)
+            .await?;
         let mut handles: Vec<JoinHandle<()>> = vec![];
 
         for current_id in (id_min..id_max).step_by(step_size) {
@@ -290,16 +1063,22 @@ impl DatabaseManager {
             let current_point_cloud =
                 point_cloud.filter_by_id_range(Some(current_id), Some(current_id_max))?;
 
-            let connection: Object<AsyncPgConnection> = self.connection_pool.get().await.unwrap();
-
             info!(
                 "Uploading point cloud with {} points in the ID range: {}-{}",
                 current_point_cloud.size(),
                 current_id,
                 current_id_max
             );
+            let database_manager = self.clone();
             let current_handle = tokio::spawn(async move {
-                upload_point_cloud_direct(connection, &current_point_cloud)
+                database_manager
+                    .upload_single_point_cloud(
+                        dataset_id,
+                        current_point_cloud,
+                        upload_method,
+                        point_cloud_format,
+                        None,
+                    )
                     .await
                     .unwrap();
             });
@@ -314,41 +1093,426 @@ impl DatabaseManager {
         Ok(())
     }
 
+    /// Uploads a single point cloud according to the upload method.
+    ///
+    /// If the point cloud belongs to a step of an upload job, the step is marked as completed
+    /// within the same transaction.
+    async fn upload_single_point_cloud(
+        &self,
+        dataset_id: i32,
+        point_cloud: PointCloud,
+        upload_method: UploadMethod,
+        point_cloud_format: PointCloudFormat,
+        upload_job_step: Option<UploadJobStep>,
+    ) -> Result<(), Error> {
+        match upload_method {
+            UploadMethod::Insert => {
+                let connection: Object<AsyncPgConnection> =
+                    self.connection_pool.get().await.unwrap();
+                upload_point_cloud_direct(
+                    connection,
+                    dataset_id,
+                    point_cloud_format.pcid,
+                    &point_cloud,
+                    upload_job_step,
+                )
+                .await?;
+            }
+            UploadMethod::CopyPatch(format) => {
+                let current_patch_id = self.reserve_patch_id().await?;
+                let copy_connection_pool = self.copy_connection_pool.clone();
+                tokio::task::spawn_blocking(move || -> Result<(), Error> {
+                    let mut connection = copy_connection_pool.get()?;
+                    connection.transaction::<_, Error, _>(|connection| {
+                        copy_point_cloud_patch(
+                            connection,
+                            dataset_id,
+                            current_patch_id,
+                            point_cloud_format.pcid,
+                            &point_cloud,
+                            format,
+                        )?;
+                        if let Some(upload_job_step) = &upload_job_step {
+                            insert_upload_job_step(connection, upload_job_step, current_patch_id)?;
+                        }
+                        Ok(())
+                    })?;
+                    info!("Uploaded number of points: {}", point_cloud.size());
+                    Ok(())
+                })
+                .await
+                .unwrap()?;
+            }
+            UploadMethod::CopyBeam(format) => {
+                let current_patch_id = self.reserve_patch_id().await?;
+                let copy_connection_pool = self.copy_connection_pool.clone();
+                tokio::task::spawn_blocking(move || -> Result<(), Error> {
+                    let mut connection = copy_connection_pool.get()?;
+                    connection.transaction::<_, Error, _>(|connection| {
+                        copy_point_cloud_beams(
+                            connection,
+                            dataset_id,
+                            current_patch_id,
+                            point_cloud_format.srid,
+                            &point_cloud,
+                            format,
+                        )?;
+                        if let Some(upload_job_step) = &upload_job_step {
+                            insert_upload_job_step(connection, upload_job_step, current_patch_id)?;
+                        }
+                        Ok(())
+                    })?;
+                    info!("Uploaded number of beams: {}", point_cloud.size());
+                    Ok(())
+                })
+                .await
+                .unwrap()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Registers an upload job or returns the id of the already registered one.
+    async fn register_upload_job(
+        &self,
+        dataset_id: i32,
+        rosbag_directory_path: impl AsRef<Path>,
+        start_date_time: DateTime<Utc>,
+        stop_date_time: DateTime<Utc>,
+        step_duration: ChronoDuration,
+        number_of_steps: i32,
+    ) -> Result<i32, Error> {
+        let mut connection = self.connection_pool.get().await.unwrap();
+
+        let entry: UploadJobEntry = diesel::sql_query(
+            "INSERT INTO sensor_data.upload_job (dataset_id, rosbag_path, start_date_time, stop_date_time, step_duration_ms, number_of_steps)
+VALUES ($1, $2, $3, $4, $5, $6)
+ON CONFLICT (dataset_id, rosbag_path, start_date_time, stop_date_time, step_duration_ms)
+DO UPDATE SET number_of_steps = EXCLUDED.number_of_steps
+RETURNING id;",
+        )
+        .bind::<Integer, _>(dataset_id)
+        .bind::<Varchar, _>(rosbag_directory_path.as_ref().to_string_lossy().to_string())
+        .bind::<Timestamptz, _>(start_date_time)
+        .bind::<Timestamptz, _>(stop_date_time)
+        .bind::<BigInt, _>(step_duration.num_milliseconds())
+        .bind::<Integer, _>(number_of_steps)
+        .get_result(&mut connection)
+        .await?;
+        Ok(entry.id)
+    }
+
+    async fn get_completed_upload_job_steps(
+        &self,
+        upload_job_id: i32,
+    ) -> Result<HashSet<i32>, Error> {
+        let mut connection = self.connection_pool.get().await.unwrap();
+
+        let steps: Vec<i32> = schema::sensor_data::upload_job_step::dsl::upload_job_step
+            .filter(schema::sensor_data::upload_job_step::upload_job_id.eq(upload_job_id))
+            .select(schema::sensor_data::upload_job_step::step)
+            .load(&mut connection)
+            .await?;
+        Ok(steps.into_iter().collect())
+    }
+
+    /// Deletes the steps of an upload job together with their patches, beams and the
+    /// associations, conflicts and downloaded points derived from them.
+    async fn discard_upload_job_steps(&self, upload_job_id: i32) -> Result<(), Error> {
+        info!("Deleting previously uploaded steps of upload job {upload_job_id}");
+        let copy_connection_pool = self.copy_connection_pool.clone();
+        tokio::task::spawn_blocking(move || -> Result<(), Error> {
+            let mut connection = copy_connection_pool.get()?;
+            connection.transaction::<_, Error, _>(|connection| {
+                delete_upload_job_steps(connection, upload_job_id)
+            })
+        })
+        .await
+        .unwrap()?;
+
+        Ok(())
+    }
+
+    /// Returns the spatial reference system of the 3DCityDB.
+    pub async fn get_city_model_srid(&self) -> Result<i32, Error> {
+        let mut connection = self.connection_pool.get().await.unwrap();
+
+        let entry: SridEntry = diesel::sql_query("SELECT srid FROM citydb.database_srs LIMIT 1;")
+            .get_result(&mut connection)
+            .await?;
+        Ok(entry.srid)
+    }
+
+    /// Registers the point cloud format of the patches in `pointcloud_formats`, if missing.
+    ///
+    /// Without `srid`, the spatial reference system of the 3DCityDB is used. Without `pcid`, an
+    /// already registered format with the same srid and point schema is reused, or a new one is
+    /// registered with the next free pcid. An already registered `pcid` must have the same srid
+    /// and the dimensions of [POINT_CLOUD_SCHEMA].
+    pub async fn register_point_cloud_format(
+        &self,
+        pcid: Option<u32>,
+        srid: Option<i32>,
+    ) -> Result<PointCloudFormat, Error> {
+        let srid = match srid {
+            Some(srid) => srid,
+            None => self.get_city_model_srid().await?,
+        };
+        let mut connection = self.connection_pool.get().await.unwrap();
+
+        let pcid: u32 = match pcid {
+            Some(pcid) => {
+                let registered_formats: Vec<PointCloudFormatEntry> = diesel::sql_query(
+                    "SELECT srid, schema FROM pointcloud_formats WHERE pcid = $1;",
+                )
+                .bind::<Integer, _>(pcid as i32)
+                .load(&mut connection)
+                .await?;
+                match registered_formats.into_iter().next() {
+                    Some(entry) if entry.srid != srid => {
+                        return Err(Error::PointCloudFormatSridMismatch {
+                            pcid,
+                            registered_srid: entry.srid,
+                            srid,
+                        });
+                    }
+                    Some(entry) if !is_compatible_schema(&entry.schema) => {
+                        return Err(Error::PointCloudFormatSchemaMismatch(pcid));
+                    }
+                    Some(_) => pcid,
+                    None => {
+                        info!("Registering point cloud format with pcid {pcid} and srid {srid}");
+                        diesel::sql_query(
+                            "INSERT INTO pointcloud_formats (pcid, srid, schema) VALUES ($1, $2, $3);",
+                        )
+                        .bind::<Integer, _>(pcid as i32)
+                        .bind::<Integer, _>(srid)
+                        .bind::<Text, _>(POINT_CLOUD_SCHEMA)
+                        .execute(&mut connection)
+                        .await?;
+                        pcid
+                    }
+                }
+            }
+            None => {
+                let registered_pcids: Vec<PointCloudFormatIdEntry> = diesel::sql_query(
+                    "SELECT pcid FROM pointcloud_formats WHERE srid = $1 AND schema = $2 ORDER BY pcid LIMIT 1;",
+                )
+                .bind::<Integer, _>(srid)
+                .bind::<Text, _>(POINT_CLOUD_SCHEMA)
+                .load(&mut connection)
+                .await?;
+                match registered_pcids.into_iter().next() {
+                    Some(entry) => entry.pcid as u32,
+                    None => {
+                        let entry: PointCloudFormatIdEntry = diesel::sql_query(
+                            "INSERT INTO pointcloud_formats (pcid, srid, schema)
+VALUES ((SELECT COALESCE(MAX(pcid), 0) + 1 FROM pointcloud_formats), $1, $2)
+RETURNING pcid;",
+                        )
+                        .bind::<Integer, _>(srid)
+                        .bind::<Text, _>(POINT_CLOUD_SCHEMA)
+                        .get_result(&mut connection)
+                        .await?;
+                        info!(
+                            "Registered point cloud format with pcid {} and srid {srid}",
+                            entry.pcid
+                        );
+                        entry.pcid as u32
+                    }
+                }
+            }
+        };
+
+        info!("Using point cloud format with pcid {pcid} and srid {srid}");
+        Ok(PointCloudFormat::new(pcid, srid))
+    }
+
+    /// Assigns the point cloud format to a dataset on its first upload and ensures that
+    /// subsequent uploads use the same format.
+    async fn assign_point_cloud_format(
+        &self,
+        dataset: &str,
+        dataset_id: i32,
+        point_cloud_format: PointCloudFormat,
+    ) -> Result<(), Error> {
+        let mut connection = self.connection_pool.get().await.unwrap();
+
+        diesel::update(
+            schema::sensor_data::dataset::dsl::dataset
+                .filter(schema::sensor_data::dataset::id.eq(dataset_id))
+                .filter(schema::sensor_data::dataset::pcid.is_null()),
+        )
+        .set((
+            schema::sensor_data::dataset::pcid.eq(point_cloud_format.pcid as i32),
+            schema::sensor_data::dataset::srid.eq(point_cloud_format.srid),
+        ))
+        .execute(&mut connection)
+        .await?;
+
+        let current_dataset: Dataset = schema::sensor_data::dataset::dsl::dataset
+            .find(dataset_id)
+            .first::<Dataset>(&mut connection)
+            .await?;
+        let registered = PointCloudFormat::new(
+            current_dataset.pcid.unwrap_or_default() as u32,
+            current_dataset.srid.unwrap_or_default(),
+        );
+        if registered != point_cloud_format {
+            return Err(Error::DatasetPointCloudFormatMismatch {
+                dataset: dataset.to_string(),
+                registered,
+                requested: point_cloud_format,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Ensures that the point cloud of a dataset and the city model share the same spatial
+    /// reference system.
+    async fn verify_srid(&self, dataset_id: i32) -> Result<(), Error> {
+        let mut connection = self.connection_pool.get().await.unwrap();
+
+        let current_dataset: Dataset = schema::sensor_data::dataset::dsl::dataset
+            .find(dataset_id)
+            .first::<Dataset>(&mut connection)
+            .await?;
+        let Some(point_cloud_srid) = current_dataset.srid else {
+            // nothing uploaded yet
+            return Ok(());
+        };
+
+        let city_model_srid = self.get_city_model_srid().await?;
+        if city_model_srid != point_cloud_srid {
+            return Err(Error::SridMismatch {
+                city_model_srid,
+                point_cloud_srid,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Explodes the surfaces of the geometry selection into `feature_geometry_data`, if not
+    /// already done for an equal selection, and records the selection of the dataset.
+    ///
+    /// Returns the id of the selection.
+    async fn assign_geometry_selection(
+        &self,
+        dataset_id: i32,
+        geometry_selection: &GeometrySelection,
+    ) -> Result<i32, Error> {
+        let geometry_selection = geometry_selection.clone();
+        let copy_connection_pool = self.copy_connection_pool.clone();
+        let geometry_selection_id = tokio::task::spawn_blocking(move || -> Result<i32, Error> {
+            let mut connection = copy_connection_pool.get()?;
+            connection.transaction::<_, Error, _>(|connection| {
+                explode_feature_geometry_data(connection, &geometry_selection)
+            })
+        })
+        .await
+        .unwrap()?;
+        info!("Using feature geometry data of geometry selection {geometry_selection_id}");
+
+        let mut connection = self.connection_pool.get().await.unwrap();
+        diesel::update(
+            schema::sensor_data::dataset::dsl::dataset
+                .filter(schema::sensor_data::dataset::id.eq(dataset_id)),
+        )
+        .set(schema::sensor_data::dataset::geometry_selection_id.eq(geometry_selection_id))
+        .execute(&mut connection)
+        .await?;
+
+        Ok(geometry_selection_id)
+    }
+
+    /// Reserves a patch id for beams that are uploaded without a patch.
+    async fn reserve_patch_id(&self) -> Result<i32, Error> {
+        let mut connection = self.connection_pool.get().await.unwrap();
+
+        let entry: PatchIdEntry = diesel::sql_query(
+            "SELECT nextval(pg_get_serial_sequence('sensor_data.point_cloud_upload', 'id'))::integer AS id;",
+        )
+        .get_result(&mut connection)
+        .await?;
+        Ok(entry.id)
+    }
+
+    /// Associates the beams of a dataset with the city model.
+    ///
+    /// The distance thresholds can differ by the class of the features, and the association mode
+    /// selects whether a point is associated with every feature within its threshold or only with
+    /// the nearest one. With `pass_through_tolerance`, the beams are additionally intersected with
+    /// the surfaces up to this distance in meters before their reflection, and each surface a beam
+    /// passes through is recorded as conflict in `pass_through_conflict`.
+    ///
+    /// Only the geometries of the selected LoDs and geometry properties participate in the
+    /// association and beam intersection, and the LoD of the geometry a point is associated with
+    /// is recorded in `association_point_model`.
+    #[allow(clippy::too_many_arguments)]
     pub async fn associate(
         &self,
-        distance_threshold: f32,
+        dataset: &str,
+        distance_thresholds: &DistanceThresholds,
+        geometry_selection: &GeometrySelection,
+        association_mode: AssociationMode,
         beam_intersection: bool,
+        pass_through_tolerance: Option<f32>,
         keep_temporary_table_entries: bool,
     ) -> Result<(), Error> {
-        self.clean_association_tables().await?;
+        if distance_thresholds.maximum().is_none() {
+            return Err(Error::MissingDistanceThreshold);
+        }
+        let dataset_id = self.get_dataset_id(dataset).await?;
+        self.verify_srid(dataset_id).await?;
+        self.clean_association_tables(dataset_id).await?;
 
         //let connection: Object<AsyncDieselConnectionManager<AsyncPgConnection>> =
         //   self.connection_pool.get().await.unwrap();
         //drop_association_index(connection).await?;
 
-        if beam_intersection {
-            info!("Explode feature geometry data");
-            let connection: Object<AsyncPgConnection> = self.connection_pool.get().await.unwrap();
-            explode_feature_geometry_data(connection).await?;
-        }
+        let geometry_selection_id = self
+            .assign_geometry_selection(dataset_id, geometry_selection)
+            .await?;
 
         let mut connection: Object<AsyncPgConnection> = self.connection_pool.get().await.unwrap();
         let patch_ids: Vec<i32> = schema::sensor_data::point_cloud_upload::dsl::point_cloud_upload
+            .filter(schema::sensor_data::point_cloud_upload::dataset_id.eq(dataset_id))
             .select(schema::sensor_data::point_cloud_upload::id)
             .load(&mut connection)
             .await?;
+        // remaining beams were uploaded directly without a patch
+        let beam_patch_ids: Vec<i32> = schema::sensor_data::beam::dsl::beam
+            .filter(schema::sensor_data::beam::dataset_id.eq(dataset_id))
+            .select(patch_id)
+            .distinct()
+            .load(&mut connection)
+            .await?;
         //dbg!("{}", id);
 
         let mut handles: Vec<JoinHandle<()>> = vec![];
-        for current_patch_id in patch_ids.into_iter() {
+        for (current_patch_id, explode_patch) in patch_ids
+            .into_iter()
+            .map(|i| (i, true))
+            .chain(beam_patch_ids.into_iter().map(|i| (i, false)))
+        {
             let connection: Object<AsyncPgConnection> = self.connection_pool.get().await.unwrap();
+            let distance_thresholds = distance_thresholds.clone();
+            let geometry_selection = geometry_selection.clone();
 
             let current_handle = tokio::spawn(async move {
                 associate_points(
                     connection,
                     current_patch_id,
-                    distance_threshold,
+                    explode_patch,
+                    distance_thresholds,
+                    geometry_selection,
+                    geometry_selection_id,
+                    association_mode,
                     beam_intersection,
+                    pass_through_tolerance,
                     keep_temporary_table_entries,
                 )
                 .await
@@ -368,63 +1532,191 @@ impl DatabaseManager {
         Ok(())
     }
 
+    /// Downloads the associated point clouds of a dataset split into files by the chunking.
+    ///
+    /// Only the points matching the filter are downloaded and patches without any matching
+    /// point are skipped. The patches are downloaded concurrently, but at most as many as there
+    /// are connections are held in memory, while the chunks are appended to their files. Hence,
+    /// one file per chunk is kept open until all patches are written.
     pub async fn download(
         &self,
+        dataset: &str,
         directory_path: impl AsRef<Path>,
+        format: DownloadFormat,
+        chunking: DownloadChunking,
+        filter: &DownloadFilter,
         keep_temporary_table_entries: bool,
     ) -> Result<(), Error> {
-        self.clean_download_tables().await?;
+        if chunking != DownloadChunking::Patch && format == DownloadFormat::Epoint {
+            return Err(Error::UnsupportedStreamingFormat(format));
+        }
+        let current_dataset = self.get_dataset(dataset).await?;
+        let dataset_id = current_dataset.id;
+        self.clean_download_tables(dataset_id).await?;
+        let filter_conditions =
+            Arc::new(filter.sql_conditions(current_dataset.srid.unwrap_or_default()));
+        if !filter.is_empty() {
+            info!("Filter download with: {filter:?}");
+        }
+        info!("Split download into chunks by: {chunking:?}");
 
         let mut connection: Object<AsyncPgConnection> = self.connection_pool.get().await.unwrap();
-        let patch_ids: Vec<i32> = schema::sensor_data::point_cloud_upload::dsl::point_cloud_upload
-            .select(schema::sensor_data::point_cloud_upload::id)
+        let patch_ids: Vec<i32> = schema::sensor_data::beam::dsl::beam
+            .filter(schema::sensor_data::beam::dataset_id.eq(dataset_id))
+            .select(patch_id)
+            .distinct()
+            .order(patch_id)
             .load(&mut connection)
             .await?;
-
-        let mut handles: Vec<JoinHandle<()>> = vec![];
-        for current_patch_id in patch_ids.into_iter() {
-            let connection: Object<AsyncPgConnection> = self.connection_pool.get().await.unwrap();
-            let path = directory_path
-                .as_ref()
-                .to_owned()
-                .clone()
-                .join(current_patch_id.to_string() + ".xyz");
-
-            let current_handle = tokio::spawn(async move {
-                let point_cloud = download_associate_points(
-                    connection,
-                    current_patch_id,
-                    keep_temporary_table_entries,
-                )
-                .await
-                .unwrap();
-                let colorized_point_cloud =
-                    epoint::transform::colorize::colorize_by_column_hash(&point_cloud, "gml_id")
-                        .unwrap();
-                XyzWriter::new(path)
-                    //.with_compressed(false)
-                    .finish(&colorized_point_cloud)
-                    .unwrap();
-            });
-            handles.push(current_handle);
+        // common LAS coordinate transforms for all chunks
+        let extent_entry: CoordinateExtentEntry = diesel::sql_query(format!(
+            "SELECT
+    ST_XMin(e) AS x_min, ST_YMin(e) AS y_min, ST_ZMin(e) AS z_min,
+    ST_XMax(e) AS x_max, ST_YMax(e) AS y_max, ST_ZMax(e) AS z_max
+FROM (SELECT ST_3DExtent(reflection) AS e FROM sensor_data.beam WHERE dataset_id = {dataset_id}) as t;"
+        ))
+        .get_result(&mut connection)
+        .await?;
+        let extent = CoordinateExtent::new(
+            Point3::new(
+                extent_entry.x_min.unwrap_or_default(),
+                extent_entry.y_min.unwrap_or_default(),
+                extent_entry.z_min.unwrap_or_default(),
+            ),
+            Point3::new(
+                extent_entry.x_max.unwrap_or_default(),
+                extent_entry.y_max.unwrap_or_default(),
+                extent_entry.z_max.unwrap_or_default(),
+            ),
+        );
+        drop(connection);
+
+        let (sender, mut receiver) = mpsc::channel::<(
+            i32,
+            JoinHandle<Result<Option<PointCloud>, Error>>,
+        )>(self.connection_pool.status().max_size.max(1));
+        let connection_pool = self.connection_pool.clone();
+        let producer_handle = tokio::spawn(async move {
+            for current_patch_id in patch_ids.into_iter() {
+                let connection: Object<AsyncPgConnection> = connection_pool.get().await.unwrap();
+                let filter_conditions = filter_conditions.clone();
+
+                let current_handle = tokio::spawn(async move {
+                    download_associate_points(
+                        connection,
+                        current_patch_id,
+                        &filter_conditions,
+                        keep_temporary_table_entries,
+                    )
+                    .await
+                });
+                if sender
+                    .send((current_patch_id, current_handle))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        let mut sinks = ChunkSinks::new(format, &directory_path, extent);
+        while let Some((current_patch_id, current_handle)) = receiver.recv().await {
+            let Some(point_cloud) = current_handle.await.unwrap()? else {
+                continue;
+            };
+
+            if chunking == DownloadChunking::Patch {
+                let path = format.file_path(&directory_path, &current_patch_id.to_string());
+                write_point_cloud(point_cloud, format, path)?;
+                continue;
+            }
+            for (chunk_name, chunk_point_cloud) in
+                chunking.split_point_cloud(point_cloud, current_patch_id, dataset)?
+            {
+                sinks.write(&chunk_name, &chunk_point_cloud)?;
+            }
         }
+        producer_handle.await.unwrap();
 
-        for current_handle in handles {
-            current_handle.await.unwrap();
-        }
+        let number_of_files = sinks.finish()?;
+        info!("Finished {number_of_files} chunk files");
 
         Ok(())
     }
 }
 
+/// Resolves the point cloud of a step to the world frame and optionally writes artefacts.
+fn georeference_point_cloud(
+    step: i32,
+    mut point_cloud: PointCloud,
+    reference_frames: &ecoord::ReferenceFrames,
+    database_point_cloud_base_path: Option<PathBuf>,
+    database_point_cloud_xyz_base_path: Option<PathBuf>,
+) -> Result<PointCloud, Error> {
+    let merged_reference_frames = ecoord::merge(&[
+        point_cloud.reference_frames().clone(),
+        reference_frames.clone(),
+    ])
+    .unwrap();
+
+    point_cloud.set_reference_frames(merged_reference_frames);
+    point_cloud.point_data.add_sequential_id()?;
+    point_cloud.resolve_to_frame("world".into())?;
+
+    if let Some(database_point_cloud_base_path) = database_point_cloud_base_path {
+        let p = database_point_cloud_base_path.join(PathBuf::from(format!("{step}.tar")));
+        EpointWriter::from_path(p)?
+            .with_compressed(false)
+            .finish(point_cloud.clone())?;
+    }
+
+    if let Some(database_point_cloud_xyz_base_path) = database_point_cloud_xyz_base_path {
+        let downsampled_point_cloud = deterministic_downsample(&point_cloud, 100000, Some(123))?;
+
+        let p = database_point_cloud_xyz_base_path.join(PathBuf::from(format!("{step}.xyz")));
+        XyzWriter::new(p)
+            //.with_frame_id("slam_map".into())
+            .finish(&downsampled_point_cloud)?;
+    }
+
+    Ok(point_cloud)
+}
+
 async fn upload_point_cloud_direct(
     mut connection: Object<AsyncPgConnection>,
+    dataset_id: i32,
+    pcid: u32,
     point_cloud: &epoint::PointCloud,
+    upload_job_step: Option<UploadJobStep>,
 ) -> Result<(), Error> {
-    let queries = insert_point_cloud(point_cloud)?;
-    for query in queries {
-        // fs::write("./query.txt", &query).expect("Unable to write file");
-        diesel::sql_query(&query).execute(&mut connection).await?;
+    let patch = PatchUncompressedBinary::from_point_cloud(pcid, point_cloud)?;
+    match upload_job_step {
+        Some(upload_job_step) => {
+            // a single statement, so that the step is only marked as completed with its patch
+            diesel::sql_query(
+                "WITH patch AS (
+    INSERT INTO sensor_data.point_cloud_upload (dataset_id, pa) VALUES ($1, $2::pcpatch) RETURNING id
+)
+INSERT INTO sensor_data.upload_job_step (upload_job_id, step, patch_id)
+SELECT $3, $4, id FROM patch;",
+            )
+            .bind::<Integer, _>(dataset_id)
+            .bind::<Text, _>(patch.to_hex())
+            .bind::<Integer, _>(upload_job_step.upload_job_id)
+            .bind::<Integer, _>(upload_job_step.step)
+            .execute(&mut connection)
+            .await?;
+        }
+        None => {
+            diesel::sql_query(
+                "INSERT INTO sensor_data.point_cloud_upload (dataset_id, pa) VALUES ($1, $2::pcpatch);",
+            )
+            .bind::<Integer, _>(dataset_id)
+            .bind::<Text, _>(patch.to_hex())
+            .execute(&mut connection)
+            .await?;
+        }
     }
 
     info!("Uploaded number of points: {}", point_cloud.size());
@@ -452,17 +1744,91 @@ async fn drop_association_index(
     Ok(())
 }*/
 
+#[allow(clippy::too_many_arguments)]
 async fn associate_points(
     mut connection: Object<AsyncPgConnection>,
     current_patch_id: i32,
-    distance_threshold: f32,
+    explode_patch: bool,
+    distance_thresholds: DistanceThresholds,
+    geometry_selection: GeometrySelection,
+    geometry_selection_id: i32,
+    association_mode: AssociationMode,
     beam_intersection: bool,
+    pass_through_tolerance: Option<f32>,
     keep_temporary_table_entries: bool,
+) -> Result<(), Error> {
+    let reflection_line_length = distance_thresholds
+        .maximum()
+        .ok_or(Error::MissingDistanceThreshold)?
+        * 2.0;
+
+    if explode_patch {
+        explode_patch_to_beams(&mut connection, current_patch_id, reflection_line_length).await?;
+    } else {
+        derive_reflection_lines(&mut connection, current_patch_id, reflection_line_length).await?;
+    }
+
+    info!("Associating point-model with patch_id: {current_patch_id}");
+    let query = association_mode.point_association_query(
+        current_patch_id,
+        &distance_thresholds,
+        &geometry_selection,
+    )?;
+    diesel::sql_query(query).execute(&mut connection).await?;
+
+    //return Ok(());
+
+    if beam_intersection {
+        info!("Associating beam-model with patch_id: {current_patch_id}");
+        let query = format!(
+            "INSERT INTO sensor_data.association_beam_model (beam_id, feature_id, intersection, intersection_angle, dataset_id)
+SELECT DISTINCT b.id, g.feature_id, ST_3DIntersection(g.valid_geometry, b.reflection_line), sensor_data.incidence_angle(b.line, g.normal_x, g.normal_y, g.normal_z), b.dataset_id
+FROM
+    (SELECT *
+     FROM sensor_data.beam
+     WHERE patch_id = {current_patch_id}) as b
+JOIN
+        (SELECT *
+         FROM sensor_data.feature_geometry_data
+         WHERE valid_geometry IS NOT NULL AND geometry_selection_id = {geometry_selection_id}) as g
+ON ST_3DIntersects(g.valid_geometry, b.reflection_line);"
+        );
+        diesel::sql_query(query).execute(&mut connection).await?;
+    }
+
+    if let Some(pass_through_tolerance) = pass_through_tolerance {
+        info!("Detecting pass-through conflicts with patch_id: {current_patch_id}");
+        // the free space of a beam reaches from its origin up to the tolerance before the reflection
+        let query = format!(
+            "INSERT INTO sensor_data.pass_through_conflict (beam_id, feature_id, feature_geometry_data_id, intersection, distance_to_reflection, dataset_id)
+SELECT i.beam_id, i.feature_id, i.feature_geometry_data_id, i.intersection, i.length - ST_3DDistance(i.origin, i.intersection), i.dataset_id
+FROM
+    (SELECT b.id as beam_id, g.feature_id, g.id as feature_geometry_data_id, ST_3DIntersection(g.valid_geometry, b.free_space_line) as intersection, b.origin, b.length, b.dataset_id
+     FROM
+        (SELECT *, ST_MakeLine(origin, ST_3DLineInterpolatePoint(line, 1.0 - {pass_through_tolerance} / length)) as free_space_line
+         FROM sensor_data.beam
+         WHERE patch_id = {current_patch_id} AND length > {pass_through_tolerance}) as b
+     JOIN
+        (SELECT *
+         FROM sensor_data.feature_geometry_data
+         WHERE valid_geometry IS NOT NULL AND geometry_selection_id = {geometry_selection_id}) as g
+     ON ST_3DIntersects(g.valid_geometry, b.free_space_line)) as i
+WHERE NOT ST_IsEmpty(i.intersection);"
+        );
+        diesel::sql_query(query).execute(&mut connection).await?;
+    }
+
+    Ok(())
+}
+
+async fn explode_patch_to_beams(
+    connection: &mut Object<AsyncPgConnection>,
+    current_patch_id: i32,
+    reflection_line_length: f32,
 ) -> Result<(), Error> {
     info!("Exploding patch with id: {current_patch_id}");
-    let reflection_line_length = distance_threshold * 2.0;
 
-    let query = format!("INSERT INTO sensor_data.beam (patch_id, point_id, timestamp_sec, timestamp_nanosec, intensity, origin, reflection, line, length, reflection_line, ros_message_id, ros_point_id)
+    let query = format!("INSERT INTO sensor_data.beam (patch_id, point_id, timestamp_sec, timestamp_nanosec, intensity, origin, reflection, line, length, reflection_line, ros_message_id, ros_point_id, dataset_id)
 SELECT
     patch_id,
     point_id,
@@ -479,7 +1845,8 @@ SELECT
                    {reflection_line_length}/length, {reflection_line_length}/length, {reflection_line_length}/length),
            ST_X(reflection), ST_Y(reflection), ST_Z(reflection)) as reflection_line,
     ros_message_id,
-    ros_point_id
+    ros_point_id,
+    dataset_id
 FROM
     (SELECT
          ST_MakeLine(origin, reflection) as line,
@@ -487,6 +1854,7 @@ FROM
          ST_LineInterpolatePoint(ST_MakeLine(origin, reflection), 0.5) AS midpoint,
          *
     FROM (SELECT id as patch_id,
+           dataset_id,
            PC_Get(pc_explode(pa), 'id') as point_id,
            PC_Get(pc_explode(pa), 'timestamp_sec') as timestamp_sec,
            PC_Get(pc_explode(pa), 'timestamp_nanosec') as timestamp_nanosec,
@@ -497,50 +1865,42 @@ FROM
            PC_Get(pc_explode(pa), 'ros_point_id') as ros_point_id
     FROM sensor_data.point_cloud_upload
     WHERE point_cloud_upload.id = {current_patch_id}) as source_point_exploded) as pc;");
-    diesel::sql_query(query).execute(&mut connection).await?;
+    diesel::sql_query(query).execute(connection).await?;
 
-    info!("Associating point-model with patch_id: {current_patch_id}");
-    let query = format!(
-        "INSERT INTO sensor_data.association_point_model (beam_id, feature_id, distance)
-    SELECT DISTINCT beam.id, geometry_data.feature_id, ST_3DDistance(citydb.geometry_data.geometry, beam.reflection)
-    FROM
-        sensor_data.beam
-    JOIN
-        citydb.geometry_data
-    ON ST_3DDWithin(citydb.geometry_data.geometry, beam.reflection, {distance_threshold})
-    WHERE
-        beam.patch_id = {current_patch_id};"
-    );
-    diesel::sql_query(query).execute(&mut connection).await?;
+    Ok(())
+}
 
-    //return Ok(());
+/// Derives the reflection lines of beams, which were uploaded directly.
+async fn derive_reflection_lines(
+    connection: &mut Object<AsyncPgConnection>,
+    current_patch_id: i32,
+    reflection_line_length: f32,
+) -> Result<(), Error> {
+    info!("Deriving reflection lines of beams with patch_id: {current_patch_id}");
 
-    if beam_intersection {
-        info!("Associating beam-model with patch_id: {current_patch_id}");
-        let query = format!(
-            "INSERT INTO sensor_data.association_beam_model (beam_id, feature_id, intersection)
-SELECT DISTINCT b.id, g.feature_id, ST_3DIntersection(g.valid_geometry, b.reflection_line)
-FROM
-    (SELECT *
-     FROM sensor_data.beam
-     WHERE patch_id = {current_patch_id}) as b
-JOIN
-        (SELECT *
-         FROM sensor_data.feature_geometry_data
-         WHERE valid_geometry IS NOT NULL) as g
-ON ST_3DIntersects(g.valid_geometry, b.reflection_line);"
-        );
-        diesel::sql_query(query).execute(&mut connection).await?;
-    }
+    let query = format!(
+        "UPDATE sensor_data.beam
+SET reflection_line = ST_Translate(
+           ST_Scale(
+                   ST_Translate(line, -ST_X(ST_LineInterpolatePoint(line, 0.5)), -ST_Y(ST_LineInterpolatePoint(line, 0.5)), -ST_Z(ST_LineInterpolatePoint(line, 0.5))),
+                   {reflection_line_length}/length, {reflection_line_length}/length, {reflection_line_length}/length),
+           ST_X(reflection), ST_Y(reflection), ST_Z(reflection))
+WHERE patch_id = {current_patch_id};"
+    );
+    diesel::sql_query(query).execute(connection).await?;
 
     Ok(())
 }
 
+/// Downloads the associated points of a patch matching the filter conditions.
+///
+/// Returns `None` if no point matches.
 async fn download_associate_points(
     mut connection: Object<AsyncPgConnection>,
     current_patch_id: i32,
+    filter_conditions: &str,
     keep_temporary_table_entries: bool,
-) -> Result<epoint::PointCloud, Error> {
+) -> Result<Option<epoint::PointCloud>, Error> {
     info!("Explode patch id: {current_patch_id}");
 
     /*let query = "SELECT PC_AsText(pa)
@@ -550,7 +1910,7 @@ async fn download_associate_points(
 
     let query = format!("
 INSERT INTO sensor_data.point_cloud_download (
-    patch_id, x, y, z, point_id, timestamp_sec, timestamp_nanosec, intensity, beam_origin_x, beam_origin_y, beam_origin_z, beam_length, ros_message_id, ros_point_id, gml_id, gml_name, classname, surface_distance, intersection_angle)
+    patch_id, x, y, z, point_id, timestamp_sec, timestamp_nanosec, intensity, beam_origin_x, beam_origin_y, beam_origin_z, beam_length, ros_message_id, ros_point_id, gml_id, gml_name, classname, surface_distance, intersection_angle, dataset_id, parent_gml_id, surface_classname)
 SELECT
     b.patch_id,
     ST_X(b.reflection),
@@ -568,12 +1928,20 @@ SELECT
     b.ros_point_id,
     cdb.objectid,
     cdb.name,
-    cdb.classname,
+    coalesce(parent_oc.classname, cdb.classname),
     apm.distance,
-    case when abm.intersection IS NULL then NULL else 1 end as intersection_angle
+    abm.intersection_angle,
+    b.dataset_id,
+    parent.objectid,
+    CASE WHEN apm.parent_feature_id IS NOT NULL THEN cdb.classname END
 FROM sensor_data.beam as b
-LEFT JOIN sensor_data.association_beam_model as abm ON b.id = abm.beam_id
 LEFT JOIN sensor_data.association_point_model as apm ON b.id = apm.beam_id
+LEFT JOIN LATERAL
+    (SELECT intersection_angle
+     FROM sensor_data.association_beam_model
+     WHERE beam_id = b.id AND feature_id = apm.feature_id
+     ORDER BY ST_3DDistance(intersection, b.reflection), id
+     LIMIT 1) as abm ON true
 LEFT JOIN
     (SELECT f.id as feature_id, f.objectid as objectid, p.val_string as name, oc.classname as classname
      FROM citydb.feature as f
@@ -585,7 +1953,9 @@ LEFT JOIN
      ON f.id = p.feature_id
      ) as cdb
 ON apm.feature_id = cdb.feature_id
-WHERE b.patch_id = {current_patch_id};");
+LEFT JOIN citydb.feature as parent ON apm.parent_feature_id = parent.id
+LEFT JOIN citydb.objectclass as parent_oc ON parent.objectclass_id = parent_oc.id
+WHERE b.patch_id = {current_patch_id}{filter_conditions};");
 
     let _a = diesel::sql_query(query).execute(&mut connection).await?;
 
@@ -609,33 +1979,148 @@ WHERE b.patch_id = {current_patch_id};");
         info!("Deleted temporary entries of patch {current_patch_id}: point_cloud_download (number of rows: {num_deleted})");
     }
 
+    if database_points.is_empty() {
+        return Ok(None);
+    }
     let point_cloud = derive_point_cloud(database_points)?;
-    Ok(point_cloud)
+    Ok(Some(point_cloud))
 }
 
-async fn explode_feature_geometry_data(
-    mut connection: Object<AsyncPgConnection>,
-) -> Result<(), Error> {
-    let query = "INSERT INTO sensor_data.feature_geometry_data (geometry_data_id, feature_id, geometry, valid_geometry)
-SELECT
-    id,
-    feature_id,
-    geometry,
-    case when ST_GeometryType(valid_geometry) = 'ST_Polygon' AND ST_IsPlanar(valid_geometry) then valid_geometry else null end as valid_geometry
-FROM
-    (SELECT
-         id,
-         feature_id,
-         (ST_Dump(geometry_data.geometry)).geom::geometry(PolygonZ) as geometry,
-         ST_MakeValid((ST_Dump(geometry_data.geometry)).geom::geometry(PolygonZ)) as valid_geometry,
-         ST_AsText(ST_MakeValid((ST_Dump(geometry_data.geometry)).geom::geometry(PolygonZ)))
-    FROM geometry_data
-    WHERE
-        ST_GeometryType(geometry_data.geometry) = 'ST_PolyhedralSurface' OR
-        ST_GeometryType(geometry_data.geometry) = 'ST_MultiPolygon'
-    ) as t;".to_string();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database_copy::CopyFormat;
+    use crate::patch::PatchPoint;
+    use std::env;
+
+    const ENV_VARIABLE_DATABASE_URL: &str = "CITYDB_DATABASE_URL";
+
+    fn synthetic_points() -> Vec<PatchPoint> {
+        (0..100u32)
+            .map(|i| {
+                let offset = i as f64;
+                PatchPoint {
+                    x: 691000.0 + offset * 0.25,
+                    y: 5336000.0 + offset * 0.5,
+                    z: 515.0 + offset * 0.125,
+                    id: i as u64,
+                    timestamp_sec: 1605702866 + (i / 10) as i64,
+                    timestamp_nanosec: i * 1_000_000 + 7,
+                    intensity: i as f32 / 100.0,
+                    beam_origin_x: 690990.0 + offset,
+                    beam_origin_y: 5335990.0 - offset,
+                    beam_origin_z: 513.5,
+                    ros_message_id: i / 10,
+                    ros_point_id: i % 10,
+                }
+            })
+            .collect()
+    }
 
-    let _a = diesel::sql_query(query).execute(&mut connection).await?;
+    /// Uploads a synthetic point cloud, associates and downloads it again.
+    async fn round_trip(dataset: &str, upload_method: UploadMethod) {
+        let database_url = env::var(ENV_VARIABLE_DATABASE_URL)
+            .expect("Environment variable CITYDB_DATABASE_URL not set.");
+        let database_manager = DatabaseManager::new(&database_url, 4);
+        database_manager.clean_dataset(dataset).await.unwrap();
+
+        let point_cloud_format = database_manager
+            .register_point_cloud_format(None, None)
+            .await
+            .unwrap();
+        let dataset_id = database_manager.create_dataset(dataset).await.unwrap();
+        database_manager
+            .assign_point_cloud_format(dataset, dataset_id, point_cloud_format)
+            .await
+            .unwrap();
+
+        let points = synthetic_points();
+        let point_cloud = PatchUncompressedBinary::new(point_cloud_format.pcid, points.clone())
+            .to_point_cloud()
+            .unwrap();
+        database_manager
+            .upload_single_point_cloud(
+                dataset_id,
+                point_cloud,
+                upload_method,
+                point_cloud_format,
+                None,
+            )
+            .await
+            .unwrap();
+        database_manager
+            .associate(
+                dataset,
+                &DistanceThresholds::default(),
+                &GeometrySelection::default(),
+                AssociationMode::default(),
+                true,
+                None,
+                false,
+            )
+            .await
+            .unwrap();
+
+        let mut connection = database_manager.connection_pool.get().await.unwrap();
+        let patch_ids: Vec<i32> = schema::sensor_data::beam::dsl::beam
+            .filter(schema::sensor_data::beam::dataset_id.eq(dataset_id))
+            .select(patch_id)
+            .distinct()
+            .load(&mut connection)
+            .await
+            .unwrap();
+        assert_eq!(patch_ids.len(), 1);
+
+        let downloaded_point_cloud = download_associate_points(connection, patch_ids[0], "", false)
+            .await
+            .unwrap()
+            .expect("no filter applied");
+        let mut downloaded_points: Vec<PatchPoint> = PatchUncompressedBinary::from_point_cloud(
+            point_cloud_format.pcid,
+            &downloaded_point_cloud,
+        )
+        .unwrap()
+        .points()
+        .clone();
+        downloaded_points.sort_by_key(|p| p.id);
+        assert_eq!(downloaded_points, points);
 
-    Ok(())
+        database_manager.clean_dataset(dataset).await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "requires a 3DCityDB with the sensor_data migrations"]
+    async fn test_round_trip_insert() {
+        round_trip("round_trip_insert", UploadMethod::Insert).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "requires a 3DCityDB with the sensor_data migrations"]
+    async fn test_round_trip_copy_patch() {
+        round_trip(
+            "round_trip_copy_patch",
+            UploadMethod::CopyPatch(CopyFormat::Text),
+        )
+        .await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "requires a 3DCityDB with the sensor_data migrations"]
+    async fn test_round_trip_copy_beam_text() {
+        round_trip(
+            "round_trip_copy_beam_text",
+            UploadMethod::CopyBeam(CopyFormat::Text),
+        )
+        .await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "requires a 3DCityDB with the sensor_data migrations"]
+    async fn test_round_trip_copy_beam_binary() {
+        round_trip(
+            "round_trip_copy_beam_binary",
+            UploadMethod::CopyBeam(CopyFormat::Binary),
+        )
+        .await;
+    }
 }
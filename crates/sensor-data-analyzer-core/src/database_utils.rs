@@ -4,129 +4,16 @@ use erosbag::RosPointCloudColumnType;
 
 use crate::error::Error;
 use crate::models::exports::PointCloudDownloadEntry;
-use itertools::{izip, Itertools};
 
-use polars::datatypes::UInt32Chunked;
 use polars::frame::DataFrame;
 use polars::prelude::NamedFrom;
 use polars::series::Series;
-use rayon::prelude::*;
 
-/// https://pgpointcloud.github.io/pointcloud/concepts/binary.html#dimensional
-#[derive(PartialEq, Eq, Debug, Clone, Hash)]
-#[repr(u32)]
-pub enum DimensionCompression {
-    NoCompression = 0,
-    RunLengthCompression = 1,
-    SignificantBitsRemoval = 2,
-    Deflate = 3,
-}
-
-#[derive(PartialEq, Eq, Debug, Clone, Hash)]
-#[repr(u8)]
-pub enum Endianess {
-    Xdr = 0,
-    Ndr = 1,
-}
-
-struct PathUncompressedBinary {
-    endianness: u8,
-    pcid: u32,
-    compression: u32,
-    npoints: u32,
-    pointdata: Vec<i64>,
-}
-
-pub fn insert_point_cloud(point_cloud: &epoint::PointCloud) -> Result<Vec<String>, Error> {
-    let x_values = point_cloud.point_data.get_x_values();
-    let y_values = point_cloud.point_data.get_y_values();
-    let z_values = point_cloud.point_data.get_z_values();
-
-    let id_values = point_cloud.point_data.get_id_values()?;
-    let timestamp_seconds_values = point_cloud.point_data.get_timestamp_sec_values()?;
-    let timestamp_nano_seconds_values = point_cloud.point_data.get_timestamp_nanosec_values()?;
-    let intensity_values = point_cloud.point_data.get_intensity_values()?;
-
-    let beam_origin_x_values = point_cloud.point_data.get_beam_origin_x_values()?;
-    let beam_origin_y_values = point_cloud.point_data.get_beam_origin_y_values()?;
-    let beam_origin_z_values = point_cloud.point_data.get_beam_origin_z_values()?;
-
-    let ros_message_id_values: &UInt32Chunked = point_cloud
-        .point_data()
-        .data_frame
-        .column(RosPointCloudColumnType::RosMessageId.as_str())
-        .unwrap()
-        .u32()
-        .unwrap();
-
-    let ros_point_id_values: &UInt32Chunked = point_cloud
-        .point_data()
-        .data_frame
-        .column(RosPointCloudColumnType::RosPointId.as_str())
-        .unwrap()
-        .u32()
-        .unwrap();
-    // let feature_id_values = vec![-1; point_cloud.point_data().height()];
-
-    let individual_entries: Vec<String> = izip!(
-        x_values,
-        y_values,
-        z_values,
-        id_values,
-        timestamp_seconds_values,
-        timestamp_nano_seconds_values,
-        intensity_values,
-        beam_origin_x_values,
-        beam_origin_y_values,
-        beam_origin_z_values,
-        ros_message_id_values,
-        ros_point_id_values,
-    )
-    .into_iter()
-    .map(
-        |(
-            x,
-            y,
-            z,
-            id,
-            timestamp_sec,
-            timestamp_nanosec,
-            intensity,
-            beam_origin_x,
-            beam_origin_y,
-            beam_origin_z,
-            ros_message_id,
-            ros_point_id,
-        )| {
-            format!(
-                "{}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}",
-                x.unwrap(),
-                y.unwrap(),
-                z.unwrap(),
-                id.unwrap(),
-                timestamp_sec.unwrap(),
-                timestamp_nanosec.unwrap(),
-                intensity.unwrap(),
-                beam_origin_x.unwrap(),
-                beam_origin_y.unwrap(),
-                beam_origin_z.unwrap(),
-                ros_message_id.unwrap(),
-                ros_point_id.unwrap(),
-            )
-        },
-    )
-    .collect();
-
-    let merged: String = individual_entries
-        .into_iter()
-        .intersperse(", ".into())
-        .collect();
-
-    let query = format!(
-        "INSERT INTO sensor_data.point_cloud_upload (pa)
-    SELECT PC_MakePatch(1, ARRAY[{merged}]);"
-    );
-    Ok(vec![query])
+/// Step of a tracked rosbag upload job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadJobStep {
+    pub upload_job_id: i32,
+    pub step: i32,
 }
 
 pub fn derive_point_cloud(
@@ -185,21 +72,21 @@ pub fn derive_point_cloud(
         PointDataColumnType::BeamOriginX.as_str(),
         database_point_cloud
             .iter()
-            .map(|p| p.x)
+            .map(|p| p.beam_origin_x)
             .collect::<Vec<f64>>(),
     );
     let beam_origin_y_series = Series::new(
         PointDataColumnType::BeamOriginY.as_str(),
         database_point_cloud
             .iter()
-            .map(|p| p.y)
+            .map(|p| p.beam_origin_y)
             .collect::<Vec<f64>>(),
     );
     let beam_origin_z_series = Series::new(
         PointDataColumnType::BeamOriginZ.as_str(),
         database_point_cloud
             .iter()
-            .map(|p| p.z)
+            .map(|p| p.beam_origin_z)
             .collect::<Vec<f64>>(),
     );
     let beam_length_series = Series::new(
@@ -213,15 +100,15 @@ pub fn derive_point_cloud(
         RosPointCloudColumnType::RosPointId.as_str(),
         database_point_cloud
             .iter()
-            .map(|p| p.ros_point_id)
-            .collect::<Vec<i32>>(),
+            .map(|p| p.ros_point_id as u32)
+            .collect::<Vec<u32>>(),
     );
     let ros_message_id_series = Series::new(
         RosPointCloudColumnType::RosMessageId.as_str(),
         database_point_cloud
             .iter()
-            .map(|p| p.ros_message_id)
-            .collect::<Vec<i32>>(),
+            .map(|p| p.ros_message_id as u32)
+            .collect::<Vec<u32>>(),
     );
 
     let gml_id_series = Series::new(
@@ -259,6 +146,20 @@ pub fn derive_point_cloud(
             .map(|p| p.intersection_angle.map(|x| x as f32).unwrap_or(f32::NAN))
             .collect::<Vec<f32>>(),
     );
+    let parent_gml_id_series = Series::new(
+        "parent_gml_id",
+        database_point_cloud
+            .iter()
+            .map(|t| t.parent_gml_id.clone().unwrap_or("".to_string()))
+            .collect::<Vec<String>>(),
+    );
+    let surface_classname_series = Series::new(
+        "surface_classname",
+        database_point_cloud
+            .iter()
+            .map(|t| t.surface_classname.clone().unwrap_or("".to_string()))
+            .collect::<Vec<String>>(),
+    );
 
     let columns = vec![
         x_series,
@@ -279,6 +180,8 @@ pub fn derive_point_cloud(
         classname_series,
         surface_distance_series,
         intersection_angle_series,
+        parent_gml_id_series,
+        surface_classname_series,
     ];
     let df = DataFrame::new(columns).unwrap();
     let point_cloud_info = PointCloudInfo::new(None);
@@ -287,3 +190,77 @@ pub fn derive_point_cloud(
 
     Ok(point_cloud)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::{PatchPoint, PatchUncompressedBinary};
+
+    fn example_entry(point_id: i32) -> PointCloudDownloadEntry {
+        let offset = point_id as f64;
+        PointCloudDownloadEntry {
+            id: point_id as i64,
+            patch_id: 3,
+            x: 691036.5 + offset,
+            y: 5336012.25 + offset,
+            z: 515.125 + offset,
+            point_id,
+            timestamp_sec: 1605702866,
+            timestamp_nanosec: 123456789 + point_id,
+            intensity: 0.75,
+            beam_origin_x: 691030.5 - offset,
+            beam_origin_y: 5336010.25 - offset,
+            beam_origin_z: 513.5,
+            beam_length: 6.5,
+            ros_message_id: 7,
+            ros_point_id: 1024 + point_id,
+            gml_id: Some("DEBY_LOD2_4959457".to_string()),
+            gml_name: None,
+            classname: Some("Building".to_string()),
+            surface_distance: Some(0.05),
+            intersection_angle: None,
+            dataset_id: 1,
+            parent_gml_id: None,
+            surface_classname: None,
+        }
+    }
+
+    #[test]
+    fn test_derive_point_cloud_columns() {
+        let entries = vec![example_entry(0), example_entry(1)];
+        let point_cloud = derive_point_cloud(entries).unwrap();
+
+        let patch = PatchUncompressedBinary::from_point_cloud(1, &point_cloud).unwrap();
+        let expected_points = vec![
+            PatchPoint {
+                x: 691036.5,
+                y: 5336012.25,
+                z: 515.125,
+                id: 0,
+                timestamp_sec: 1605702866,
+                timestamp_nanosec: 123456789,
+                intensity: 0.75,
+                beam_origin_x: 691030.5,
+                beam_origin_y: 5336010.25,
+                beam_origin_z: 513.5,
+                ros_message_id: 7,
+                ros_point_id: 1024,
+            },
+            PatchPoint {
+                x: 691037.5,
+                y: 5336013.25,
+                z: 516.125,
+                id: 1,
+                timestamp_sec: 1605702866,
+                timestamp_nanosec: 123456790,
+                intensity: 0.75,
+                beam_origin_x: 691029.5,
+                beam_origin_y: 5336009.25,
+                beam_origin_z: 513.5,
+                ros_message_id: 7,
+                ros_point_id: 1025,
+            },
+        ];
+        assert_eq!(patch.points(), &expected_points);
+    }
+}
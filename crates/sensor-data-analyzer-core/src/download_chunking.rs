@@ -0,0 +1,174 @@
+use crate::error::Error;
+use chrono::{DateTime, Duration};
+use epoint::PointCloud;
+use itertools::izip;
+use std::collections::{BTreeMap, HashSet};
+
+/// Name of the chunk containing the points without associated feature.
+const UNASSOCIATED_CHUNK_NAME: &str = "unassociated";
+
+/// Splits the downloaded points into output files.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DownloadChunking {
+    /// One file per uploaded patch.
+    #[default]
+    Patch,
+    /// A single file for the whole dataset.
+    Merged,
+    /// One file per time interval of the given duration, named by its start time.
+    TimeInterval(Duration),
+    /// One file per square tile of the given edge length, named by its lower left corner.
+    SpatialTile(f64),
+    /// One file per associated feature, named by its `gml_id`.
+    Feature,
+}
+
+impl DownloadChunking {
+    /// Splits the point cloud of a patch into the chunks it contributes to.
+    pub(crate) fn split_point_cloud(
+        &self,
+        point_cloud: PointCloud,
+        patch_id: i32,
+        dataset: &str,
+    ) -> Result<Vec<(String, PointCloud)>, Error> {
+        let chunk_names: Vec<String> = match self {
+            DownloadChunking::Patch => return Ok(vec![(patch_id.to_string(), point_cloud)]),
+            DownloadChunking::Merged => {
+                return Ok(vec![(sanitize_file_name(dataset), point_cloud)])
+            }
+            DownloadChunking::TimeInterval(_) => {
+                let timestamp_seconds_values = point_cloud.point_data.get_timestamp_sec_values()?;
+                let timestamp_nano_seconds_values =
+                    point_cloud.point_data.get_timestamp_nanosec_values()?;
+                izip!(timestamp_seconds_values, timestamp_nano_seconds_values)
+                    .map(|(timestamp_sec, timestamp_nanosec)| {
+                        self.chunk_name(
+                            timestamp_sec.unwrap() * 1_000_000_000
+                                + timestamp_nanosec.unwrap() as i64,
+                            0.0,
+                            0.0,
+                            None,
+                        )
+                    })
+                    .collect()
+            }
+            DownloadChunking::SpatialTile(_) => izip!(
+                point_cloud.point_data.get_x_values(),
+                point_cloud.point_data.get_y_values()
+            )
+            .map(|(x, y)| self.chunk_name(0, x.unwrap(), y.unwrap(), None))
+            .collect(),
+            DownloadChunking::Feature => point_cloud
+                .point_data
+                .data_frame
+                .column("gml_id")?
+                .str()?
+                .into_iter()
+                .map(|gml_id| self.chunk_name(0, 0.0, 0.0, gml_id))
+                .collect(),
+        };
+
+        let mut chunk_row_indices: BTreeMap<String, HashSet<usize>> = BTreeMap::new();
+        for (row_index, chunk_name) in chunk_names.into_iter().enumerate() {
+            chunk_row_indices
+                .entry(chunk_name)
+                .or_default()
+                .insert(row_index);
+        }
+        if chunk_row_indices.len() == 1 {
+            let chunk_name = chunk_row_indices.into_keys().next().unwrap();
+            return Ok(vec![(chunk_name, point_cloud)]);
+        }
+
+        chunk_row_indices
+            .into_iter()
+            .map(|(chunk_name, row_indices)| {
+                Ok((chunk_name, point_cloud.filter_by_row_indices(row_indices)?))
+            })
+            .collect()
+    }
+
+    /// Returns the name of the chunk of a single point.
+    fn chunk_name(&self, timestamp_nanos: i64, x: f64, y: f64, gml_id: Option<&str>) -> String {
+        match self {
+            DownloadChunking::Patch | DownloadChunking::Merged => unreachable!(),
+            DownloadChunking::TimeInterval(interval) => {
+                let interval_nanos = interval.num_nanoseconds().unwrap_or(i64::MAX).max(1);
+                let interval_start = timestamp_nanos.div_euclid(interval_nanos) * interval_nanos;
+                DateTime::from_timestamp_nanos(interval_start)
+                    .format("%Y%m%dT%H%M%S%.3fZ")
+                    .to_string()
+            }
+            DownloadChunking::SpatialTile(tile_size) => format!(
+                "{}_{}",
+                (x / tile_size).floor() * tile_size,
+                (y / tile_size).floor() * tile_size
+            ),
+            DownloadChunking::Feature => gml_id
+                .map(sanitize_file_name)
+                .unwrap_or(UNASSOCIATED_CHUNK_NAME.to_string()),
+        }
+    }
+}
+
+/// Replaces the characters of a chunk name, which are not safe to be used in file names.
+fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_interval_chunk_name() {
+        let chunking = DownloadChunking::TimeInterval(Duration::seconds(10));
+
+        assert_eq!(
+            chunking.chunk_name(1605702866 * 1_000_000_000 + 123456789, 0.0, 0.0, None),
+            "20201118T123420.000Z"
+        );
+        assert_eq!(
+            chunking.chunk_name(1605702869 * 1_000_000_000 + 999999999, 0.0, 0.0, None),
+            "20201118T123420.000Z"
+        );
+        assert_eq!(
+            chunking.chunk_name(1605702870 * 1_000_000_000, 0.0, 0.0, None),
+            "20201118T123430.000Z"
+        );
+    }
+
+    #[test]
+    fn test_spatial_tile_chunk_name() {
+        let chunking = DownloadChunking::SpatialTile(50.0);
+
+        assert_eq!(
+            chunking.chunk_name(0, 691024.3, 5336099.9, None),
+            "691000_5336050"
+        );
+        assert_eq!(chunking.chunk_name(0, -0.5, 12.0, None), "-50_0");
+    }
+
+    #[test]
+    fn test_feature_chunk_name() {
+        let chunking = DownloadChunking::Feature;
+
+        assert_eq!(
+            chunking.chunk_name(0, 0.0, 0.0, Some("DEBY_LOD2_4959457")),
+            "DEBY_LOD2_4959457"
+        );
+        assert_eq!(
+            chunking.chunk_name(0, 0.0, 0.0, Some("UUID/a b")),
+            "UUID_a_b"
+        );
+        assert_eq!(chunking.chunk_name(0, 0.0, 0.0, None), "unassociated");
+    }
+}
@@ -0,0 +1,158 @@
+use chrono::{DateTime, Utc};
+use nalgebra::{Point2, Point3};
+
+/// Axis-aligned bounding box in the spatial reference system of the dataset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BoundingBox {
+    TwoDimensional {
+        lower: Point2<f64>,
+        upper: Point2<f64>,
+    },
+    ThreeDimensional {
+        lower: Point3<f64>,
+        upper: Point3<f64>,
+    },
+}
+
+/// Restricts the points of a download.
+///
+/// All set criteria must be fulfilled by a point, while multiple `gml_ids` or `classnames` are
+/// alternatives. The filter is translated into the SQL statement that fills
+/// `point_cloud_download`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DownloadFilter {
+    /// Only points associated with one of these features
+    pub gml_ids: Vec<String>,
+    /// Only points associated with a feature of one of these classes, e.g. `WallSurface`
+    pub classnames: Vec<String>,
+    pub start_date_time: Option<DateTime<Utc>>,
+    pub stop_date_time: Option<DateTime<Utc>>,
+    pub bounding_box: Option<BoundingBox>,
+    /// Only points within this polygon given as well-known text
+    pub polygon: Option<String>,
+    /// Only points not associated with any feature
+    pub unassociated_only: bool,
+}
+
+impl DownloadFilter {
+    pub fn is_empty(&self) -> bool {
+        self == &DownloadFilter::default()
+    }
+
+    /// Returns the conditions to be appended to the `WHERE` clause of the download query, where
+    /// `b` is the beam, `apm` the point-model association and `cdb` the associated feature.
+    pub(crate) fn sql_conditions(&self, srid: i32) -> String {
+        let mut conditions: Vec<String> = Vec::new();
+
+        if !self.gml_ids.is_empty() {
+            conditions.push(format!("cdb.objectid IN ({})", quote_list(&self.gml_ids)));
+        }
+        if !self.classnames.is_empty() {
+            conditions.push(format!(
+                "cdb.classname IN ({})",
+                quote_list(&self.classnames)
+            ));
+        }
+
+        if let Some(start_date_time) = self.start_date_time {
+            conditions.push(format!(
+                "(b.timestamp_sec::BIGINT * 1000000000 + b.timestamp_nanosec) >= {}",
+                start_date_time.timestamp_nanos_opt().unwrap_or(i64::MIN)
+            ));
+        }
+        if let Some(stop_date_time) = self.stop_date_time {
+            conditions.push(format!(
+                "(b.timestamp_sec::BIGINT * 1000000000 + b.timestamp_nanosec) < {}",
+                stop_date_time.timestamp_nanos_opt().unwrap_or(i64::MAX)
+            ));
+        }
+
+        match self.bounding_box {
+            Some(BoundingBox::TwoDimensional { lower, upper }) => {
+                conditions.push(format!(
+                    "b.reflection && ST_MakeEnvelope({}, {}, {}, {}, {srid})",
+                    lower.x, lower.y, upper.x, upper.y
+                ));
+            }
+            Some(BoundingBox::ThreeDimensional { lower, upper }) => {
+                conditions.push(format!(
+                    "b.reflection && ST_MakeEnvelope({}, {}, {}, {}, {srid}) AND ST_Z(b.reflection) BETWEEN {} AND {}",
+                    lower.x, lower.y, upper.x, upper.y, lower.z, upper.z
+                ));
+            }
+            None => {}
+        }
+
+        if let Some(polygon) = &self.polygon {
+            conditions.push(format!(
+                "ST_Intersects(b.reflection, ST_GeomFromText({}, {srid}))",
+                quote(polygon)
+            ));
+        }
+
+        if self.unassociated_only {
+            conditions.push("apm.beam_id IS NULL".to_string());
+        }
+
+        conditions
+            .into_iter()
+            .map(|c| format!("\nAND {c}"))
+            .collect()
+    }
+}
+
+/// Quotes a string as SQL literal.
+pub(crate) fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+pub(crate) fn quote_list(values: &[String]) -> String {
+    values
+        .iter()
+        .map(|v| quote(v))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_filter() {
+        let filter = DownloadFilter::default();
+
+        assert!(filter.is_empty());
+        assert_eq!(filter.sql_conditions(25832), "");
+    }
+
+    #[test]
+    fn test_quoted_gml_ids() {
+        let filter = DownloadFilter {
+            gml_ids: vec!["DEBY_LOD2_4959457".to_string(), "it's".to_string()],
+            unassociated_only: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            filter.sql_conditions(25832),
+            "\nAND cdb.objectid IN ('DEBY_LOD2_4959457', 'it''s')\nAND apm.beam_id IS NULL"
+        );
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let filter = DownloadFilter {
+            bounding_box: Some(BoundingBox::TwoDimensional {
+                lower: Point2::new(691000.0, 5336000.0),
+                upper: Point2::new(691100.5, 5336100.0),
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            filter.sql_conditions(25832),
+            "\nAND b.reflection && ST_MakeEnvelope(691000, 5336000, 691100.5, 5336100, 25832)"
+        );
+    }
+}
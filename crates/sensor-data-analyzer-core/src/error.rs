@@ -1,3 +1,6 @@
+use crate::database_copy::CopyFormat;
+use crate::point_cloud_export::DownloadFormat;
+use crate::point_cloud_format::PointCloudFormat;
 use polars::error::PolarsError;
 use thiserror::Error;
 
@@ -14,6 +17,8 @@ pub enum Error {
     #[error(transparent)]
     EmeshConverterError(#[from] emesh_converter::Error),
     #[error(transparent)]
+    EgraphicsError(#[from] egraphics::Error),
+    #[error(transparent)]
     EgraphicsIoError(#[from] egraphics::io::Error),
 
     #[error(transparent)]
@@ -22,4 +27,68 @@ pub enum Error {
     DieselResult(#[from] diesel::result::Error),
     #[error(transparent)]
     PolarsResult(#[from] PolarsError),
+    #[error(transparent)]
+    LasError(#[from] las::Error),
+    #[error(transparent)]
+    SerdeJsonError(#[from] serde_json::Error),
+    #[error(transparent)]
+    CsvError(#[from] csv::Error),
+    #[error(transparent)]
+    TomlError(#[from] toml::de::Error),
+    #[error(transparent)]
+    PngError(#[from] png::EncodingError),
+    #[error(transparent)]
+    TiffError(#[from] tiff::TiffError),
+
+    #[error(transparent)]
+    R2d2Result(#[from] diesel::r2d2::PoolError),
+
+    #[error("invalid point cloud patch: {0}")]
+    InvalidPatch(String),
+    #[error("copy format {0:?} is not supported for this upload method")]
+    UnsupportedCopyFormat(CopyFormat),
+    #[error("value {value} does not fit into the integer column {column}")]
+    IntegerColumnOutOfRange { column: &'static str, value: String },
+    #[error("download format {0:?} cannot be written incrementally")]
+    UnsupportedStreamingFormat(DownloadFormat),
+    #[error("distance bin width must be positive, but is {0}")]
+    InvalidDistanceBinWidth(f64),
+    #[error("cell size must be positive, but is {0}")]
+    InvalidCellSize(f64),
+    #[error("neither a default nor a class distance threshold is given")]
+    MissingDistanceThreshold,
+    #[error("dataset {0} does not exist")]
+    DatasetNotFound(String),
+    #[error("ROS message {0} is not contained in the rosbag")]
+    RosMessageNotFound(i32),
+    #[error("ROS message {ros_message_id} of the rosbag has only {number_of_points} points, but more points were uploaded")]
+    RosMessageSizeMismatch {
+        ros_message_id: i32,
+        number_of_points: i32,
+    },
+    #[error("sensor profile {0} does not exist")]
+    UnknownSensorProfile(String),
+    #[error("no sensor profile is assigned to frame {0}")]
+    MissingSensorProfile(String),
+    #[error("sensor profile file {0} is neither a .toml nor a .json file")]
+    UnsupportedSensorProfileFile(String),
+    #[error("point cloud format {pcid} is registered with srid {registered_srid}, but srid {srid} was requested")]
+    PointCloudFormatSridMismatch {
+        pcid: u32,
+        registered_srid: i32,
+        srid: i32,
+    },
+    #[error("point cloud format {0} is registered with a schema differing from the point layout of the patches")]
+    PointCloudFormatSchemaMismatch(u32),
+    #[error("dataset {dataset} was uploaded with point cloud format {registered:?}, but {requested:?} was requested")]
+    DatasetPointCloudFormatMismatch {
+        dataset: String,
+        registered: PointCloudFormat,
+        requested: PointCloudFormat,
+    },
+    #[error("srid {city_model_srid} of the city model differs from srid {point_cloud_srid} of the point cloud")]
+    SridMismatch {
+        city_model_srid: i32,
+        point_cloud_srid: i32,
+    },
 }
@@ -1,10 +1,26 @@
+mod association;
+mod change_detection;
+mod city_model_enrichment;
+mod database_copy;
 mod database_manager;
 mod database_utils;
+mod download_chunking;
+mod download_filter;
 mod error;
 mod models;
+mod patch;
+mod point_cloud_export;
+mod point_cloud_format;
+mod range_image;
+mod range_image_export;
+mod rosbag_labels;
 mod rosbag_mesh;
 mod schema;
+mod semantic_kitti;
+mod sensor_profile;
 mod sphere;
+mod statistics;
+mod surface_coverage;
 
 extern crate diesel;
 extern crate dotenvy;
@@ -16,4 +32,57 @@ pub use error::Error;
 pub use database_manager::DatabaseManager;
 
 #[doc(inline)]
-pub use rosbag_mesh::extract_lidar_text_mesh;
+pub use association::{
+    AssociationMode, DistanceThresholds, GeometrySelection, DEFAULT_DISTANCE_THRESHOLD,
+};
+
+#[doc(inline)]
+pub use change_detection::{
+    write_change_report, ChangeDetectionParameters, ChangeReport, UnexplainedCluster,
+    UnobservedFeature,
+};
+
+#[doc(inline)]
+pub use city_model_enrichment::FeatureObservationSummary;
+
+#[doc(inline)]
+pub use database_copy::{CopyFormat, UploadMethod};
+
+#[doc(inline)]
+pub use models::sensor_data::Dataset;
+
+#[doc(inline)]
+pub use patch::{Endianess, PatchPoint, PatchUncompressedBinary};
+
+#[doc(inline)]
+pub use download_chunking::DownloadChunking;
+
+#[doc(inline)]
+pub use download_filter::{BoundingBox, DownloadFilter};
+
+#[doc(inline)]
+pub use point_cloud_export::DownloadFormat;
+
+#[doc(inline)]
+pub use point_cloud_format::{PointCloudFormat, POINT_CLOUD_SCHEMA};
+
+#[doc(inline)]
+pub use range_image::RangeImageMeshingParameters;
+
+#[doc(inline)]
+pub use range_image_export::RangeImageFormat;
+
+#[doc(inline)]
+pub use semantic_kitti::SensorFrame;
+
+#[doc(inline)]
+pub use sensor_profile::{LidarSensorProfile, SensorAxisProfile, SensorProfileRegistry};
+
+#[doc(inline)]
+pub use statistics::{
+    write_statistics, ClassStatistics, DatasetStatistics, DistanceHistogramBin, FeatureStatistics,
+    StatisticsFormat,
+};
+
+#[doc(inline)]
+pub use rosbag_mesh::extract_lidar_meshes;
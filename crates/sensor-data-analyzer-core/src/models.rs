@@ -1,8 +1,11 @@
 pub mod sensor_data {
+    use crate::schema::sensor_data::dataset;
     use crate::schema::sensor_data::point_cloud_download;
     use crate::schema::sensor_data::point_cloud_upload;
 
-    use diesel::{Identifiable, Insertable, Queryable};
+    use chrono::{DateTime, Utc};
+    use diesel::sql_types::{Array, BigInt, Double, Integer, Nullable, Text, Timestamptz, Varchar};
+    use diesel::{Identifiable, Insertable, Queryable, QueryableByName};
 
     //     #[diesel(table_name = sensor_data::patches)]
 
@@ -16,7 +19,7 @@ pub mod sensor_data {
         // pub pa: PcPath,
     }
 
-    #[derive(Debug, Clone, Insertable, Queryable, Identifiable)]
+    #[derive(Debug, Clone, Insertable, Queryable, QueryableByName, Identifiable)]
     #[diesel(table_name = point_cloud_download)]
     pub struct PointCloudDownloadEntry {
         pub id: i64,
@@ -39,6 +42,310 @@ pub mod sensor_data {
         pub classname: Option<String>,
         pub surface_distance: Option<f64>,
         pub intersection_angle: Option<f64>,
+        pub dataset_id: i32,
+        pub parent_gml_id: Option<String>,
+        pub surface_classname: Option<String>,
+    }
+
+    #[derive(Debug, Clone, Queryable, Identifiable)]
+    #[diesel(table_name = dataset)]
+    pub struct Dataset {
+        pub id: i32,
+        pub name: String,
+        pub created_at: DateTime<Utc>,
+        pub pcid: Option<i32>,
+        pub srid: Option<i32>,
+        pub geometry_selection_id: Option<i32>,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct CoordinateExtentEntry {
+        #[diesel(sql_type = Nullable<Double>)]
+        pub x_min: Option<f64>,
+        #[diesel(sql_type = Nullable<Double>)]
+        pub y_min: Option<f64>,
+        #[diesel(sql_type = Nullable<Double>)]
+        pub z_min: Option<f64>,
+        #[diesel(sql_type = Nullable<Double>)]
+        pub x_max: Option<f64>,
+        #[diesel(sql_type = Nullable<Double>)]
+        pub y_max: Option<f64>,
+        #[diesel(sql_type = Nullable<Double>)]
+        pub z_max: Option<f64>,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct SridEntry {
+        #[diesel(sql_type = Integer)]
+        pub srid: i32,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct PointCloudFormatEntry {
+        #[diesel(sql_type = Integer)]
+        pub srid: i32,
+        #[diesel(sql_type = Text)]
+        pub schema: String,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct PointCloudFormatIdEntry {
+        #[diesel(sql_type = Integer)]
+        pub pcid: i32,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct PatchIdEntry {
+        #[diesel(sql_type = Integer)]
+        pub id: i32,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct UploadJobEntry {
+        #[diesel(sql_type = Integer)]
+        pub id: i32,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct DatasetIdEntry {
+        #[diesel(sql_type = Integer)]
+        pub id: i32,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct GeometrySelectionIdEntry {
+        #[diesel(sql_type = Integer)]
+        pub id: i32,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct CountEntry {
+        #[diesel(sql_type = BigInt)]
+        pub count: i64,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct BeamIntersectionCountEntry {
+        #[diesel(sql_type = BigInt)]
+        pub number_of_intersections: i64,
+        #[diesel(sql_type = BigInt)]
+        pub number_of_beams: i64,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct TemporalCoverageEntry {
+        #[diesel(sql_type = Nullable<BigInt>)]
+        pub start_timestamp: Option<i64>,
+        #[diesel(sql_type = Nullable<BigInt>)]
+        pub stop_timestamp: Option<i64>,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct SurfaceAreaEntry {
+        #[diesel(sql_type = Nullable<Double>)]
+        pub observed_surface_area: Option<f64>,
+        #[diesel(sql_type = Nullable<Double>)]
+        pub total_surface_area: Option<f64>,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct ClassPointCountEntry {
+        #[diesel(sql_type = Nullable<Varchar>)]
+        pub classname: Option<String>,
+        #[diesel(sql_type = BigInt)]
+        pub number_of_features: i64,
+        #[diesel(sql_type = BigInt)]
+        pub number_of_points: i64,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct FeaturePointCountEntry {
+        #[diesel(sql_type = Nullable<Varchar>)]
+        pub gml_id: Option<String>,
+        #[diesel(sql_type = Nullable<Varchar>)]
+        pub classname: Option<String>,
+        #[diesel(sql_type = BigInt)]
+        pub number_of_points: i64,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct FeatureObservationSummaryEntry {
+        #[diesel(sql_type = BigInt)]
+        pub feature_id: i64,
+        #[diesel(sql_type = Nullable<Varchar>)]
+        pub gml_id: Option<String>,
+        #[diesel(sql_type = Nullable<Varchar>)]
+        pub classname: Option<String>,
+        #[diesel(sql_type = BigInt)]
+        pub number_of_points: i64,
+        #[diesel(sql_type = BigInt)]
+        pub number_of_beam_intersections: i64,
+        #[diesel(sql_type = Nullable<Timestamptz>)]
+        pub first_observed: Option<DateTime<Utc>>,
+        #[diesel(sql_type = Nullable<Timestamptz>)]
+        pub last_observed: Option<DateTime<Utc>>,
+        #[diesel(sql_type = Nullable<Double>)]
+        pub mean_distance: Option<f64>,
+        #[diesel(sql_type = Nullable<Double>)]
+        pub coverage: Option<f64>,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct DistanceBinEntry {
+        #[diesel(sql_type = Integer)]
+        pub bin: i32,
+        #[diesel(sql_type = BigInt)]
+        pub number_of_points: i64,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct SurfaceVertexEntry {
+        #[diesel(sql_type = BigInt)]
+        pub feature_geometry_data_id: i64,
+        #[diesel(sql_type = BigInt)]
+        pub feature_id: i64,
+        #[diesel(sql_type = Integer)]
+        pub ring: i32,
+        #[diesel(sql_type = Double)]
+        pub x: f64,
+        #[diesel(sql_type = Double)]
+        pub y: f64,
+        #[diesel(sql_type = Double)]
+        pub z: f64,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct SurfacePointEntry {
+        #[diesel(sql_type = BigInt)]
+        pub feature_geometry_data_id: i64,
+        #[diesel(sql_type = Double)]
+        pub x: f64,
+        #[diesel(sql_type = Double)]
+        pub y: f64,
+        #[diesel(sql_type = Double)]
+        pub z: f64,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct CoverageCellVertexEntry {
+        #[diesel(sql_type = BigInt)]
+        pub id: i64,
+        #[diesel(sql_type = Integer)]
+        pub number_of_points: i32,
+        #[diesel(sql_type = Double)]
+        pub x: f64,
+        #[diesel(sql_type = Double)]
+        pub y: f64,
+        #[diesel(sql_type = Double)]
+        pub z: f64,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct VoxelEntry {
+        #[diesel(sql_type = Integer)]
+        pub i: i32,
+        #[diesel(sql_type = Integer)]
+        pub j: i32,
+        #[diesel(sql_type = Integer)]
+        pub k: i32,
+        #[diesel(sql_type = BigInt)]
+        pub number_of_points: i64,
+        #[diesel(sql_type = Array<BigInt>)]
+        pub observation_times: Vec<i64>,
+        #[diesel(sql_type = Double)]
+        pub first_timestamp: f64,
+        #[diesel(sql_type = Double)]
+        pub last_timestamp: f64,
+        #[diesel(sql_type = Double)]
+        pub sum_x: f64,
+        #[diesel(sql_type = Double)]
+        pub sum_y: f64,
+        #[diesel(sql_type = Double)]
+        pub sum_z: f64,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct NearestFeatureEntry {
+        #[diesel(sql_type = Nullable<Varchar>)]
+        pub gml_id: Option<String>,
+        #[diesel(sql_type = Nullable<Varchar>)]
+        pub classname: Option<String>,
+        #[diesel(sql_type = Double)]
+        pub distance: f64,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct UnobservedFeatureEntry {
+        #[diesel(sql_type = Nullable<Varchar>)]
+        pub gml_id: Option<String>,
+        #[diesel(sql_type = Nullable<Varchar>)]
+        pub classname: Option<String>,
+        #[diesel(sql_type = BigInt)]
+        pub number_of_visible_surfaces: i64,
+        #[diesel(sql_type = Double)]
+        pub visible_surface_area: f64,
+        #[diesel(sql_type = BigInt)]
+        pub number_of_points: i64,
+        #[diesel(sql_type = BigInt)]
+        pub number_of_beam_intersections: i64,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct FeatureClassEntry {
+        #[diesel(sql_type = BigInt)]
+        pub feature_id: i64,
+        #[diesel(sql_type = Nullable<Varchar>)]
+        pub classname: Option<String>,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct RosMessageIdEntry {
+        #[diesel(sql_type = Integer)]
+        pub ros_message_id: i32,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct ScanPointEntry {
+        #[diesel(sql_type = Integer)]
+        pub ros_point_id: i32,
+        #[diesel(sql_type = Double)]
+        pub x: f64,
+        #[diesel(sql_type = Double)]
+        pub y: f64,
+        #[diesel(sql_type = Double)]
+        pub z: f64,
+        #[diesel(sql_type = Double)]
+        pub intensity: f64,
+        #[diesel(sql_type = Integer)]
+        pub timestamp_sec: i32,
+        #[diesel(sql_type = Integer)]
+        pub timestamp_nanosec: i32,
+        #[diesel(sql_type = Nullable<BigInt>)]
+        pub feature_id: Option<i64>,
+        #[diesel(sql_type = Nullable<Varchar>)]
+        pub classname: Option<String>,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct RosMessageSizeEntry {
+        #[diesel(sql_type = Integer)]
+        pub ros_message_id: i32,
+        #[diesel(sql_type = Integer)]
+        pub number_of_points: i32,
+    }
+
+    #[derive(Debug, Clone, QueryableByName)]
+    pub struct PointLabelEntry {
+        #[diesel(sql_type = Integer)]
+        pub ros_message_id: i32,
+        #[diesel(sql_type = Integer)]
+        pub ros_point_id: i32,
+        #[diesel(sql_type = BigInt)]
+        pub feature_id: i64,
+        #[diesel(sql_type = Nullable<Varchar>)]
+        pub classname: Option<String>,
+        #[diesel(sql_type = Double)]
+        pub surface_distance: f64,
     }
 
     /*#[derive(Debug, Clone, FromSqlRow, AsExpression)]
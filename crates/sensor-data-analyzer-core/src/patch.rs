@@ -0,0 +1,552 @@
+use crate::error::Error;
+use ecoord::ReferenceFrames;
+use epoint::{PointCloud, PointCloudInfo, PointDataColumnType};
+use erosbag::RosPointCloudColumnType;
+use itertools::izip;
+use polars::datatypes::UInt32Chunked;
+use polars::frame::DataFrame;
+use polars::prelude::NamedFrom;
+use polars::series::Series;
+
+/// Size of the header of an uncompressed patch in bytes.
+const PATCH_HEADER_SIZE: usize = 1 + 4 + 4 + 4;
+
+/// Size of a single point of the point cloud format, see [crate::POINT_CLOUD_SCHEMA], in bytes.
+const PATCH_POINT_SIZE: usize = 8 + 8 + 8 + 8 + 8 + 4 + 4 + 8 + 8 + 8 + 4 + 4;
+
+/// https://pgpointcloud.github.io/pointcloud/concepts/binary.html
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+#[repr(u32)]
+pub enum PatchCompression {
+    NoCompression = 0,
+    Ght = 1,
+    Dimensional = 2,
+    Laz = 3,
+}
+
+impl TryFrom<u32> for PatchCompression {
+    type Error = Error;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(PatchCompression::NoCompression),
+            1 => Ok(PatchCompression::Ght),
+            2 => Ok(PatchCompression::Dimensional),
+            3 => Ok(PatchCompression::Laz),
+            _ => Err(Error::InvalidPatch(format!(
+                "unknown compression type {value}"
+            ))),
+        }
+    }
+}
+
+/// Byte order of a binary patch.
+///
+/// XDR is big endian and NDR is little endian.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+#[repr(u8)]
+pub enum Endianess {
+    Xdr = 0,
+    Ndr = 1,
+}
+
+impl TryFrom<u8> for Endianess {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Endianess::Xdr),
+            1 => Ok(Endianess::Ndr),
+            _ => Err(Error::InvalidPatch(format!("unknown endianness {value}"))),
+        }
+    }
+}
+
+/// A single point as described by the point cloud format with pcid 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PatchPoint {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub id: u64,
+    pub timestamp_sec: i64,
+    pub timestamp_nanosec: u32,
+    pub intensity: f32,
+    pub beam_origin_x: f64,
+    pub beam_origin_y: f64,
+    pub beam_origin_z: f64,
+    pub ros_message_id: u32,
+    pub ros_point_id: u32,
+}
+
+/// Uncompressed pgPointCloud patch in its well-known binary representation.
+///
+/// See: https://pgpointcloud.github.io/pointcloud/concepts/binary.html#uncompressed
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchUncompressedBinary {
+    endianness: Endianess,
+    pcid: u32,
+    points: Vec<PatchPoint>,
+}
+
+impl PatchUncompressedBinary {
+    pub fn new(pcid: u32, points: Vec<PatchPoint>) -> Self {
+        Self {
+            endianness: Endianess::Ndr,
+            pcid,
+            points,
+        }
+    }
+
+    pub fn with_endianness(mut self, endianness: Endianess) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    pub fn endianness(&self) -> Endianess {
+        self.endianness
+    }
+
+    pub fn pcid(&self) -> u32 {
+        self.pcid
+    }
+
+    pub fn npoints(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn points(&self) -> &Vec<PatchPoint> {
+        &self.points
+    }
+
+    pub fn from_point_cloud(pcid: u32, point_cloud: &PointCloud) -> Result<Self, Error> {
+        let x_values = point_cloud.point_data.get_x_values();
+        let y_values = point_cloud.point_data.get_y_values();
+        let z_values = point_cloud.point_data.get_z_values();
+
+        let id_values = point_cloud.point_data.get_id_values()?;
+        let timestamp_seconds_values = point_cloud.point_data.get_timestamp_sec_values()?;
+        let timestamp_nano_seconds_values =
+            point_cloud.point_data.get_timestamp_nanosec_values()?;
+        let intensity_values = point_cloud.point_data.get_intensity_values()?;
+
+        let beam_origin_x_values = point_cloud.point_data.get_beam_origin_x_values()?;
+        let beam_origin_y_values = point_cloud.point_data.get_beam_origin_y_values()?;
+        let beam_origin_z_values = point_cloud.point_data.get_beam_origin_z_values()?;
+
+        let ros_message_id_values: &UInt32Chunked = point_cloud
+            .point_data()
+            .data_frame
+            .column(RosPointCloudColumnType::RosMessageId.as_str())?
+            .u32()?;
+        let ros_point_id_values: &UInt32Chunked = point_cloud
+            .point_data()
+            .data_frame
+            .column(RosPointCloudColumnType::RosPointId.as_str())?
+            .u32()?;
+
+        let points = izip!(
+            x_values,
+            y_values,
+            z_values,
+            id_values,
+            timestamp_seconds_values,
+            timestamp_nano_seconds_values,
+            intensity_values,
+            beam_origin_x_values,
+            beam_origin_y_values,
+            beam_origin_z_values,
+            ros_message_id_values,
+            ros_point_id_values,
+        )
+        .map(
+            |(
+                x,
+                y,
+                z,
+                id,
+                timestamp_sec,
+                timestamp_nanosec,
+                intensity,
+                beam_origin_x,
+                beam_origin_y,
+                beam_origin_z,
+                ros_message_id,
+                ros_point_id,
+            )| {
+                Ok(PatchPoint {
+                    x: required_value(x, "x")?,
+                    y: required_value(y, "y")?,
+                    z: required_value(z, "z")?,
+                    id: required_value(id, "id")?,
+                    timestamp_sec: required_value(timestamp_sec, "timestamp_sec")?,
+                    timestamp_nanosec: required_value(timestamp_nanosec, "timestamp_nanosec")?,
+                    intensity: required_value(intensity, "intensity")?,
+                    beam_origin_x: required_value(beam_origin_x, "beam_origin_x")?,
+                    beam_origin_y: required_value(beam_origin_y, "beam_origin_y")?,
+                    beam_origin_z: required_value(beam_origin_z, "beam_origin_z")?,
+                    ros_message_id: required_value(ros_message_id, "ros_message_id")?,
+                    ros_point_id: required_value(ros_point_id, "ros_point_id")?,
+                })
+            },
+        )
+        .collect::<Result<Vec<PatchPoint>, Error>>()?;
+
+        Ok(Self::new(pcid, points))
+    }
+
+    pub fn to_point_cloud(&self) -> Result<PointCloud, Error> {
+        let columns = vec![
+            Series::new(
+                PointDataColumnType::X.as_str(),
+                self.points.iter().map(|p| p.x).collect::<Vec<f64>>(),
+            ),
+            Series::new(
+                PointDataColumnType::Y.as_str(),
+                self.points.iter().map(|p| p.y).collect::<Vec<f64>>(),
+            ),
+            Series::new(
+                PointDataColumnType::Z.as_str(),
+                self.points.iter().map(|p| p.z).collect::<Vec<f64>>(),
+            ),
+            Series::new(
+                PointDataColumnType::Id.as_str(),
+                self.points.iter().map(|p| p.id).collect::<Vec<u64>>(),
+            ),
+            Series::new(
+                PointDataColumnType::TimestampSeconds.as_str(),
+                self.points
+                    .iter()
+                    .map(|p| p.timestamp_sec)
+                    .collect::<Vec<i64>>(),
+            ),
+            Series::new(
+                PointDataColumnType::TimestampNanoSeconds.as_str(),
+                self.points
+                    .iter()
+                    .map(|p| p.timestamp_nanosec)
+                    .collect::<Vec<u32>>(),
+            ),
+            Series::new(
+                PointDataColumnType::Intensity.as_str(),
+                self.points
+                    .iter()
+                    .map(|p| p.intensity)
+                    .collect::<Vec<f32>>(),
+            ),
+            Series::new(
+                PointDataColumnType::BeamOriginX.as_str(),
+                self.points
+                    .iter()
+                    .map(|p| p.beam_origin_x)
+                    .collect::<Vec<f64>>(),
+            ),
+            Series::new(
+                PointDataColumnType::BeamOriginY.as_str(),
+                self.points
+                    .iter()
+                    .map(|p| p.beam_origin_y)
+                    .collect::<Vec<f64>>(),
+            ),
+            Series::new(
+                PointDataColumnType::BeamOriginZ.as_str(),
+                self.points
+                    .iter()
+                    .map(|p| p.beam_origin_z)
+                    .collect::<Vec<f64>>(),
+            ),
+            Series::new(
+                RosPointCloudColumnType::RosMessageId.as_str(),
+                self.points
+                    .iter()
+                    .map(|p| p.ros_message_id)
+                    .collect::<Vec<u32>>(),
+            ),
+            Series::new(
+                RosPointCloudColumnType::RosPointId.as_str(),
+                self.points
+                    .iter()
+                    .map(|p| p.ros_point_id)
+                    .collect::<Vec<u32>>(),
+            ),
+        ];
+
+        let df = DataFrame::new(columns)?;
+        let point_cloud_info = PointCloudInfo::new(None);
+        let point_cloud =
+            PointCloud::from_data_frame(df, point_cloud_info, ReferenceFrames::default())?;
+        Ok(point_cloud)
+    }
+
+    /// Serializes the patch to its well-known binary representation.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buffer: Vec<u8> =
+            Vec::with_capacity(PATCH_HEADER_SIZE + self.points.len() * PATCH_POINT_SIZE);
+        let mut writer = PatchWriter {
+            endianness: self.endianness,
+            buffer: &mut buffer,
+        };
+
+        writer.buffer.push(self.endianness as u8);
+        writer.write_u32(self.pcid);
+        writer.write_u32(PatchCompression::NoCompression as u32);
+        writer.write_u32(self.points.len() as u32);
+
+        for point in &self.points {
+            writer.write_f64(point.x);
+            writer.write_f64(point.y);
+            writer.write_f64(point.z);
+            writer.write_u64(point.id);
+            writer.write_i64(point.timestamp_sec);
+            writer.write_u32(point.timestamp_nanosec);
+            writer.write_f32(point.intensity);
+            writer.write_f64(point.beam_origin_x);
+            writer.write_f64(point.beam_origin_y);
+            writer.write_f64(point.beam_origin_z);
+            writer.write_u32(point.ros_message_id);
+            writer.write_u32(point.ros_point_id);
+        }
+
+        buffer
+    }
+
+    /// Serializes the patch to the hex encoded representation accepted by the `pcpatch` type.
+    pub fn to_hex(&self) -> String {
+        encode_hex(&self.to_bytes())
+    }
+
+    /// Deserializes a patch from its well-known binary representation.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < PATCH_HEADER_SIZE {
+            return Err(Error::InvalidPatch(format!(
+                "patch has {} bytes, but the header requires {PATCH_HEADER_SIZE}",
+                bytes.len()
+            )));
+        }
+        let endianness = Endianess::try_from(bytes[0])?;
+        let mut reader = PatchReader {
+            endianness,
+            bytes: &bytes[1..],
+        };
+
+        let pcid = reader.read_u32()?;
+        let compression = PatchCompression::try_from(reader.read_u32()?)?;
+        if compression != PatchCompression::NoCompression {
+            return Err(Error::InvalidPatch(format!(
+                "compression {compression:?} is not supported"
+            )));
+        }
+        let npoints = reader.read_u32()? as usize;
+        if reader.bytes.len() != npoints * PATCH_POINT_SIZE {
+            return Err(Error::InvalidPatch(format!(
+                "expected {} bytes of point data for {npoints} points, but got {}",
+                npoints * PATCH_POINT_SIZE,
+                reader.bytes.len()
+            )));
+        }
+
+        let mut points: Vec<PatchPoint> = Vec::with_capacity(npoints);
+        for _ in 0..npoints {
+            points.push(PatchPoint {
+                x: reader.read_f64()?,
+                y: reader.read_f64()?,
+                z: reader.read_f64()?,
+                id: reader.read_u64()?,
+                timestamp_sec: reader.read_i64()?,
+                timestamp_nanosec: reader.read_u32()?,
+                intensity: reader.read_f32()?,
+                beam_origin_x: reader.read_f64()?,
+                beam_origin_y: reader.read_f64()?,
+                beam_origin_z: reader.read_f64()?,
+                ros_message_id: reader.read_u32()?,
+                ros_point_id: reader.read_u32()?,
+            });
+        }
+
+        Ok(Self {
+            endianness,
+            pcid,
+            points,
+        })
+    }
+
+    /// Deserializes a patch from the hex encoded representation returned by the `pcpatch` type.
+    pub fn from_hex(hex: &str) -> Result<Self, Error> {
+        Self::from_bytes(&decode_hex(hex)?)
+    }
+}
+
+struct PatchWriter<'a> {
+    endianness: Endianess,
+    buffer: &'a mut Vec<u8>,
+}
+
+macro_rules! patch_writer_fn {
+    ($name:ident, $t:ty) => {
+        fn $name(&mut self, value: $t) {
+            match self.endianness {
+                Endianess::Xdr => self.buffer.extend_from_slice(&value.to_be_bytes()),
+                Endianess::Ndr => self.buffer.extend_from_slice(&value.to_le_bytes()),
+            }
+        }
+    };
+}
+
+impl PatchWriter<'_> {
+    patch_writer_fn!(write_u32, u32);
+    patch_writer_fn!(write_u64, u64);
+    patch_writer_fn!(write_i64, i64);
+    patch_writer_fn!(write_f32, f32);
+    patch_writer_fn!(write_f64, f64);
+}
+
+struct PatchReader<'a> {
+    endianness: Endianess,
+    bytes: &'a [u8],
+}
+
+macro_rules! patch_reader_fn {
+    ($name:ident, $t:ty) => {
+        fn $name(&mut self) -> Result<$t, Error> {
+            const SIZE: usize = std::mem::size_of::<$t>();
+            if self.bytes.len() < SIZE {
+                return Err(Error::InvalidPatch("unexpected end of patch".into()));
+            }
+            let (value_bytes, remaining_bytes) = self.bytes.split_at(SIZE);
+            self.bytes = remaining_bytes;
+            let value_bytes: [u8; SIZE] = value_bytes.try_into().expect("size must match");
+
+            let value = match self.endianness {
+                Endianess::Xdr => <$t>::from_be_bytes(value_bytes),
+                Endianess::Ndr => <$t>::from_le_bytes(value_bytes),
+            };
+            Ok(value)
+        }
+    };
+}
+
+impl PatchReader<'_> {
+    patch_reader_fn!(read_u32, u32);
+    patch_reader_fn!(read_u64, u64);
+    patch_reader_fn!(read_i64, i64);
+    patch_reader_fn!(read_f32, f32);
+    patch_reader_fn!(read_f64, f64);
+}
+
+/// Returns the value of a point or fails for a point cloud with missing values in the column.
+fn required_value<T>(value: Option<T>, column: &str) -> Result<T, Error> {
+    value.ok_or_else(|| Error::InvalidPatch(format!("missing value in column {column}")))
+}
+
+pub(crate) fn encode_hex(bytes: &[u8]) -> String {
+    const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        hex.push(HEX_DIGITS[(byte >> 4) as usize] as char);
+        hex.push(HEX_DIGITS[(byte & 0x0F) as usize] as char);
+    }
+    hex
+}
+
+pub(crate) fn decode_hex(hex: &str) -> Result<Vec<u8>, Error> {
+    if hex.len() % 2 != 0 {
+        return Err(Error::InvalidPatch(
+            "hex encoded patch has an odd number of digits".into(),
+        ));
+    }
+
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let digits = std::str::from_utf8(pair)
+                .map_err(|_| Error::InvalidPatch("patch is not valid hex".into()))?;
+            u8::from_str_radix(digits, 16)
+                .map_err(|_| Error::InvalidPatch(format!("invalid hex digits {digits}")))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_point() -> PatchPoint {
+        PatchPoint {
+            x: 691036.123456789,
+            y: 5336012.987654321,
+            z: 515.25,
+            id: 42,
+            timestamp_sec: 1605702866,
+            timestamp_nanosec: 123456789,
+            intensity: 0.75,
+            beam_origin_x: 691030.5,
+            beam_origin_y: 5336010.25,
+            beam_origin_z: 513.125,
+            ros_message_id: 7,
+            ros_point_id: 1024,
+        }
+    }
+
+    #[test]
+    fn test_header_layout() {
+        let patch = PatchUncompressedBinary::new(1, vec![example_point(), example_point()]);
+        let bytes = patch.to_bytes();
+
+        assert_eq!(bytes.len(), PATCH_HEADER_SIZE + 2 * PATCH_POINT_SIZE);
+        assert_eq!(bytes[0], 1);
+        assert_eq!(&bytes[1..5], &1u32.to_le_bytes());
+        assert_eq!(&bytes[5..9], &0u32.to_le_bytes());
+        assert_eq!(&bytes[9..13], &2u32.to_le_bytes());
+        assert_eq!(&bytes[13..21], &691036.123456789f64.to_le_bytes());
+        assert_eq!(&bytes[89..93], &1024u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_empty_patch_from_hex() {
+        // endianness, pcid, compression and npoints
+        let hex = "01010000000000000000000000";
+        let patch = PatchUncompressedBinary::from_hex(hex).unwrap();
+
+        assert_eq!(patch.endianness(), Endianess::Ndr);
+        assert_eq!(patch.pcid(), 1);
+        assert_eq!(patch.npoints(), 0);
+        assert_eq!(patch.to_hex(), hex);
+    }
+
+    #[test]
+    fn test_round_trip_ndr() {
+        let points = vec![example_point(); 3];
+        let patch = PatchUncompressedBinary::new(1, points);
+
+        let decoded_patch = PatchUncompressedBinary::from_hex(&patch.to_hex()).unwrap();
+        assert_eq!(decoded_patch, patch);
+    }
+
+    #[test]
+    fn test_round_trip_xdr() {
+        let patch =
+            PatchUncompressedBinary::new(1, vec![example_point()]).with_endianness(Endianess::Xdr);
+        let bytes = patch.to_bytes();
+        assert_eq!(bytes[0], 0);
+        assert_eq!(&bytes[1..5], &1u32.to_be_bytes());
+
+        let decoded_patch = PatchUncompressedBinary::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded_patch, patch);
+    }
+
+    #[test]
+    fn test_reject_compressed_patch() {
+        let mut bytes = PatchUncompressedBinary::new(1, vec![example_point()]).to_bytes();
+        bytes[5] = PatchCompression::Dimensional as u8;
+
+        assert!(PatchUncompressedBinary::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_reject_truncated_patch() {
+        let bytes = PatchUncompressedBinary::new(1, vec![example_point()]).to_bytes();
+
+        assert!(PatchUncompressedBinary::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,563 @@
+use crate::error::Error;
+use ecoord::ReferenceFrames;
+use epoint::io::EpointWriter;
+use epoint::{PointCloud, PointCloudInfo};
+use itertools::izip;
+use nalgebra::Point3;
+use polars::prelude::{
+    BatchedWriter, CsvWriter, ParquetReader, ParquetWriter, SerReader, SerWriter,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Maximum number of simultaneously open output files of a chunked download, beyond which the
+/// points of further chunks are spilled to temporary files.
+const MAXIMUM_OPEN_SINKS: usize = 64;
+/// Name of the directory within the download directory holding the spilled points.
+const SPILL_DIRECTORY_NAME: &str = ".spill";
+
+/// File format of downloaded point clouds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DownloadFormat {
+    /// Text file colorized by the associated feature.
+    #[default]
+    Xyz,
+    /// LAS file with the association stored as extra bytes.
+    Las,
+    /// Compressed LAS file with the association stored as extra bytes.
+    Laz,
+    /// Native epoint tar archive containing all columns.
+    Epoint,
+    /// Columnar Parquet file containing all columns.
+    Parquet,
+}
+
+impl DownloadFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            DownloadFormat::Xyz => "xyz",
+            DownloadFormat::Las => "las",
+            DownloadFormat::Laz => "laz",
+            DownloadFormat::Epoint => "tar",
+            DownloadFormat::Parquet => "parquet",
+        }
+    }
+
+    /// Returns the path of a file with the given name in the directory.
+    pub fn file_path(&self, directory_path: impl AsRef<Path>, file_name: &str) -> PathBuf {
+        directory_path
+            .as_ref()
+            .join(format!("{}.{}", file_name, self.extension()))
+    }
+}
+
+/// Writes a downloaded point cloud in the given format.
+pub fn write_point_cloud(
+    point_cloud: PointCloud,
+    format: DownloadFormat,
+    path: impl AsRef<Path>,
+) -> Result<(), Error> {
+    match format {
+        DownloadFormat::Xyz
+        | DownloadFormat::Las
+        | DownloadFormat::Laz
+        | DownloadFormat::Parquet => {
+            let extent = CoordinateExtent::from_point_cloud(&point_cloud);
+            let mut sink = PointCloudSink::create(format, path, &point_cloud, &extent)?;
+            sink.write(&point_cloud)?;
+            sink.finish()?;
+        }
+        DownloadFormat::Epoint => {
+            EpointWriter::from_path(path)?
+                .with_compressed(false)
+                .finish(point_cloud)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Axis-aligned bounding box of the points written into a file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct CoordinateExtent {
+    lower: Point3<f64>,
+    upper: Point3<f64>,
+}
+
+impl CoordinateExtent {
+    pub(crate) fn new(lower: Point3<f64>, upper: Point3<f64>) -> Self {
+        Self { lower, upper }
+    }
+
+    pub(crate) fn from_point_cloud(point_cloud: &PointCloud) -> Self {
+        let x_values = point_cloud.point_data.get_x_values();
+        let y_values = point_cloud.point_data.get_y_values();
+        let z_values = point_cloud.point_data.get_z_values();
+        Self::new(
+            Point3::new(
+                x_values.min().unwrap_or_default(),
+                y_values.min().unwrap_or_default(),
+                z_values.min().unwrap_or_default(),
+            ),
+            Point3::new(
+                x_values.max().unwrap_or_default(),
+                y_values.max().unwrap_or_default(),
+                z_values.max().unwrap_or_default(),
+            ),
+        )
+    }
+
+    /// Returns the LAS coordinate transforms, whose offsets are centered in the extent and whose
+    /// scales are as fine as possible, so that the whole extent fits into the `i32` coordinates.
+    fn las_transforms(&self) -> las::Vector<las::Transform> {
+        las::Vector {
+            x: las_transform(self.lower.x, self.upper.x),
+            y: las_transform(self.lower.y, self.upper.y),
+            z: las_transform(self.lower.z, self.upper.z),
+        }
+    }
+}
+
+fn las_transform(lower: f64, upper: f64) -> las::Transform {
+    let offset = ((lower + upper) / 2.0).round();
+    let offset = if offset.is_finite() { offset } else { 0.0 };
+    let maximum_distance = (upper - offset).abs().max((offset - lower).abs());
+    let scale = LAS_COORDINATE_SCALES
+        .into_iter()
+        .find(|s| maximum_distance / s < i32::MAX as f64)
+        .unwrap_or(LAS_COORDINATE_SCALES[LAS_COORDINATE_SCALES.len() - 1]);
+    las::Transform { scale, offset }
+}
+
+/// Output files of a chunked download.
+///
+/// At most [MAXIMUM_OPEN_SINKS] files are kept open. The points of further chunks are spilled to
+/// temporary Parquet files, which are written into their output files one chunk after another
+/// when finishing.
+pub(crate) struct ChunkSinks {
+    format: DownloadFormat,
+    directory_path: PathBuf,
+    extent: CoordinateExtent,
+    sinks: HashMap<String, PointCloudSink>,
+    spilled_chunks: HashMap<String, (usize, usize)>,
+}
+
+impl ChunkSinks {
+    /// Creates the sinks, where the LAS coordinate transforms are derived from the extent of the
+    /// whole download.
+    pub(crate) fn new(
+        format: DownloadFormat,
+        directory_path: impl AsRef<Path>,
+        extent: CoordinateExtent,
+    ) -> Self {
+        Self {
+            format,
+            directory_path: directory_path.as_ref().to_owned(),
+            extent,
+            sinks: HashMap::new(),
+            spilled_chunks: HashMap::new(),
+        }
+    }
+
+    /// Appends the point cloud to the file of the chunk.
+    pub(crate) fn write(
+        &mut self,
+        chunk_name: &str,
+        point_cloud: &PointCloud,
+    ) -> Result<(), Error> {
+        if let Some(sink) = self.sinks.get_mut(chunk_name) {
+            return sink.write(point_cloud);
+        }
+        if self.sinks.len() < MAXIMUM_OPEN_SINKS && !self.spilled_chunks.contains_key(chunk_name) {
+            let path = self.format.file_path(&self.directory_path, chunk_name);
+            let mut sink = PointCloudSink::create(self.format, path, point_cloud, &self.extent)?;
+            sink.write(point_cloud)?;
+            self.sinks.insert(chunk_name.to_string(), sink);
+            return Ok(());
+        }
+
+        let number_of_spilled_chunks = self.spilled_chunks.len();
+        let (chunk_index, number_of_pieces) = self
+            .spilled_chunks
+            .entry(chunk_name.to_string())
+            .or_insert((number_of_spilled_chunks, 0));
+        let piece_path = Self::piece_path(&self.directory_path, *chunk_index, *number_of_pieces);
+        fs::create_dir_all(piece_path.parent().expect("spill piece has a directory"))?;
+        ParquetWriter::new(File::create(piece_path)?)
+            .finish(&mut point_cloud.point_data.data_frame.clone())?;
+        *number_of_pieces += 1;
+        Ok(())
+    }
+
+    /// Closes the open files and writes the spilled chunks. Returns the number of files.
+    pub(crate) fn finish(self) -> Result<usize, Error> {
+        let number_of_files = self.sinks.len() + self.spilled_chunks.len();
+        for sink in self.sinks.into_values() {
+            sink.finish()?;
+        }
+        if self.spilled_chunks.is_empty() {
+            return Ok(number_of_files);
+        }
+
+        info!("Write {} spilled chunks", self.spilled_chunks.len());
+        for (chunk_name, (chunk_index, number_of_pieces)) in &self.spilled_chunks {
+            let path = self.format.file_path(&self.directory_path, chunk_name);
+            let mut sink: Option<PointCloudSink> = None;
+            for piece_index in 0..*number_of_pieces {
+                let piece_path = Self::piece_path(&self.directory_path, *chunk_index, piece_index);
+                let data_frame = ParquetReader::new(File::open(&piece_path)?).finish()?;
+                let point_cloud = PointCloud::from_data_frame(
+                    data_frame,
+                    PointCloudInfo::new(None),
+                    ReferenceFrames::default(),
+                )?;
+                let sink = match &mut sink {
+                    Some(sink) => sink,
+                    None => sink.insert(PointCloudSink::create(
+                        self.format,
+                        &path,
+                        &point_cloud,
+                        &self.extent,
+                    )?),
+                };
+                sink.write(&point_cloud)?;
+            }
+            if let Some(sink) = sink {
+                sink.finish()?;
+            }
+        }
+        fs::remove_dir_all(self.directory_path.join(SPILL_DIRECTORY_NAME))?;
+
+        Ok(number_of_files)
+    }
+
+    fn piece_path(directory_path: &Path, chunk_index: usize, piece_index: usize) -> PathBuf {
+        directory_path
+            .join(SPILL_DIRECTORY_NAME)
+            .join(chunk_index.to_string())
+            .join(format!("{piece_index}.parquet"))
+    }
+}
+
+/// Output file to which the point clouds of multiple patches are appended one after another, so
+/// that merged downloads do not have to be held in memory.
+pub(crate) enum PointCloudSink {
+    /// Space separated columns without a header, so that the appended point clouds form one
+    /// table.
+    Xyz(BufWriter<File>),
+    Las(las::Writer<BufWriter<File>>),
+    Parquet(BatchedWriter<File>),
+}
+
+impl PointCloudSink {
+    /// Creates the output file, whose header is derived from the first point cloud to be written
+    /// and the extent of all points to be written.
+    ///
+    /// Epoint archives can only be written at once and are therefore not supported.
+    pub(crate) fn create(
+        format: DownloadFormat,
+        path: impl AsRef<Path>,
+        point_cloud: &PointCloud,
+        extent: &CoordinateExtent,
+    ) -> Result<Self, Error> {
+        let sink = match format {
+            DownloadFormat::Xyz => PointCloudSink::Xyz(BufWriter::new(File::create(path)?)),
+            DownloadFormat::Las | DownloadFormat::Laz => {
+                PointCloudSink::Las(create_las_writer(extent, path)?)
+            }
+            DownloadFormat::Epoint => return Err(Error::UnsupportedStreamingFormat(format)),
+            DownloadFormat::Parquet => {
+                let schema = point_cloud.point_data.data_frame.schema();
+                PointCloudSink::Parquet(ParquetWriter::new(File::create(path)?).batched(&schema)?)
+            }
+        };
+        Ok(sink)
+    }
+
+    pub(crate) fn write(&mut self, point_cloud: &PointCloud) -> Result<(), Error> {
+        match self {
+            PointCloudSink::Xyz(writer) => {
+                let colorized_point_cloud =
+                    epoint::transform::colorize::colorize_by_column_hash(point_cloud, "gml_id")?;
+                let mut data_frame = colorized_point_cloud.point_data.data_frame;
+                CsvWriter::new(writer)
+                    .include_header(false)
+                    .with_separator(b' ')
+                    .finish(&mut data_frame)?;
+            }
+            PointCloudSink::Las(writer) => write_las_points(writer, point_cloud)?,
+            PointCloudSink::Parquet(writer) => {
+                writer.write_batch(&point_cloud.point_data.data_frame)?
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> Result<(), Error> {
+        match self {
+            PointCloudSink::Xyz(mut writer) => writer.flush()?,
+            PointCloudSink::Las(mut writer) => writer.close()?,
+            PointCloudSink::Parquet(mut writer) => {
+                writer.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Offset between the Unix and the GPS epoch in seconds.
+const GPS_EPOCH_UNIX_TIMESTAMP: i64 = 315964800;
+/// Leap seconds between UTC and GPS time.
+const GPS_LEAP_SECONDS: i64 = 18;
+/// Offset of the adjusted standard GPS time in seconds.
+const ADJUSTED_STANDARD_GPS_TIME_OFFSET: i64 = 1_000_000_000;
+
+/// Scales of the LAS coordinates from the finest, which is used if the extent fits, to the
+/// coarsest.
+const LAS_COORDINATE_SCALES: [f64; 4] = [0.001, 0.01, 0.1, 1.0];
+const LAS_GML_ID_LENGTH: usize = 64;
+const LAS_CLASSNAME_LENGTH: usize = 32;
+const LAS_EXTRA_BYTES_LENGTH: usize = 2 * LAS_GML_ID_LENGTH + 2 * LAS_CLASSNAME_LENGTH + 4;
+/// Factor converting the intensities to the 16-bit LAS intensities, which matches the intensity
+/// channel of the range images.
+const LAS_INTENSITY_SCALE: f32 = 256.0;
+
+/// Data types of the extra bytes descriptors as defined by the LAS 1.4 specification.
+const LAS_EXTRA_BYTES_UNDOCUMENTED: u8 = 0;
+const LAS_EXTRA_BYTES_FLOAT: u8 = 9;
+
+/// Creates a LAS or LAZ file depending on the file extension.
+///
+/// Since LAS has no variable length attributes, `gml_id`, `classname`, `parent_gml_id` and
+/// `surface_classname` are stored as null padded extra bytes of fixed length and
+/// `surface_distance` as float. The coordinate transforms are derived from the extent of all
+/// points to be written.
+fn create_las_writer(
+    extent: &CoordinateExtent,
+    path: impl AsRef<Path>,
+) -> Result<las::Writer<BufWriter<File>>, Error> {
+    let mut builder = las::Builder::from((1, 4));
+    builder.point_format = las::point::Format::new(1)?;
+    builder.point_format.extra_bytes = LAS_EXTRA_BYTES_LENGTH as u16;
+    builder.gps_time_type = las::GpsTimeType::Standard;
+    builder.transforms = extent.las_transforms();
+    builder.vlrs.push(las::Vlr {
+        user_id: "LASF_Spec".to_string(),
+        record_id: 4,
+        description: "Extra bytes".to_string(),
+        data: [
+            extra_bytes_descriptor(
+                LAS_EXTRA_BYTES_UNDOCUMENTED,
+                LAS_GML_ID_LENGTH as u8,
+                "gml_id",
+                "gml_id of the associated feature",
+            ),
+            extra_bytes_descriptor(
+                LAS_EXTRA_BYTES_UNDOCUMENTED,
+                LAS_CLASSNAME_LENGTH as u8,
+                "classname",
+                "Class of the associated feature",
+            ),
+            extra_bytes_descriptor(
+                LAS_EXTRA_BYTES_FLOAT,
+                0,
+                "surface_distance",
+                "Distance to associated surface",
+            ),
+            extra_bytes_descriptor(
+                LAS_EXTRA_BYTES_UNDOCUMENTED,
+                LAS_GML_ID_LENGTH as u8,
+                "parent_gml_id",
+                "gml_id of the parent feature",
+            ),
+            extra_bytes_descriptor(
+                LAS_EXTRA_BYTES_UNDOCUMENTED,
+                LAS_CLASSNAME_LENGTH as u8,
+                "surface_classname",
+                "Class of the thematic surface",
+            ),
+        ]
+        .concat(),
+    });
+    let header = builder.into_header()?;
+    let writer = las::Writer::from_path(path, header)?;
+    Ok(writer)
+}
+
+fn write_las_points(
+    writer: &mut las::Writer<BufWriter<File>>,
+    point_cloud: &PointCloud,
+) -> Result<(), Error> {
+    let x_values = point_cloud.point_data.get_x_values();
+    let y_values = point_cloud.point_data.get_y_values();
+    let z_values = point_cloud.point_data.get_z_values();
+    let timestamp_seconds_values = point_cloud.point_data.get_timestamp_sec_values()?;
+    let timestamp_nano_seconds_values = point_cloud.point_data.get_timestamp_nanosec_values()?;
+    let intensity_values = point_cloud.point_data.get_intensity_values()?;
+    let data_frame = &point_cloud.point_data.data_frame;
+    let gml_id_values = data_frame.column("gml_id")?.str()?;
+    let classname_values = data_frame.column("classname")?.str()?;
+    let surface_distance_values = data_frame.column("surface_distance")?.f32()?;
+    let parent_gml_id_values = data_frame.column("parent_gml_id")?.str()?;
+    let surface_classname_values = data_frame.column("surface_classname")?.str()?;
+
+    for (
+        x,
+        y,
+        z,
+        timestamp_sec,
+        timestamp_nanosec,
+        intensity,
+        gml_id,
+        classname,
+        surface_distance,
+        parent_gml_id,
+        surface_classname,
+    ) in izip!(
+        x_values,
+        y_values,
+        z_values,
+        timestamp_seconds_values,
+        timestamp_nano_seconds_values,
+        intensity_values,
+        gml_id_values,
+        classname_values,
+        surface_distance_values,
+        parent_gml_id_values,
+        surface_classname_values,
+    ) {
+        let mut extra_bytes: Vec<u8> = Vec::with_capacity(LAS_EXTRA_BYTES_LENGTH);
+        extra_bytes.extend(fixed_length_bytes(
+            gml_id.unwrap_or_default(),
+            LAS_GML_ID_LENGTH,
+        ));
+        extra_bytes.extend(fixed_length_bytes(
+            classname.unwrap_or_default(),
+            LAS_CLASSNAME_LENGTH,
+        ));
+        extra_bytes.extend_from_slice(&surface_distance.unwrap_or(f32::NAN).to_le_bytes());
+        extra_bytes.extend(fixed_length_bytes(
+            parent_gml_id.unwrap_or_default(),
+            LAS_GML_ID_LENGTH,
+        ));
+        extra_bytes.extend(fixed_length_bytes(
+            surface_classname.unwrap_or_default(),
+            LAS_CLASSNAME_LENGTH,
+        ));
+
+        let gps_time = (timestamp_sec.unwrap() - GPS_EPOCH_UNIX_TIMESTAMP + GPS_LEAP_SECONDS
+            - ADJUSTED_STANDARD_GPS_TIME_OFFSET) as f64
+            + timestamp_nanosec.unwrap() as f64 * 1.0e-9;
+        let point = las::Point {
+            x: x.unwrap(),
+            y: y.unwrap(),
+            z: z.unwrap(),
+            intensity: las_intensity(intensity),
+            gps_time: Some(gps_time),
+            extra_bytes,
+            ..Default::default()
+        };
+        writer.write_point(point)?;
+    }
+
+    Ok(())
+}
+
+/// Scales an intensity to the 16-bit LAS range, where missing intensities are `0`.
+fn las_intensity(intensity: Option<f32>) -> u16 {
+    intensity.map_or(0, |v| {
+        (v * LAS_INTENSITY_SCALE)
+            .round()
+            .clamp(0.0, u16::MAX as f32) as u16
+    })
+}
+
+/// Encodes an extra bytes descriptor of the LAS 1.4 specification with a size of 192 bytes.
+fn extra_bytes_descriptor(data_type: u8, options: u8, name: &str, description: &str) -> Vec<u8> {
+    let mut descriptor: Vec<u8> = Vec::with_capacity(192);
+    descriptor.extend_from_slice(&[0, 0]);
+    descriptor.push(data_type);
+    descriptor.push(options);
+    descriptor.extend(fixed_length_bytes(name, 32));
+    // unused, no_data, deprecated, min, deprecated, max, deprecated, scale, deprecated, offset,
+    // deprecated
+    descriptor.extend_from_slice(&[0; 4 + 8 + 16 + 8 + 16 + 8 + 16 + 8 + 16 + 8 + 16]);
+    descriptor.extend(fixed_length_bytes(description, 32));
+    descriptor
+}
+
+/// Returns the bytes of a string truncated or null padded to the given length.
+fn fixed_length_bytes(value: &str, length: usize) -> Vec<u8> {
+    if value.len() > length {
+        warn!("Truncating {value} to {length} bytes");
+    }
+    let mut bytes: Vec<u8> = value.bytes().take(length).collect();
+    bytes.resize(length, 0);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extra_bytes_descriptor_size() {
+        let descriptor = extra_bytes_descriptor(
+            LAS_EXTRA_BYTES_FLOAT,
+            0,
+            "surface_distance",
+            "Distance to associated surface",
+        );
+
+        assert_eq!(descriptor.len(), 192);
+        assert_eq!(descriptor[2], LAS_EXTRA_BYTES_FLOAT);
+        assert_eq!(&descriptor[4..20], b"surface_distance");
+        assert_eq!(&descriptor[160..190], b"Distance to associated surface");
+        assert_eq!(&descriptor[190..192], &[0, 0]);
+    }
+
+    #[test]
+    fn test_las_transforms_cover_extent() {
+        let extent = CoordinateExtent::new(
+            Point3::new(690000.25, 5330000.5, -5.0e6),
+            Point3::new(691000.75, 5345000.5, 5.0e6),
+        );
+        let transforms = extent.las_transforms();
+
+        assert_eq!(transforms.x.offset, 690501.0);
+        assert_eq!(transforms.x.scale, 0.001);
+        assert_eq!(transforms.y.offset, 5337501.0);
+        assert_eq!(transforms.y.scale, 0.001);
+        assert_eq!(transforms.z.offset, 0.0);
+        assert_eq!(transforms.z.scale, 0.01);
+        for (lower, upper, transform) in [
+            (extent.lower.x, extent.upper.x, transforms.x),
+            (extent.lower.y, extent.upper.y, transforms.y),
+            (extent.lower.z, extent.upper.z, transforms.z),
+        ] {
+            for value in [lower, upper] {
+                let coordinate = ((value - transform.offset) / transform.scale).round();
+                assert!(coordinate.abs() < i32::MAX as f64);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fixed_length_bytes() {
+        assert_eq!(fixed_length_bytes("Door", 6), b"Door\0\0".to_vec());
+        assert_eq!(fixed_length_bytes("WallSurface", 4), b"Wall".to_vec());
+    }
+
+    #[test]
+    fn test_las_intensity() {
+        assert_eq!(las_intensity(None), 0);
+        assert_eq!(las_intensity(Some(0.5)), 128);
+        assert_eq!(las_intensity(Some(1.2)), 307);
+        assert_eq!(las_intensity(Some(-1.0)), 0);
+        assert_eq!(las_intensity(Some(1000.0)), u16::MAX);
+    }
+}
@@ -0,0 +1,184 @@
+/// Registered pgPointCloud format, which defines the point schema and spatial reference system of
+/// the uploaded patches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PointCloudFormat {
+    pub pcid: u32,
+    pub srid: i32,
+}
+
+impl PointCloudFormat {
+    pub fn new(pcid: u32, srid: i32) -> Self {
+        Self { pcid, srid }
+    }
+}
+
+/// Point schema of the patches as registered in `pointcloud_formats`.
+///
+/// The dimensions correspond to the point layout of [crate::PatchUncompressedBinary].
+pub const POINT_CLOUD_SCHEMA: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<pc:PointCloudSchema xmlns:pc="http://pointcloud.org/schemas/PC/1.1"
+    xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+  <pc:dimension>
+    <pc:position>1</pc:position>
+    <pc:size>8</pc:size>
+    <pc:description>X coordinate.</pc:description>
+    <pc:name>x</pc:name>
+    <pc:interpretation>double</pc:interpretation>
+  </pc:dimension>
+  <pc:dimension>
+    <pc:position>2</pc:position>
+    <pc:size>8</pc:size>
+    <pc:description>Y coordinate.</pc:description>
+    <pc:name>y</pc:name>
+    <pc:interpretation>double</pc:interpretation>
+  </pc:dimension>
+  <pc:dimension>
+    <pc:position>3</pc:position>
+    <pc:size>8</pc:size>
+    <pc:description>Z coordinate.</pc:description>
+    <pc:name>z</pc:name>
+    <pc:interpretation>double</pc:interpretation>
+  </pc:dimension>
+  <pc:dimension>
+    <pc:position>4</pc:position>
+    <pc:size>8</pc:size>
+    <pc:description>The identifier.</pc:description>
+    <pc:name>id</pc:name>
+    <pc:interpretation>uint64_t</pc:interpretation>
+  </pc:dimension>
+  <pc:dimension>
+    <pc:position>5</pc:position>
+    <pc:size>8</pc:size>
+    <pc:description>The timestamp in seconds.</pc:description>
+    <pc:name>timestamp_sec</pc:name>
+    <pc:interpretation>int64_t</pc:interpretation>
+  </pc:dimension>
+  <pc:dimension>
+    <pc:position>6</pc:position>
+    <pc:size>4</pc:size>
+    <pc:description>The timestamp in nanoseconds since the last whole non-leap second.</pc:description>
+    <pc:name>timestamp_nanosec</pc:name>
+    <pc:interpretation>uint32_t</pc:interpretation>
+  </pc:dimension>
+  <pc:dimension>
+    <pc:position>7</pc:position>
+    <pc:size>4</pc:size>
+    <pc:description>The intensity.</pc:description>
+    <pc:name>intensity</pc:name>
+    <pc:interpretation>float</pc:interpretation>
+  </pc:dimension>
+  <pc:dimension>
+    <pc:position>8</pc:position>
+    <pc:size>8</pc:size>
+    <pc:description>Beam origin X coordinate of current laser shot.</pc:description>
+    <pc:name>beam_origin_x</pc:name>
+    <pc:interpretation>double</pc:interpretation>
+  </pc:dimension>
+  <pc:dimension>
+    <pc:position>9</pc:position>
+    <pc:size>8</pc:size>
+    <pc:description>Beam origin Y coordinate of current laser shot.</pc:description>
+    <pc:name>beam_origin_y</pc:name>
+    <pc:interpretation>double</pc:interpretation>
+  </pc:dimension>
+  <pc:dimension>
+    <pc:position>10</pc:position>
+    <pc:size>8</pc:size>
+    <pc:description>Beam origin Z coordinate of current laser shot.</pc:description>
+    <pc:name>beam_origin_z</pc:name>
+    <pc:interpretation>double</pc:interpretation>
+  </pc:dimension>
+  <pc:dimension>
+    <pc:position>11</pc:position>
+    <pc:size>4</pc:size>
+    <pc:description>Message ID from the ROS bag.</pc:description>
+    <pc:name>ros_message_id</pc:name>
+    <pc:interpretation>uint32_t</pc:interpretation>
+  </pc:dimension>
+  <pc:dimension>
+    <pc:position>12</pc:position>
+    <pc:size>4</pc:size>
+    <pc:description>Point ID from the ROS bag.</pc:description>
+    <pc:name>ros_point_id</pc:name>
+    <pc:interpretation>uint32_t</pc:interpretation>
+  </pc:dimension>
+  <pc:metadata>
+    <Metadata name="compression">none</Metadata>
+  </pc:metadata>
+</pc:PointCloudSchema>"#;
+
+/// Dimension of a point schema, whose descriptions are not relevant for the point layout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SchemaDimension {
+    position: String,
+    size: String,
+    name: String,
+    interpretation: String,
+    scale: Option<String>,
+    offset: Option<String>,
+}
+
+/// Returns the dimensions of a pgPointCloud schema document ordered by their position.
+fn parse_schema_dimensions(schema: &str) -> Vec<SchemaDimension> {
+    let element = |dimension: &str, name: &str| -> Option<String> {
+        let start_tag = format!("<pc:{name}>");
+        let start = dimension.find(&start_tag)? + start_tag.len();
+        let end = dimension[start..].find(&format!("</pc:{name}>"))? + start;
+        Some(dimension[start..end].trim().to_string())
+    };
+
+    let mut dimensions: Vec<SchemaDimension> = schema
+        .split("<pc:dimension>")
+        .skip(1)
+        .filter_map(|d| d.split("</pc:dimension>").next())
+        .map(|d| SchemaDimension {
+            position: element(d, "position").unwrap_or_default(),
+            size: element(d, "size").unwrap_or_default(),
+            name: element(d, "name").unwrap_or_default(),
+            interpretation: element(d, "interpretation").unwrap_or_default(),
+            scale: element(d, "scale"),
+            offset: element(d, "offset"),
+        })
+        .collect();
+    dimensions.sort_by_key(|d| d.position.parse::<u32>().unwrap_or(u32::MAX));
+    dimensions
+}
+
+/// Returns true if the schema describes the point layout of [POINT_CLOUD_SCHEMA], so that the
+/// patches written by [crate::PatchUncompressedBinary] can be stored with it.
+pub(crate) fn is_compatible_schema(schema: &str) -> bool {
+    parse_schema_dimensions(schema) == parse_schema_dimensions(POINT_CLOUD_SCHEMA)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_dimensions() {
+        let dimensions = parse_schema_dimensions(POINT_CLOUD_SCHEMA);
+
+        assert_eq!(dimensions.len(), 12);
+        assert_eq!(dimensions[0].name, "x");
+        assert_eq!(dimensions[11].name, "ros_point_id");
+        assert_eq!(dimensions[11].interpretation, "uint32_t");
+        assert!(dimensions[11].scale.is_none());
+    }
+
+    #[test]
+    fn test_compatible_schema_ignores_descriptions() {
+        let schema = POINT_CLOUD_SCHEMA
+            .replace("X coordinate.", "Easting")
+            .replace("    ", "\t");
+        assert!(is_compatible_schema(&schema));
+
+        let schema = POINT_CLOUD_SCHEMA.replacen(
+            "<pc:size>4</pc:size>\n    <pc:description>The intensity.",
+            "<pc:size>2</pc:size>\n    <pc:description>The intensity.",
+            1,
+        );
+        assert_ne!(schema, POINT_CLOUD_SCHEMA);
+        assert!(!is_compatible_schema(&schema));
+        assert!(!is_compatible_schema(""));
+    }
+}
@@ -0,0 +1,347 @@
+use crate::sphere::{
+    SphericalRasterizationAxis, SphericalRasterizationTransform, UnitSphericalCellIndex3,
+};
+use nalgebra::Point3;
+use std::f64::consts::TAU;
+
+/// Rules for connecting the points of neighbouring range image cells to triangles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RangeImageMeshingParameters {
+    /// Maximum length of a triangle edge in meters.
+    pub maximum_edge_length: f64,
+    /// Maximum relative difference between the largest and smallest range of a triangle, so that
+    /// foreground and background are not connected across depth discontinuities.
+    pub maximum_depth_discontinuity: f64,
+}
+
+impl Default for RangeImageMeshingParameters {
+    fn default() -> Self {
+        Self {
+            maximum_edge_length: 2.0,
+            maximum_depth_discontinuity: 0.1,
+        }
+    }
+}
+
+impl RangeImageMeshingParameters {
+    /// Returns true if the points, given relative to the sensor, may form a triangle.
+    fn is_connected(&self, triangle: &[Point3<f64>; 3]) -> bool {
+        let exceeds_edge_length = (0..3).any(|i| {
+            nalgebra::distance(&triangle[i], &triangle[(i + 1) % 3]) > self.maximum_edge_length
+        });
+        if exceeds_edge_length {
+            return false;
+        }
+
+        let ranges = triangle.map(|p| p.coords.norm());
+        let minimum_range = ranges.iter().copied().fold(f64::INFINITY, f64::min);
+        let maximum_range = ranges.iter().copied().fold(0.0, f64::max);
+        minimum_range > 0.0
+            && (maximum_range - minimum_range) / minimum_range <= self.maximum_depth_discontinuity
+    }
+}
+
+/// Dense raster of a lidar scan with a column per azimuth and a row per elevation cell.
+///
+/// If the azimuth axis covers the full circle, the columns span the cells of the whole field of
+/// view and the first and last column are neighbours, so that the seam at ±180° is closed.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RangeImage<T> {
+    width: usize,
+    height: usize,
+    wraps_azimuth: bool,
+    cells: Vec<Option<T>>,
+}
+
+impl<T> RangeImage<T> {
+    /// Creates the image spanning the cells with values, where the first value of a cell is kept.
+    pub(crate) fn new(
+        transform: &SphericalRasterizationTransform,
+        values: impl IntoIterator<Item = (UnitSphericalCellIndex3, T)>,
+    ) -> Self {
+        let values: Vec<(UnitSphericalCellIndex3, T)> = values.into_iter().collect();
+        let elevation_indices = values.iter().map(|(c, _)| c.elevation());
+        let elevation_bounds = (
+            elevation_indices.clone().min().unwrap_or_default(),
+            elevation_indices.max().unwrap_or(-1),
+        );
+        let azimuth_indices = values.iter().map(|(c, _)| c.azimuth());
+        let azimuth_bounds = (
+            azimuth_indices.clone().min().unwrap_or_default(),
+            azimuth_indices.max().unwrap_or(-1),
+        );
+
+        Self::from_bounds(transform, values, elevation_bounds, azimuth_bounds)
+    }
+
+    /// Creates the image spanning the field of view of the transform, so that the images of all
+    /// scans of a sensor have the same size. Values outside the field of view are discarded.
+    pub(crate) fn with_field_of_view(
+        transform: &SphericalRasterizationTransform,
+        values: impl IntoIterator<Item = (UnitSphericalCellIndex3, T)>,
+    ) -> Self {
+        let axis_bounds = |axis: SphericalRasterizationAxis| {
+            (
+                axis.transform_to_grid_cell_index(axis.field_of_view_min()),
+                axis.transform_to_grid_cell_index(axis.field_of_view_max()),
+            )
+        };
+
+        Self::from_bounds(
+            transform,
+            values.into_iter().collect(),
+            axis_bounds(transform.elevation()),
+            axis_bounds(transform.azimuth()),
+        )
+    }
+
+    fn from_bounds(
+        transform: &SphericalRasterizationTransform,
+        values: Vec<(UnitSphericalCellIndex3, T)>,
+        elevation_bounds: (i32, i32),
+        azimuth_bounds: (i32, i32),
+    ) -> Self {
+        let azimuth = transform.azimuth();
+        let wraps_azimuth = azimuth.range() >= TAU - azimuth.resolution() / 2.0;
+
+        // a full circle spans the cells of the field of view, where the cells at -180° and 180°
+        // coincide only if the resolution divides the circle, so that the columns are folded
+        let (azimuth_bounds, folds_azimuth) = if wraps_azimuth {
+            let lower_bound = azimuth.transform_to_grid_cell_index(azimuth.field_of_view_min());
+            let upper_bound = azimuth.transform_to_grid_cell_index(azimuth.field_of_view_max());
+            let span = (upper_bound - lower_bound) as f64 * azimuth.resolution();
+            if (span - TAU).abs() < azimuth.resolution() * 1.0e-6 {
+                ((lower_bound, upper_bound - 1), true)
+            } else {
+                ((lower_bound, upper_bound), false)
+            }
+        } else {
+            (azimuth_bounds, false)
+        };
+        let wraps_azimuth = wraps_azimuth && azimuth_bounds.1 > azimuth_bounds.0;
+
+        let width = (azimuth_bounds.1 - azimuth_bounds.0 + 1).max(0);
+        let height = (elevation_bounds.1 - elevation_bounds.0 + 1).max(0);
+        let mut range_image = Self {
+            width: width as usize,
+            height: height as usize,
+            wraps_azimuth,
+            cells: Vec::new(),
+        };
+        range_image
+            .cells
+            .resize_with(range_image.width * range_image.height, || None);
+
+        for (cell_index, value) in values {
+            let column = if folds_azimuth {
+                (cell_index.azimuth() - azimuth_bounds.0).rem_euclid(width)
+            } else {
+                cell_index.azimuth() - azimuth_bounds.0
+            };
+            let row = cell_index.elevation() - elevation_bounds.0;
+            if !(0..width).contains(&column) || !(0..height).contains(&row) {
+                continue;
+            }
+
+            let index = range_image.get_index(column as usize, row as usize);
+            range_image.cells[index].get_or_insert(value);
+        }
+
+        range_image
+    }
+
+    pub(crate) fn width(&self) -> usize {
+        self.width
+    }
+
+    pub(crate) fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the value of a cell, where row `0` is the lowest elevation.
+    pub(crate) fn get(&self, column: usize, row: usize) -> Option<&T> {
+        self.cells[self.get_index(column, row)].as_ref()
+    }
+
+    /// Returns the column of the next higher azimuth.
+    fn get_right_column(&self, column: usize) -> Option<usize> {
+        if column + 1 < self.width {
+            Some(column + 1)
+        } else if self.wraps_azimuth {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    fn get_index(&self, column: usize, row: usize) -> usize {
+        row * self.width + column
+    }
+}
+
+impl RangeImage<Point3<f64>> {
+    /// Connects the points of each 2x2 block of cells to up to two triangles, where points are
+    /// given relative to the sensor.
+    pub(crate) fn triangulate(
+        &self,
+        parameters: &RangeImageMeshingParameters,
+    ) -> Vec<[Point3<f64>; 3]> {
+        let mut triangles: Vec<[Point3<f64>; 3]> = Vec::new();
+        for row in 0..self.height.saturating_sub(1) {
+            for column in 0..self.width {
+                let Some(right_column) = self.get_right_column(column) else {
+                    continue;
+                };
+                let current = self.get(column, row);
+                let upper = self.get(column, row + 1);
+                let right = self.get(right_column, row);
+                let upper_right = self.get(right_column, row + 1);
+
+                for triangle in [[current, upper, right], [right, upper, upper_right]] {
+                    if let [Some(a), Some(b), Some(c)] = triangle {
+                        let triangle = [*a, *b, *c];
+                        if parameters.is_connected(&triangle) {
+                            triangles.push(triangle);
+                        }
+                    }
+                }
+            }
+        }
+
+        triangles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(azimuth_min_deg: f64, azimuth_max_deg: f64) -> SphericalRasterizationTransform {
+        SphericalRasterizationTransform::new(
+            SphericalRasterizationAxis::from_deg(azimuth_min_deg, azimuth_max_deg, 90.0, 0.0),
+            SphericalRasterizationAxis::from_deg(-10.0, 10.0, 10.0, 0.0),
+        )
+    }
+
+    /// Returns a point at the given distance in the direction of the cell center.
+    fn cell_point(
+        transform: &SphericalRasterizationTransform,
+        azimuth: i32,
+        elevation: i32,
+        distance: f64,
+    ) -> (UnitSphericalCellIndex3, Point3<f64>) {
+        let azimuth_rad = transform
+            .azimuth()
+            .transform_to_continues_value_rad(azimuth);
+        let elevation_rad = transform
+            .elevation()
+            .transform_to_continues_value_rad(elevation);
+        let point = Point3::new(
+            distance * elevation_rad.cos() * azimuth_rad.cos(),
+            distance * elevation_rad.cos() * azimuth_rad.sin(),
+            distance * elevation_rad.sin(),
+        );
+        (UnitSphericalCellIndex3::new(azimuth, elevation), point)
+    }
+
+    fn parameters() -> RangeImageMeshingParameters {
+        RangeImageMeshingParameters {
+            maximum_edge_length: 100.0,
+            maximum_depth_discontinuity: 0.1,
+        }
+    }
+
+    #[test]
+    fn test_wrap_around_closes_seam() {
+        let transform = transform(-180.0, 180.0);
+        let points: Vec<_> = (-2..2)
+            .flat_map(|a| (0..2).map(move |e| (a, e)))
+            .map(|(a, e)| cell_point(&transform, a, e, 10.0))
+            .collect();
+
+        let range_image = RangeImage::new(&transform, points);
+        assert_eq!(range_image.width(), 4);
+        assert_eq!(range_image.height(), 2);
+        assert!(range_image.get(0, 0).is_some());
+
+        // four 2x2 blocks including the one across the seam
+        assert_eq!(range_image.triangulate(&parameters()).len(), 8);
+    }
+
+    #[test]
+    fn test_full_circle_keeps_cells_at_seam_apart() {
+        // the resolution of the VLP-16 does not divide the full circle
+        let transform = SphericalRasterizationTransform::new(
+            SphericalRasterizationAxis::from_deg(-180.0, 180.0, 0.1990656, 0.0),
+            SphericalRasterizationAxis::from_deg(-10.0, 10.0, 10.0, 0.0),
+        );
+        let points = vec![
+            cell_point(&transform, -905, 0, 10.0),
+            cell_point(&transform, 904, 0, 10.0),
+        ];
+
+        let range_image = RangeImage::with_field_of_view(&transform, points);
+        assert_eq!(range_image.width(), 1810);
+        assert!(range_image.get(0, 1).is_some());
+        assert!(range_image.get(1809, 1).is_some());
+    }
+
+    #[test]
+    fn test_partial_field_of_view_is_not_wrapped() {
+        let transform = transform(-90.0, 90.0);
+        let points: Vec<_> = (-1..2)
+            .flat_map(|a| (0..2).map(move |e| (a, e)))
+            .map(|(a, e)| cell_point(&transform, a, e, 10.0))
+            .collect();
+
+        let range_image = RangeImage::new(&transform, points);
+        assert_eq!(range_image.width(), 3);
+        assert_eq!(range_image.triangulate(&parameters()).len(), 4);
+    }
+
+    #[test]
+    fn test_field_of_view_bounds() {
+        let transform = transform(-90.0, 90.0);
+        let points = vec![
+            cell_point(&transform, 0, 0, 10.0),
+            cell_point(&transform, 5, 0, 10.0),
+        ];
+
+        let range_image = RangeImage::with_field_of_view(&transform, points);
+        assert_eq!(range_image.width(), 3);
+        assert_eq!(range_image.height(), 3);
+        assert!(range_image.get(1, 1).is_some());
+        assert_eq!(range_image.cells.iter().filter(|c| c.is_some()).count(), 1);
+    }
+
+    #[test]
+    fn test_depth_discontinuity_is_not_connected() {
+        let transform = transform(-90.0, 90.0);
+        let points = vec![
+            cell_point(&transform, 0, 0, 10.0),
+            cell_point(&transform, 0, 1, 10.0),
+            cell_point(&transform, 1, 0, 10.5),
+            cell_point(&transform, 1, 1, 30.0),
+        ];
+
+        let triangles = RangeImage::new(&transform, points).triangulate(&parameters());
+        assert_eq!(triangles.len(), 1);
+    }
+
+    #[test]
+    fn test_long_edges_are_not_connected() {
+        let transform = transform(-90.0, 90.0);
+        let points = vec![
+            cell_point(&transform, 0, 0, 10.0),
+            cell_point(&transform, 0, 1, 10.0),
+            cell_point(&transform, 1, 0, 10.0),
+        ];
+        let parameters = RangeImageMeshingParameters {
+            maximum_edge_length: 1.0,
+            ..parameters()
+        };
+
+        let triangles = RangeImage::new(&transform, points).triangulate(&parameters);
+        assert!(triangles.is_empty());
+    }
+}
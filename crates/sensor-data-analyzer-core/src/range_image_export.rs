@@ -0,0 +1,362 @@
+use crate::error::Error;
+use crate::range_image::RangeImage;
+use crate::rosbag_labels::{COLUMN_NAME_CLASS_ID_STR, COLUMN_NAME_SURFACE_DISTANCE_STR};
+use crate::rosbag_mesh::{
+    calculate_cell_center_distance, rasterize_point_cloud, remove_cell_duplicates,
+    COLUMN_NAME_SPHERICAL_AZIMUTH_INDEX_STR, COLUMN_NAME_SPHERICAL_ELEVATION_INDEX_STR,
+};
+use crate::sphere::{SphericalRasterizationTransform, UnitSphericalCellIndex3};
+use epoint::{PointCloud, PointCloudInfo};
+use erosbag::RosPointCloudColumnType;
+use itertools::izip;
+use nalgebra::Vector3;
+use std::fs::{create_dir_all, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// File format of the exported range images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RangeImageFormat {
+    /// 16-bit grayscale PNG of the scaled values, where empty cells are `0`.
+    Png,
+    /// 16-bit grayscale TIFF of the scaled values, where empty cells are `0`.
+    Tiff,
+    /// NumPy array of the unscaled values as `float32`, where empty cells are NaN.
+    Npy,
+}
+
+impl RangeImageFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            RangeImageFormat::Png => "png",
+            RangeImageFormat::Tiff => "tiff",
+            RangeImageFormat::Npy => "npy",
+        }
+    }
+}
+
+/// Values of a range image cell.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct RangeImagePixel {
+    pub range: f32,
+    pub intensity: f32,
+    pub class_id: u32,
+    pub surface_distance: f32,
+}
+
+/// Quantity of a range image, which is written to a directory of the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RangeImageChannel {
+    /// Distance between sensor and point in meters.
+    Range,
+    Intensity,
+    /// Class id of the nearest associated feature as in the SemanticKITTI export.
+    Class,
+    /// Distance between point and the nearest associated feature in meters.
+    SurfaceDistance,
+}
+
+impl RangeImageChannel {
+    const ALL: [RangeImageChannel; 4] = [
+        RangeImageChannel::Range,
+        RangeImageChannel::Intensity,
+        RangeImageChannel::Class,
+        RangeImageChannel::SurfaceDistance,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            RangeImageChannel::Range => "range",
+            RangeImageChannel::Intensity => "intensity",
+            RangeImageChannel::Class => "class",
+            RangeImageChannel::SurfaceDistance => "surface_distance",
+        }
+    }
+
+    /// Factor converting the values to 16-bit integers, which resolves the range in 5 mm steps up
+    /// to 327 m and the surface distance in 0.1 mm steps up to 6.5 m.
+    fn scale(&self) -> f32 {
+        match self {
+            RangeImageChannel::Range => 200.0,
+            RangeImageChannel::Intensity => 256.0,
+            RangeImageChannel::Class => 1.0,
+            RangeImageChannel::SurfaceDistance => 10000.0,
+        }
+    }
+
+    fn value(&self, pixel: &RangeImagePixel) -> Option<f32> {
+        let value = match self {
+            RangeImageChannel::Range => pixel.range,
+            RangeImageChannel::Intensity => pixel.intensity,
+            RangeImageChannel::Class => pixel.class_id as f32,
+            RangeImageChannel::SurfaceDistance => pixel.surface_distance,
+        };
+        (!value.is_nan()).then_some(value)
+    }
+}
+
+/// Rasterizes the labelled point cloud of a lidar and writes the range images of each
+/// `ros_message_id` named by the id to the directory.
+///
+/// Returns the number of written scans.
+pub(crate) fn write_range_images(
+    point_cloud: &PointCloud,
+    transform: &SphericalRasterizationTransform,
+    directory_path: &Path,
+    formats: &[RangeImageFormat],
+) -> Result<usize, Error> {
+    let messages = point_cloud
+        .point_data
+        .data_frame
+        .partition_by_stable([RosPointCloudColumnType::RosMessageId.as_str()], true)?;
+
+    for message in &messages {
+        let ros_message_id = message
+            .column(RosPointCloudColumnType::RosMessageId.as_str())?
+            .u32()?
+            .get(0)
+            .expect("partition must not be empty");
+        let mut message_point_cloud = PointCloud::from_data_frame(
+            message.clone(),
+            PointCloudInfo::new(None),
+            point_cloud.reference_frames().clone(),
+        )?;
+        rasterize_point_cloud(&mut message_point_cloud, transform);
+        calculate_cell_center_distance(&mut message_point_cloud, transform)?;
+        remove_cell_duplicates(&mut message_point_cloud)?;
+
+        let range_image = derive_range_image(&message_point_cloud, transform)?;
+        write_range_image(
+            &range_image,
+            directory_path,
+            &format!("{ros_message_id:06}"),
+            formats,
+        )?;
+    }
+
+    Ok(messages.len())
+}
+
+fn derive_range_image(
+    point_cloud: &PointCloud,
+    transform: &SphericalRasterizationTransform,
+) -> Result<RangeImage<RangeImagePixel>, Error> {
+    let data_frame = &point_cloud.point_data.data_frame;
+    let spherical_azimuth_index_values = data_frame
+        .column(COLUMN_NAME_SPHERICAL_AZIMUTH_INDEX_STR)?
+        .i32()?;
+    let spherical_elevation_index_values = data_frame
+        .column(COLUMN_NAME_SPHERICAL_ELEVATION_INDEX_STR)?
+        .i32()?;
+    let intensity_values = point_cloud.point_data.get_intensity_values()?;
+    let class_id_values = data_frame.column(COLUMN_NAME_CLASS_ID_STR)?.u32()?;
+    let surface_distance_values = data_frame.column(COLUMN_NAME_SURFACE_DISTANCE_STR)?.f32()?;
+
+    let cells = izip!(
+        spherical_azimuth_index_values,
+        spherical_elevation_index_values,
+        point_cloud.point_data.get_x_values(),
+        point_cloud.point_data.get_y_values(),
+        point_cloud.point_data.get_z_values(),
+        intensity_values,
+        class_id_values,
+        surface_distance_values
+    )
+    .map(|(a, e, x, y, z, intensity, class_id, surface_distance)| {
+        let pixel = RangeImagePixel {
+            range: Vector3::new(x.unwrap(), y.unwrap(), z.unwrap()).norm() as f32,
+            intensity: intensity.unwrap_or(f32::NAN),
+            class_id: class_id.unwrap_or_default(),
+            surface_distance: surface_distance.unwrap_or(f32::NAN),
+        };
+        (UnitSphericalCellIndex3::new(a.unwrap(), e.unwrap()), pixel)
+    });
+
+    Ok(RangeImage::with_field_of_view(transform, cells))
+}
+
+/// Writes each channel of the range image to `{channel}/{file_stem}.{extension}`, where the
+/// columns run from -180° to 180° azimuth and the rows from the highest to the lowest elevation.
+pub(crate) fn write_range_image(
+    range_image: &RangeImage<RangeImagePixel>,
+    directory_path: &Path,
+    file_stem: &str,
+    formats: &[RangeImageFormat],
+) -> Result<(), Error> {
+    let width = range_image.width();
+    let height = range_image.height();
+
+    for channel in RangeImageChannel::ALL {
+        let channel_directory_path = directory_path.join(channel.name());
+        create_dir_all(&channel_directory_path)?;
+        let values: Vec<Option<f32>> = (0..height)
+            .rev()
+            .flat_map(|row| (0..width).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                range_image
+                    .get(column, row)
+                    .and_then(|pixel| channel.value(pixel))
+            })
+            .collect();
+
+        for format in formats {
+            let path = channel_directory_path.join(format!("{file_stem}.{}", format.extension()));
+            match format {
+                RangeImageFormat::Png => {
+                    let values = quantize(&values, channel.scale());
+                    write_png(BufWriter::new(File::create(path)?), width, height, &values)?;
+                }
+                RangeImageFormat::Tiff => {
+                    let values = quantize(&values, channel.scale());
+                    write_tiff(File::create(path)?, width, height, &values)?;
+                }
+                RangeImageFormat::Npy => {
+                    let values: Vec<f32> = values.iter().map(|v| v.unwrap_or(f32::NAN)).collect();
+                    write_npy(BufWriter::new(File::create(path)?), width, height, &values)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts the values to 16-bit integers, where empty cells are `0`.
+fn quantize(values: &[Option<f32>], scale: f32) -> Vec<u16> {
+    values
+        .iter()
+        .map(|v| {
+            v.map_or(0, |v| {
+                (v * scale).round().clamp(0.0, u16::MAX as f32) as u16
+            })
+        })
+        .collect()
+}
+
+fn write_png(writer: impl Write, width: usize, height: usize, values: &[u16]) -> Result<(), Error> {
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Sixteen);
+    let mut writer = encoder.write_header()?;
+    // 16-bit samples are stored in network byte order
+    let data: Vec<u8> = values.iter().flat_map(|v| v.to_be_bytes()).collect();
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(())
+}
+
+fn write_tiff(file: File, width: usize, height: usize, values: &[u16]) -> Result<(), Error> {
+    let mut encoder = tiff::encoder::TiffEncoder::new(file)?;
+    encoder.write_image::<tiff::encoder::colortype::Gray16>(width as u32, height as u32, values)?;
+    Ok(())
+}
+
+/// Writes the values as two-dimensional `float32` array in the NumPy format version 1.0.
+///
+/// See: https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html
+fn write_npy(
+    mut writer: impl Write,
+    width: usize,
+    height: usize,
+    values: &[f32],
+) -> Result<(), Error> {
+    const MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+    let mut header =
+        format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({height}, {width}), }}");
+    // the header including magic, length and newline is padded to a multiple of 64 bytes
+    let header_length = MAGIC.len() + 2 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - header_length % 64) % 64));
+    header.push('\n');
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for value in values {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphere::SphericalRasterizationAxis;
+
+    #[test]
+    fn test_write_npy() {
+        let mut buffer: Vec<u8> = Vec::new();
+        write_npy(&mut buffer, 3, 2, &[1.0, 2.0, 3.0, 4.0, 5.0, f32::NAN]).unwrap();
+
+        assert_eq!(&buffer[..8], b"\x93NUMPY\x01\x00");
+        let header_length = u16::from_le_bytes([buffer[8], buffer[9]]) as usize;
+        assert_eq!((10 + header_length) % 64, 0);
+        let header = std::str::from_utf8(&buffer[10..10 + header_length]).unwrap();
+        assert!(header.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }"));
+        assert!(header.ends_with('\n'));
+        assert_eq!(buffer.len(), 10 + header_length + 6 * 4);
+        assert_eq!(
+            &buffer[10 + header_length..14 + header_length],
+            &1.0f32.to_le_bytes()
+        );
+    }
+
+    #[test]
+    fn test_quantize() {
+        let values = quantize(&[Some(1.2345), None, Some(-1.0), Some(1000.0)], 200.0);
+
+        assert_eq!(values, vec![247, 0, 0, u16::MAX]);
+    }
+
+    #[test]
+    fn test_write_range_image() {
+        let transform = SphericalRasterizationTransform::new(
+            SphericalRasterizationAxis::from_deg(-90.0, 90.0, 90.0, 0.0),
+            SphericalRasterizationAxis::from_deg(-10.0, 10.0, 10.0, 0.0),
+        );
+        let pixel = RangeImagePixel {
+            range: 10.0,
+            intensity: 12.0,
+            class_id: 3,
+            surface_distance: f32::NAN,
+        };
+        let range_image = RangeImage::with_field_of_view(
+            &transform,
+            [(UnitSphericalCellIndex3::new(1, 1), pixel)],
+        );
+        let directory_path = std::env::temp_dir().join("sensor_data_analyzer_range_image");
+
+        write_range_image(
+            &range_image,
+            &directory_path,
+            "000007",
+            &[
+                RangeImageFormat::Png,
+                RangeImageFormat::Tiff,
+                RangeImageFormat::Npy,
+            ],
+        )
+        .unwrap();
+
+        for channel in ["range", "intensity", "class", "surface_distance"] {
+            for extension in ["png", "tiff", "npy"] {
+                assert!(directory_path
+                    .join(channel)
+                    .join(format!("000007.{extension}"))
+                    .exists());
+            }
+        }
+        let decoder =
+            png::Decoder::new(File::open(directory_path.join("range").join("000007.png")).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+        // the highest elevation is the first row and the azimuth index 1 the last column
+        let values: Vec<u16> = data
+            .chunks(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect();
+        assert_eq!(values, vec![0, 0, 2000, 0, 0, 0, 0, 0, 0]);
+        std::fs::remove_dir_all(directory_path).unwrap();
+    }
+}
@@ -0,0 +1,326 @@
+use crate::error::Error;
+use crate::models::exports::{PointLabelEntry, RosMessageSizeEntry};
+use crate::semantic_kitti::LabelMapping;
+use diesel::{PgConnection, RunQueryDsl};
+use epoint::{PointCloud, PointCloudInfo};
+use erosbag::{RosPointCloudColumnType, Rosbag};
+use polars::prelude::{NamedFrom, Series, SortMultipleOptions};
+use std::collections::HashMap;
+
+const COLUMN_NAME_FEATURE_ID_STR: &str = "feature_id";
+pub(crate) const COLUMN_NAME_CLASS_ID_STR: &str = "class_id";
+pub(crate) const COLUMN_NAME_SURFACE_DISTANCE_STR: &str = "surface_distance";
+
+/// Association of a point of a ROS message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PointLabel {
+    pub feature_id: u32,
+    pub class_id: u32,
+    pub surface_distance: f32,
+}
+
+impl Default for PointLabel {
+    /// Label of unassociated points.
+    fn default() -> Self {
+        Self {
+            feature_id: 0,
+            class_id: 0,
+            surface_distance: f32::NAN,
+        }
+    }
+}
+
+/// Loads the labels of the associated points within the range of ROS messages keyed by their
+/// `ros_message_id` and `ros_point_id`, where each point gets its nearest feature.
+pub(crate) fn load_point_labels(
+    connection: &mut PgConnection,
+    dataset_id: i32,
+    ros_message_id_range: (u32, u32),
+    label_mapping: &LabelMapping,
+) -> Result<HashMap<(u32, u32), PointLabel>, Error> {
+    let (first_ros_message_id, last_ros_message_id) = ros_message_id_range;
+    let query = format!(
+        "SELECT DISTINCT ON (b.ros_message_id, b.ros_point_id)
+    b.ros_message_id,
+    b.ros_point_id,
+    apm.feature_id,
+    oc.classname,
+    apm.distance AS surface_distance
+FROM sensor_data.beam as b
+JOIN sensor_data.association_point_model as apm ON b.id = apm.beam_id
+JOIN citydb.feature as f ON apm.feature_id = f.id
+LEFT JOIN citydb.objectclass as oc ON f.objectclass_id = oc.id
+WHERE b.dataset_id = {dataset_id}
+  AND b.ros_message_id BETWEEN {first_ros_message_id} AND {last_ros_message_id}
+ORDER BY b.ros_message_id, b.ros_point_id, apm.distance, apm.feature_id;"
+    );
+    let entries: Vec<PointLabelEntry> = diesel::sql_query(query).load(connection)?;
+
+    let labels = entries
+        .into_iter()
+        .map(|e| {
+            let label = PointLabel {
+                feature_id: u32::try_from(e.feature_id).unwrap_or_default(),
+                class_id: label_mapping.label(e.classname.as_deref()) as u32,
+                surface_distance: e.surface_distance as f32,
+            };
+            ((e.ros_message_id as u32, e.ros_point_id as u32), label)
+        })
+        .collect();
+    Ok(labels)
+}
+
+/// Loads the number of points of the uploaded ROS messages within the range given by their highest
+/// `ros_point_id`.
+pub(crate) fn load_ros_message_sizes(
+    connection: &mut PgConnection,
+    dataset_id: i32,
+    ros_message_id_range: (u32, u32),
+) -> Result<HashMap<u32, u32>, Error> {
+    let (first_ros_message_id, last_ros_message_id) = ros_message_id_range;
+    let query = format!(
+        "SELECT ros_message_id, max(ros_point_id) + 1 AS number_of_points
+FROM sensor_data.beam
+WHERE dataset_id = {dataset_id}
+  AND ros_message_id BETWEEN {first_ros_message_id} AND {last_ros_message_id}
+GROUP BY ros_message_id;"
+    );
+    let entries: Vec<RosMessageSizeEntry> = diesel::sql_query(query).load(connection)?;
+
+    Ok(entries
+        .into_iter()
+        .map(|e| (e.ros_message_id as u32, e.number_of_points as u32))
+        .collect())
+}
+
+/// Checks that each uploaded ROS message is contained in the rosbag with at least as many points,
+/// so that the `ros_message_id`s of the upload and the rosbag refer to the same messages.
+pub(crate) fn check_ros_message_sizes(
+    uploaded_sizes: &HashMap<u32, u32>,
+    rosbag_sizes: &HashMap<u32, u32>,
+) -> Result<(), Error> {
+    for (ros_message_id, uploaded_size) in uploaded_sizes {
+        let rosbag_size = rosbag_sizes
+            .get(ros_message_id)
+            .ok_or(Error::RosMessageNotFound(*ros_message_id as i32))?;
+        if rosbag_size < uploaded_size {
+            return Err(Error::RosMessageSizeMismatch {
+                ros_message_id: *ros_message_id as i32,
+                number_of_points: *rosbag_size as i32,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Returns the label columns for the points identified by their `ros_message_id` and
+/// `ros_point_id`.
+pub(crate) fn derive_label_columns(
+    ros_ids: impl IntoIterator<Item = (u32, u32)>,
+    labels: &HashMap<(u32, u32), PointLabel>,
+) -> (Vec<u32>, Vec<u32>, Vec<f32>) {
+    let mut feature_ids: Vec<u32> = Vec::new();
+    let mut class_ids: Vec<u32> = Vec::new();
+    let mut surface_distances: Vec<f32> = Vec::new();
+    for ros_id in ros_ids {
+        let label = labels.get(&ros_id).copied().unwrap_or_default();
+        feature_ids.push(label.feature_id);
+        class_ids.push(label.class_id);
+        surface_distances.push(label.surface_distance);
+    }
+
+    (feature_ids, class_ids, surface_distances)
+}
+
+/// Returns the range of the `ros_message_id`s of the point cloud.
+pub(crate) fn get_ros_message_id_range(point_cloud: &PointCloud) -> Result<(u32, u32), Error> {
+    let ros_message_ids = point_cloud
+        .point_data
+        .data_frame
+        .column(RosPointCloudColumnType::RosMessageId.as_str())?
+        .u32()?;
+    Ok((
+        ros_message_ids.min().unwrap_or_default(),
+        ros_message_ids.max().unwrap_or_default(),
+    ))
+}
+
+/// Returns the number of points of each ROS message given by its highest `ros_point_id`.
+pub(crate) fn derive_ros_message_sizes(
+    ros_ids: impl IntoIterator<Item = (u32, u32)>,
+) -> HashMap<u32, u32> {
+    let mut ros_message_sizes: HashMap<u32, u32> = HashMap::new();
+    for (ros_message_id, ros_point_id) in ros_ids {
+        let size = ros_message_sizes.entry(ros_message_id).or_default();
+        *size = (*size).max(ros_point_id + 1);
+    }
+    ros_message_sizes
+}
+
+/// Returns the number of points of each ROS message of the point cloud read from the rosbag.
+pub(crate) fn get_ros_message_sizes(point_cloud: &PointCloud) -> Result<HashMap<u32, u32>, Error> {
+    let data_frame = &point_cloud.point_data.data_frame;
+    let ros_message_ids = data_frame
+        .column(RosPointCloudColumnType::RosMessageId.as_str())?
+        .u32()?;
+    let ros_point_ids = data_frame
+        .column(RosPointCloudColumnType::RosPointId.as_str())?
+        .u32()?;
+    Ok(derive_ros_message_sizes(
+        ros_message_ids
+            .into_iter()
+            .zip(ros_point_ids)
+            .map(|(m, p)| (m.unwrap(), p.unwrap())),
+    ))
+}
+
+/// Removes the points of previously labelled topics, which carry the label columns, from the
+/// point cloud read from the rosbag, so that only the points of the source topics remain.
+pub(crate) fn remove_labelled_points(point_cloud: &mut PointCloud) -> Result<(), Error> {
+    let data_frame = &point_cloud.point_data.data_frame;
+    if data_frame.column(COLUMN_NAME_CLASS_ID_STR).is_err() {
+        return Ok(());
+    }
+
+    let source_mask = data_frame.column(COLUMN_NAME_CLASS_ID_STR)?.is_null();
+    let mut source_data_frame = data_frame.filter(&source_mask)?;
+    for column_name in [
+        COLUMN_NAME_FEATURE_ID_STR,
+        COLUMN_NAME_CLASS_ID_STR,
+        COLUMN_NAME_SURFACE_DISTANCE_STR,
+    ] {
+        if source_data_frame.column(column_name).is_ok() {
+            source_data_frame = source_data_frame.drop(column_name)?;
+        }
+    }
+    point_cloud.point_data.data_frame = source_data_frame;
+    Ok(())
+}
+
+/// Adds the `feature_id`, `class_id` and `surface_distance` columns to the point cloud read from
+/// the rosbag.
+pub(crate) fn add_label_columns(
+    point_cloud: &mut PointCloud,
+    labels: &HashMap<(u32, u32), PointLabel>,
+) -> Result<(), Error> {
+    let data_frame = &point_cloud.point_data.data_frame;
+    let ros_message_ids = data_frame
+        .column(RosPointCloudColumnType::RosMessageId.as_str())?
+        .u32()?;
+    let ros_point_ids = data_frame
+        .column(RosPointCloudColumnType::RosPointId.as_str())?
+        .u32()?;
+    let (feature_ids, class_ids, surface_distances) = derive_label_columns(
+        ros_message_ids
+            .into_iter()
+            .zip(ros_point_ids)
+            .map(|(m, p)| (m.unwrap(), p.unwrap())),
+        labels,
+    );
+
+    let data_frame = &mut point_cloud.point_data.data_frame;
+    data_frame.with_column(Series::new(COLUMN_NAME_FEATURE_ID_STR, feature_ids))?;
+    data_frame.with_column(Series::new(COLUMN_NAME_CLASS_ID_STR, class_ids))?;
+    data_frame.with_column(Series::new(
+        COLUMN_NAME_SURFACE_DISTANCE_STR,
+        surface_distances,
+    ))?;
+    Ok(())
+}
+
+/// Appends the labelled points of each lidar to the topic `{topic_prefix}/{frame_id}`.
+///
+/// Returns the number of written messages.
+pub(crate) fn append_labelled_point_clouds(
+    rosbag: &mut Rosbag,
+    topic_prefix: &str,
+    point_cloud: &PointCloud,
+) -> Result<usize, Error> {
+    let mut number_of_messages: usize = 0;
+    for frame_id in point_cloud.get_distinct_frame_ids() {
+        let topic_name = format!("{topic_prefix}/{frame_id}");
+        let lidar_point_cloud = point_cloud.filter_by_frame_id(&frame_id)?;
+        number_of_messages += append_labelled_point_cloud(rosbag, &topic_name, &lidar_point_cloud)?;
+    }
+
+    Ok(number_of_messages)
+}
+
+/// Appends the labelled point cloud of a lidar to the topic as one PointCloud2 message per
+/// `ros_message_id`, whose points keep the order of their `ros_point_id` and their timestamps.
+fn append_labelled_point_cloud(
+    rosbag: &mut Rosbag,
+    topic_name: &str,
+    point_cloud: &PointCloud,
+) -> Result<usize, Error> {
+    let messages = point_cloud
+        .point_data
+        .data_frame
+        .partition_by_stable([RosPointCloudColumnType::RosMessageId.as_str()], true)?;
+
+    for message in &messages {
+        let message = message.sort(
+            [RosPointCloudColumnType::RosPointId.as_str()],
+            SortMultipleOptions::default(),
+        )?;
+        let message_point_cloud = PointCloud::from_data_frame(
+            message,
+            PointCloudInfo::new(None),
+            point_cloud.reference_frames().clone(),
+        )?;
+        rosbag.append_point_cloud(topic_name, &message_point_cloud)?;
+    }
+
+    Ok(messages.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derive_label_columns() {
+        let labels = HashMap::from([(
+            (7, 2),
+            PointLabel {
+                feature_id: 42,
+                class_id: 3,
+                surface_distance: 0.05,
+            },
+        )]);
+
+        let (feature_ids, class_ids, surface_distances) =
+            derive_label_columns([(7, 1), (7, 2), (8, 2)], &labels);
+
+        assert_eq!(feature_ids, vec![0, 42, 0]);
+        assert_eq!(class_ids, vec![0, 3, 0]);
+        assert!(surface_distances[0].is_nan());
+        assert_eq!(surface_distances[1], 0.05);
+        assert!(surface_distances[2].is_nan());
+    }
+
+    #[test]
+    fn test_check_ros_message_sizes() {
+        let rosbag_sizes = HashMap::from([(7, 4), (8, 2)]);
+
+        assert!(check_ros_message_sizes(&HashMap::from([(7, 4), (8, 1)]), &rosbag_sizes).is_ok());
+        assert!(matches!(
+            check_ros_message_sizes(&HashMap::from([(8, 3)]), &rosbag_sizes),
+            Err(Error::RosMessageSizeMismatch {
+                ros_message_id: 8,
+                number_of_points: 2
+            })
+        ));
+        assert!(matches!(
+            check_ros_message_sizes(&HashMap::from([(9, 1)]), &rosbag_sizes),
+            Err(Error::RosMessageNotFound(9))
+        ));
+    }
+
+    #[test]
+    fn test_derive_ros_message_sizes() {
+        let ros_message_sizes = derive_ros_message_sizes([(7, 0), (7, 3), (7, 1), (8, 0)]);
+
+        assert_eq!(ros_message_sizes, HashMap::from([(7, 4), (8, 1)]));
+    }
+}
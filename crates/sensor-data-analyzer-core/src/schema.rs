@@ -21,6 +21,8 @@ pub mod sensor_data {
             beam_id -> Int8,
             feature_id -> Int8,
             intersection -> Geometry,
+            dataset_id -> Int4,
+            intersection_angle -> Nullable<Float8>,
         }
     }
 
@@ -33,6 +35,9 @@ pub mod sensor_data {
             beam_id -> Int8,
             feature_id -> Int8,
             distance -> Float8,
+            dataset_id -> Int4,
+            lod -> Nullable<Text>,
+            parent_feature_id -> Nullable<Int8>,
         }
     }
 
@@ -55,6 +60,22 @@ pub mod sensor_data {
             reflection_line -> Geometry,
             ros_message_id -> Int4,
             ros_point_id -> Int4,
+            dataset_id -> Int4,
+        }
+    }
+
+    diesel::table! {
+        use diesel::sql_types::*;
+        use crate::models::exports::*;
+
+        sensor_data.dataset (id) {
+            id -> Int4,
+            #[max_length = 256]
+            name -> Varchar,
+            created_at -> Timestamptz,
+            pcid -> Nullable<Int4>,
+            srid -> Nullable<Int4>,
+            geometry_selection_id -> Nullable<Int4>,
         }
     }
 
@@ -69,6 +90,38 @@ pub mod sensor_data {
             feature_id -> Int8,
             geometry -> Geometry,
             valid_geometry -> Nullable<Geometry>,
+            normal_x -> Nullable<Float8>,
+            normal_y -> Nullable<Float8>,
+            normal_z -> Nullable<Float8>,
+            area -> Nullable<Float8>,
+            geometry_selection_id -> Int4,
+        }
+    }
+
+    diesel::table! {
+        use diesel::sql_types::*;
+        use crate::models::exports::*;
+
+        sensor_data.geometry_selection (id) {
+            id -> Int4,
+            key -> Text,
+            created_at -> Timestamptz,
+        }
+    }
+
+    diesel::table! {
+        use diesel::sql_types::*;
+        use crate::models::exports::*;
+        use super::sql_types::Geometry;
+
+        sensor_data.pass_through_conflict (id) {
+            id -> Int8,
+            beam_id -> Int8,
+            feature_id -> Int8,
+            feature_geometry_data_id -> Int8,
+            intersection -> Geometry,
+            distance_to_reflection -> Float8,
+            dataset_id -> Int4,
         }
     }
 
@@ -100,6 +153,11 @@ pub mod sensor_data {
             classname -> Nullable<Varchar>,
             surface_distance -> Nullable<Float8>,
             intersection_angle -> Nullable<Float8>,
+            dataset_id -> Int4,
+            #[max_length = 256]
+            parent_gml_id -> Nullable<Varchar>,
+            #[max_length = 256]
+            surface_classname -> Nullable<Varchar>,
         }
     }
 
@@ -111,18 +169,85 @@ pub mod sensor_data {
         sensor_data.point_cloud_upload (id) {
             id -> Int4,
             pa -> Pcpatch,
+            dataset_id -> Int4,
+        }
+    }
+
+    diesel::table! {
+        use diesel::sql_types::*;
+        use crate::models::exports::*;
+        use super::sql_types::Geometry;
+
+        sensor_data.surface_coverage_cell (id) {
+            id -> Int8,
+            dataset_id -> Int4,
+            feature_geometry_data_id -> Int8,
+            feature_id -> Int8,
+            column_index -> Int4,
+            row_index -> Int4,
+            cell_size -> Float8,
+            number_of_points -> Int4,
+            geometry -> Geometry,
+        }
+    }
+
+    diesel::table! {
+        use diesel::sql_types::*;
+        use crate::models::exports::*;
+
+        sensor_data.upload_job (id) {
+            id -> Int4,
+            #[max_length = 4096]
+            rosbag_path -> Varchar,
+            start_date_time -> Timestamptz,
+            stop_date_time -> Timestamptz,
+            step_duration_ms -> Int8,
+            number_of_steps -> Int4,
+            created_at -> Timestamptz,
+            dataset_id -> Int4,
+        }
+    }
+
+    diesel::table! {
+        use diesel::sql_types::*;
+        use crate::models::exports::*;
+
+        sensor_data.upload_job_step (id) {
+            id -> Int8,
+            upload_job_id -> Int4,
+            step -> Int4,
+            patch_id -> Int4,
+            completed_at -> Timestamptz,
         }
     }
 
     diesel::joinable!(association_beam_model -> beam (beam_id));
+    diesel::joinable!(association_beam_model -> dataset (dataset_id));
     diesel::joinable!(association_point_model -> beam (beam_id));
+    diesel::joinable!(association_point_model -> dataset (dataset_id));
+    diesel::joinable!(beam -> dataset (dataset_id));
+    diesel::joinable!(dataset -> geometry_selection (geometry_selection_id));
+    diesel::joinable!(feature_geometry_data -> geometry_selection (geometry_selection_id));
+    diesel::joinable!(pass_through_conflict -> beam (beam_id));
+    diesel::joinable!(pass_through_conflict -> dataset (dataset_id));
+    diesel::joinable!(point_cloud_download -> dataset (dataset_id));
+    diesel::joinable!(point_cloud_upload -> dataset (dataset_id));
+    diesel::joinable!(surface_coverage_cell -> dataset (dataset_id));
+    diesel::joinable!(upload_job -> dataset (dataset_id));
+    diesel::joinable!(upload_job_step -> upload_job (upload_job_id));
 
     diesel::allow_tables_to_appear_in_same_query!(
         association_beam_model,
         association_point_model,
         beam,
+        dataset,
         feature_geometry_data,
+        geometry_selection,
+        pass_through_conflict,
         point_cloud_download,
         point_cloud_upload,
+        surface_coverage_cell,
+        upload_job,
+        upload_job_step,
     );
 }
@@ -0,0 +1,475 @@
+use crate::error::Error;
+use crate::models::exports::{FeatureClassEntry, RosMessageIdEntry, ScanPointEntry};
+use diesel::{PgConnection, RunQueryDsl};
+use ecoord::{FrameId, ReferenceFrames};
+use epoint::{PointCloud, PointCloudInfo, PointDataColumnType};
+use itertools::izip;
+use nalgebra::{Matrix4, Point3};
+use polars::frame::DataFrame;
+use polars::prelude::NamedFrom;
+use polars::series::Series;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{create_dir_all, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use tracing::{info, warn};
+
+/// Frame to which the uploaded point clouds are resolved before they are stored as beams.
+const WORLD_FRAME_ID: &str = "world";
+
+/// Semantic label of the points without associated feature.
+const UNLABELED_LABEL: u16 = 0;
+const UNLABELED_NAME: &str = "unlabeled";
+
+/// Reference frames for resolving the scans to the frame of the lidar sensor.
+#[derive(Debug, Clone)]
+pub struct SensorFrame {
+    pub reference_frames: ReferenceFrames,
+    pub frame_id: FrameId,
+}
+
+/// Semantic labels of the CityGML classnames numbered in alphabetical order, where
+/// `0` is reserved for unlabeled points.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub(crate) struct LabelMapping {
+    labels: BTreeMap<String, u16>,
+}
+
+impl LabelMapping {
+    pub(crate) fn from_classnames(classnames: impl IntoIterator<Item = String>) -> Self {
+        let classnames: BTreeSet<String> = classnames.into_iter().collect();
+        Self {
+            labels: classnames.into_iter().zip(UNLABELED_LABEL + 1..).collect(),
+        }
+    }
+
+    pub(crate) fn label(&self, classname: Option<&str>) -> u16 {
+        classname
+            .and_then(|c| self.labels.get(c).copied())
+            .unwrap_or(UNLABELED_LABEL)
+    }
+
+    /// Writes the mapping as `labels` section of a SemanticKITTI configuration.
+    pub(crate) fn write_yaml(&self, mut writer: impl Write) -> Result<(), Error> {
+        writeln!(writer, "labels:")?;
+        // JSON strings are valid YAML scalars
+        writeln!(
+            writer,
+            "  {UNLABELED_LABEL}: {}",
+            serde_json::to_string(UNLABELED_NAME)?
+        )?;
+        for (classname, label) in &self.labels {
+            writeln!(writer, "  {label}: {}", serde_json::to_string(classname)?)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Numbers the features by their id starting at `1`, where features beyond the 16 bit instance
+/// range keep the instance `0`.
+pub(crate) fn derive_instance_ids(feature_ids: &BTreeSet<i64>) -> HashMap<i64, u16> {
+    if feature_ids.len() > u16::MAX as usize {
+        warn!(
+            "Only {} of {} features get an instance id",
+            u16::MAX,
+            feature_ids.len()
+        );
+    }
+    feature_ids.iter().copied().zip(1..=u16::MAX).collect()
+}
+
+/// Combines the semantic label in the lower and the instance id in the upper 16 bits.
+pub(crate) fn encode_label(semantic_label: u16, instance_id: u16) -> u32 {
+    ((instance_id as u32) << 16) | semantic_label as u32
+}
+
+/// Point of a scan in the sensor frame with its encoded label.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ScanPoint {
+    pub ros_point_id: i32,
+    pub point: Point3<f64>,
+    pub remission: f32,
+    pub label: u32,
+}
+
+/// Writes the points sorted by `ros_point_id` as little endian float32 `x y z remission` values
+/// and their labels as uint32 values.
+///
+/// Missing `ros_point_id`s up to the `number_of_points` of the ROS message are filled with zero
+/// points and unlabeled labels, so that the files line up with the points of the message.
+pub(crate) fn write_scan(
+    points: &[ScanPoint],
+    number_of_points: i32,
+    mut scan_writer: impl Write,
+    mut label_writer: impl Write,
+) -> Result<(), Error> {
+    let mut next_ros_point_id = 0;
+    for current_point in points {
+        for _ in next_ros_point_id..current_point.ros_point_id {
+            scan_writer.write_all(&[0; 16])?;
+            label_writer.write_all(&encode_label(UNLABELED_LABEL, 0).to_le_bytes())?;
+        }
+
+        for value in [
+            current_point.point.x as f32,
+            current_point.point.y as f32,
+            current_point.point.z as f32,
+            current_point.remission,
+        ] {
+            scan_writer.write_all(&value.to_le_bytes())?;
+        }
+        label_writer.write_all(&current_point.label.to_le_bytes())?;
+        next_ros_point_id = current_point.ros_point_id + 1;
+    }
+    for _ in next_ros_point_id..number_of_points {
+        scan_writer.write_all(&[0; 16])?;
+        label_writer.write_all(&encode_label(UNLABELED_LABEL, 0).to_le_bytes())?;
+    }
+
+    scan_writer.flush()?;
+    label_writer.flush()?;
+    Ok(())
+}
+
+/// Formats the upper 3x4 part of the pose row by row as in the KITTI poses file.
+pub(crate) fn format_pose(pose: &Matrix4<f64>) -> String {
+    (0..3)
+        .flat_map(|row| (0..4).map(move |column| pose[(row, column)].to_string()))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Writes the scans of a dataset as SemanticKITTI sequence and returns the number of scans.
+///
+/// Only the ROS messages contained in `ros_message_sizes` are exported, and each scan is padded to
+/// the number of points of its message. The scans are named by their index, which is the line of
+/// their pose and time, and the `ros_message_id` of each scan is written to a separate line of
+/// `ros_message_ids.txt`.
+pub fn write_semantic_kitti_sequence(
+    connection: &mut PgConnection,
+    dataset_id: i32,
+    directory_path: &Path,
+    sequence: u32,
+    sensor_frame: &SensorFrame,
+    ros_message_sizes: &HashMap<u32, u32>,
+) -> Result<usize, Error> {
+    let sequence_path = directory_path
+        .join("sequences")
+        .join(format!("{sequence:02}"));
+    let scan_directory_path = sequence_path.join("velodyne");
+    let label_directory_path = sequence_path.join("labels");
+    create_dir_all(&scan_directory_path)?;
+    create_dir_all(&label_directory_path)?;
+
+    let features = load_associated_features(connection, dataset_id)?;
+    let label_mapping =
+        LabelMapping::from_classnames(features.iter().filter_map(|f| f.classname.clone()));
+    let instance_ids = derive_instance_ids(&features.iter().map(|f| f.feature_id).collect());
+    label_mapping.write_yaml(BufWriter::new(File::create(
+        directory_path.join("labels.yaml"),
+    )?))?;
+
+    // the poses are already given in the frame of the sensor
+    let mut calibration_writer = BufWriter::new(File::create(sequence_path.join("calib.txt"))?);
+    writeln!(
+        calibration_writer,
+        "Tr: {}",
+        format_pose(&Matrix4::identity())
+    )?;
+    calibration_writer.flush()?;
+
+    let mut poses_writer = BufWriter::new(File::create(sequence_path.join("poses.txt"))?);
+    let mut times_writer = BufWriter::new(File::create(sequence_path.join("times.txt"))?);
+    let mut ros_message_ids_writer =
+        BufWriter::new(File::create(sequence_path.join("ros_message_ids.txt"))?);
+    let mut first_scan_time: Option<f64> = None;
+    let mut number_of_scans: usize = 0;
+
+    // the messages of other sensors are contained in the dataset but not in the message sizes
+    let messages: Vec<(i32, i32)> = load_ros_message_ids(connection, dataset_id)?
+        .into_iter()
+        .filter_map(|id| ros_message_sizes.get(&(id as u32)).map(|s| (id, *s as i32)))
+        .collect();
+    for (ros_message_id, number_of_points) in messages {
+        let entries = load_scan_points(connection, dataset_id, ros_message_id)?;
+        let (Some(first_entry), Some(last_entry)) = (entries.first(), entries.last()) else {
+            continue;
+        };
+        if last_entry.ros_point_id >= number_of_points {
+            return Err(Error::RosMessageSizeMismatch {
+                ros_message_id,
+                number_of_points,
+            });
+        }
+        let scan_time =
+            first_entry.timestamp_sec as f64 + first_entry.timestamp_nanosec as f64 * 1.0e-9;
+        let reflections: Vec<Point3<f64>> =
+            entries.iter().map(|e| Point3::new(e.x, e.y, e.z)).collect();
+
+        let timestamps: Vec<(i64, u32)> = entries
+            .iter()
+            .map(|e| (e.timestamp_sec as i64, e.timestamp_nanosec as u32))
+            .collect();
+        let points = resolve_points(
+            &reflections,
+            &timestamps,
+            FrameId::from(WORLD_FRAME_ID),
+            sensor_frame.frame_id.clone(),
+            &sensor_frame.reference_frames,
+        )?;
+
+        // the pose is derived from the resolved origin and unit axes of the sensor frame
+        let axes = resolve_points(
+            &[
+                Point3::origin(),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(0.0, 1.0, 0.0),
+                Point3::new(0.0, 0.0, 1.0),
+            ],
+            &[timestamps[0]; 4],
+            sensor_frame.frame_id.clone(),
+            FrameId::from(WORLD_FRAME_ID),
+            &sensor_frame.reference_frames,
+        )?;
+        let mut pose = Matrix4::identity();
+        for row in 0..3 {
+            for column in 0..3 {
+                pose[(row, column)] = axes[column + 1][row] - axes[0][row];
+            }
+            pose[(row, 3)] = axes[0][row];
+        }
+
+        let scan_points: Vec<ScanPoint> = entries
+            .iter()
+            .zip(points)
+            .map(|(e, point)| ScanPoint {
+                ros_point_id: e.ros_point_id,
+                point,
+                remission: e.intensity as f32,
+                label: encode_label(
+                    label_mapping.label(e.classname.as_deref()),
+                    e.feature_id
+                        .and_then(|f| instance_ids.get(&f).copied())
+                        .unwrap_or(0),
+                ),
+            })
+            .collect();
+        let file_name = format!("{number_of_scans:06}");
+        write_scan(
+            &scan_points,
+            number_of_points,
+            BufWriter::new(File::create(
+                scan_directory_path.join(format!("{file_name}.bin")),
+            )?),
+            BufWriter::new(File::create(
+                label_directory_path.join(format!("{file_name}.label")),
+            )?),
+        )?;
+
+        writeln!(poses_writer, "{}", format_pose(&pose))?;
+        let first_scan_time = *first_scan_time.get_or_insert(scan_time);
+        writeln!(times_writer, "{:.6}", scan_time - first_scan_time)?;
+        writeln!(ros_message_ids_writer, "{ros_message_id}")?;
+        number_of_scans += 1;
+    }
+    poses_writer.flush()?;
+    times_writer.flush()?;
+    ros_message_ids_writer.flush()?;
+
+    info!(
+        "Wrote {} scans to {}",
+        number_of_scans,
+        sequence_path.display()
+    );
+    Ok(number_of_scans)
+}
+
+/// Transforms the points with their timestamps from the source to the target frame.
+fn resolve_points(
+    points: &[Point3<f64>],
+    timestamps: &[(i64, u32)],
+    source_frame_id: FrameId,
+    target_frame_id: FrameId,
+    reference_frames: &ReferenceFrames,
+) -> Result<Vec<Point3<f64>>, Error> {
+    let data_frame = DataFrame::new(vec![
+        Series::new(
+            PointDataColumnType::X.as_str(),
+            points.iter().map(|p| p.x).collect::<Vec<f64>>(),
+        ),
+        Series::new(
+            PointDataColumnType::Y.as_str(),
+            points.iter().map(|p| p.y).collect::<Vec<f64>>(),
+        ),
+        Series::new(
+            PointDataColumnType::Z.as_str(),
+            points.iter().map(|p| p.z).collect::<Vec<f64>>(),
+        ),
+        Series::new(
+            PointDataColumnType::TimestampSeconds.as_str(),
+            timestamps.iter().map(|t| t.0).collect::<Vec<i64>>(),
+        ),
+        Series::new(
+            PointDataColumnType::TimestampNanoSeconds.as_str(),
+            timestamps.iter().map(|t| t.1).collect::<Vec<u32>>(),
+        ),
+    ])?;
+    let mut point_cloud = PointCloud::from_data_frame(
+        data_frame,
+        PointCloudInfo::new(Some(source_frame_id)),
+        reference_frames.clone(),
+    )?;
+    point_cloud.resolve_to_frame(target_frame_id)?;
+
+    let resolved_points = izip!(
+        point_cloud.point_data.get_x_values(),
+        point_cloud.point_data.get_y_values(),
+        point_cloud.point_data.get_z_values()
+    )
+    .map(|(x, y, z)| Point3::new(x.unwrap(), y.unwrap(), z.unwrap()))
+    .collect();
+    Ok(resolved_points)
+}
+
+/// Loads the features with associated points of a dataset ordered by their id.
+pub(crate) fn load_associated_features(
+    connection: &mut PgConnection,
+    dataset_id: i32,
+) -> Result<Vec<FeatureClassEntry>, Error> {
+    let query = format!(
+        "SELECT DISTINCT apm.feature_id, oc.classname
+FROM sensor_data.association_point_model as apm
+JOIN citydb.feature as f ON apm.feature_id = f.id
+LEFT JOIN citydb.objectclass as oc ON f.objectclass_id = oc.id
+WHERE apm.dataset_id = {dataset_id}
+ORDER BY apm.feature_id;"
+    );
+    let features: Vec<FeatureClassEntry> = diesel::sql_query(query).load(connection)?;
+    Ok(features)
+}
+
+fn load_ros_message_ids(connection: &mut PgConnection, dataset_id: i32) -> Result<Vec<i32>, Error> {
+    let query = format!(
+        "SELECT DISTINCT ros_message_id
+FROM sensor_data.beam
+WHERE dataset_id = {dataset_id}
+ORDER BY ros_message_id;"
+    );
+    let entries: Vec<RosMessageIdEntry> = diesel::sql_query(query).load(connection)?;
+    Ok(entries.into_iter().map(|e| e.ros_message_id).collect())
+}
+
+/// Loads the points of a ROS message ordered by `ros_point_id`, where each point gets the
+/// class of its nearest associated feature.
+fn load_scan_points(
+    connection: &mut PgConnection,
+    dataset_id: i32,
+    ros_message_id: i32,
+) -> Result<Vec<ScanPointEntry>, Error> {
+    let query = format!(
+        "SELECT DISTINCT ON (b.ros_point_id)
+    b.ros_point_id,
+    ST_X(b.reflection) AS x,
+    ST_Y(b.reflection) AS y,
+    ST_Z(b.reflection) AS z,
+    b.intensity,
+    b.timestamp_sec,
+    b.timestamp_nanosec,
+    apm.feature_id,
+    oc.classname
+FROM sensor_data.beam as b
+LEFT JOIN sensor_data.association_point_model as apm ON b.id = apm.beam_id
+LEFT JOIN citydb.feature as f ON apm.feature_id = f.id
+LEFT JOIN citydb.objectclass as oc ON f.objectclass_id = oc.id
+WHERE b.dataset_id = {dataset_id} AND b.ros_message_id = {ros_message_id}
+ORDER BY b.ros_point_id, apm.distance NULLS LAST, apm.feature_id;"
+    );
+    let entries: Vec<ScanPointEntry> = diesel::sql_query(query).load(connection)?;
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label_mapping() {
+        let label_mapping = LabelMapping::from_classnames(vec![
+            "WallSurface".to_string(),
+            "RoofSurface".to_string(),
+            "WallSurface".to_string(),
+        ]);
+
+        assert_eq!(label_mapping.label(Some("RoofSurface")), 1);
+        assert_eq!(label_mapping.label(Some("WallSurface")), 2);
+        assert_eq!(label_mapping.label(Some("Door")), UNLABELED_LABEL);
+        assert_eq!(label_mapping.label(None), UNLABELED_LABEL);
+
+        let mut buffer: Vec<u8> = Vec::new();
+        label_mapping.write_yaml(&mut buffer).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "labels:\n  0: \"unlabeled\"\n  1: \"RoofSurface\"\n  2: \"WallSurface\"\n"
+        );
+    }
+
+    #[test]
+    fn test_encode_label() {
+        assert_eq!(encode_label(2, 0), 2);
+        assert_eq!(encode_label(2, 3), 0x0003_0002);
+        assert_eq!(
+            derive_instance_ids(&BTreeSet::from([42, 7])),
+            HashMap::from([(7, 1), (42, 2)])
+        );
+    }
+
+    #[test]
+    fn test_write_scan_fills_missing_points() {
+        let points = vec![
+            ScanPoint {
+                ros_point_id: 1,
+                point: Point3::new(1.0, 2.0, 3.0),
+                remission: 0.5,
+                label: encode_label(1, 1),
+            },
+            ScanPoint {
+                ros_point_id: 3,
+                point: Point3::new(-1.0, 0.0, 0.25),
+                remission: 1.0,
+                label: encode_label(2, 0),
+            },
+        ];
+
+        let mut scan_buffer: Vec<u8> = Vec::new();
+        let mut label_buffer: Vec<u8> = Vec::new();
+        write_scan(&points, 6, &mut scan_buffer, &mut label_buffer).unwrap();
+
+        let values: Vec<f32> = scan_buffer
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        let labels: Vec<u32> = label_buffer
+            .chunks_exact(4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 0.5, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.25, 1.0,
+                0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0
+            ]
+        );
+        assert_eq!(labels, vec![0, 0x0001_0001, 0, 2, 0, 0]);
+    }
+
+    #[test]
+    fn test_format_pose() {
+        let pose = Matrix4::new_translation(&nalgebra::Vector3::new(691000.5, 5336000.25, 515.0));
+
+        assert_eq!(
+            format_pose(&pose),
+            "1 0 0 691000.5 0 1 0 5336000.25 0 0 1 515"
+        );
+    }
+}
@@ -0,0 +1,334 @@
+use crate::error::Error;
+use crate::sphere::{SphericalRasterizationAxis, SphericalRasterizationTransform};
+use ecoord::FrameId;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Angular raster of a lidar sensor along the azimuth or elevation axis in degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct SensorAxisProfile {
+    pub field_of_view_min_deg: f64,
+    pub field_of_view_max_deg: f64,
+    pub resolution_deg: f64,
+    #[serde(default)]
+    pub offset_deg: f64,
+}
+
+impl SensorAxisProfile {
+    pub fn new(
+        field_of_view_min_deg: f64,
+        field_of_view_max_deg: f64,
+        resolution_deg: f64,
+        offset_deg: f64,
+    ) -> Self {
+        Self {
+            field_of_view_min_deg,
+            field_of_view_max_deg,
+            resolution_deg,
+            offset_deg,
+        }
+    }
+
+    pub(crate) fn rasterization_axis(&self) -> SphericalRasterizationAxis {
+        SphericalRasterizationAxis::from_deg(
+            self.field_of_view_min_deg,
+            self.field_of_view_max_deg,
+            self.resolution_deg,
+            self.offset_deg,
+        )
+    }
+}
+
+/// Scan pattern of a lidar sensor used for rasterizing its point clouds into range images.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LidarSensorProfile {
+    pub name: String,
+    pub azimuth: SensorAxisProfile,
+    pub elevation: SensorAxisProfile,
+}
+
+impl LidarSensorProfile {
+    pub fn new(
+        name: impl Into<String>,
+        azimuth: SensorAxisProfile,
+        elevation: SensorAxisProfile,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            azimuth,
+            elevation,
+        }
+    }
+
+    pub(crate) fn rasterization_transform(&self) -> SphericalRasterizationTransform {
+        SphericalRasterizationTransform::new(
+            self.azimuth.rasterization_axis(),
+            self.elevation.rasterization_axis(),
+        )
+    }
+
+    /// Returns the profile of a sensor with a full horizontal field of view and evenly spaced
+    /// channels, whose elevations are centered in the raster cells.
+    fn with_uniform_channels(
+        name: &str,
+        azimuth_resolution_deg: f64,
+        elevation_min_deg: f64,
+        elevation_max_deg: f64,
+        number_of_channels: u32,
+    ) -> Self {
+        let elevation_resolution_deg =
+            (elevation_max_deg - elevation_min_deg) / (number_of_channels - 1) as f64;
+        // shifts the cell boundaries, so that the lowest channel and thereby all others lie in the
+        // middle of a cell
+        let elevation_offset_deg =
+            elevation_resolution_deg / 2.0 - elevation_min_deg.rem_euclid(elevation_resolution_deg);
+        Self::new(
+            name,
+            SensorAxisProfile::new(-180.0, 180.0, azimuth_resolution_deg, 0.0),
+            SensorAxisProfile::new(
+                elevation_min_deg,
+                elevation_max_deg,
+                elevation_resolution_deg,
+                elevation_offset_deg,
+            ),
+        )
+    }
+
+    /// Returns the profiles of commonly used sensors at a rotation rate of 10 Hz.
+    pub fn built_in_profiles() -> Vec<LidarSensorProfile> {
+        vec![
+            // https://velodynelidar.com/wp-content/uploads/2019/12/63-9243-Rev-E-VLP-16-User-Manual.pdf
+            Self::new(
+                "VLP-16",
+                SensorAxisProfile::new(-180.0, 180.0, 0.1990656, 0.0),
+                SensorAxisProfile::new(-15.0, 15.0, 1.875, 1.875 / 2.0),
+            ),
+            Self::with_uniform_channels("HDL-32E", 0.16, -30.67, 10.67, 32),
+            Self::with_uniform_channels("OS1-64", 360.0 / 1024.0, -22.5, 22.5, 64),
+            Self::with_uniform_channels("OS1-128", 360.0 / 1024.0, -22.5, 22.5, 128),
+            Self::with_uniform_channels("PandarXT32", 0.18, -16.0, 15.0, 32),
+        ]
+    }
+}
+
+/// Content of a sensor profile file in TOML or JSON.
+#[derive(Debug, Clone, Deserialize)]
+struct SensorProfileFile {
+    #[serde(default)]
+    profiles: Vec<LidarSensorProfile>,
+    #[serde(default)]
+    frames: BTreeMap<String, String>,
+}
+
+/// Registry of lidar sensor profiles and their assignment to frame ids.
+///
+/// The registry contains the [built-in profiles](LidarSensorProfile::built_in_profiles) and can
+/// be extended by files of the form:
+///
+/// ```toml
+/// [[profiles]]
+/// name = "OS1-64-2048"
+/// azimuth = { field_of_view_min_deg = -180.0, field_of_view_max_deg = 180.0, resolution_deg = 0.17578125 }
+/// elevation = { field_of_view_min_deg = -22.5, field_of_view_max_deg = 22.5, resolution_deg = 0.714, offset_deg = 0.357 }
+///
+/// [frames]
+/// lidar_front_center = "OS1-64-2048"
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensorProfileRegistry {
+    profiles: BTreeMap<String, LidarSensorProfile>,
+    frames: BTreeMap<String, String>,
+}
+
+impl Default for SensorProfileRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            profiles: BTreeMap::new(),
+            frames: BTreeMap::new(),
+        };
+        for profile in LidarSensorProfile::built_in_profiles() {
+            registry.add_profile(profile);
+        }
+        registry
+    }
+}
+
+impl SensorProfileRegistry {
+    /// Adds a profile, which replaces a registered profile of the same name.
+    pub fn add_profile(&mut self, profile: LidarSensorProfile) {
+        self.profiles.insert(profile.name.clone(), profile);
+    }
+
+    /// Adds the profiles and frame assignments of a `.toml` or `.json` file.
+    pub fn add_file(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        let file: SensorProfileFile = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&content)?,
+            Some("json") => serde_json::from_str(&content)?,
+            _ => {
+                return Err(Error::UnsupportedSensorProfileFile(
+                    path.display().to_string(),
+                ))
+            }
+        };
+
+        for profile in file.profiles {
+            self.add_profile(profile);
+        }
+        for (frame_id, profile_name) in file.frames {
+            self.assign_frame(frame_id, &profile_name)?;
+        }
+        Ok(())
+    }
+
+    /// Assigns the profile to the frame id of a lidar sensor.
+    pub fn assign_frame(
+        &mut self,
+        frame_id: impl Into<String>,
+        profile_name: &str,
+    ) -> Result<(), Error> {
+        if !self.profiles.contains_key(profile_name) {
+            return Err(Error::UnknownSensorProfile(profile_name.to_string()));
+        }
+        self.frames
+            .insert(frame_id.into(), profile_name.to_string());
+        Ok(())
+    }
+
+    pub fn get_profile(&self, profile_name: &str) -> Option<&LidarSensorProfile> {
+        self.profiles.get(profile_name)
+    }
+
+    /// Returns the profile assigned to the frame id.
+    pub fn get_frame_profile(&self, frame_id: &FrameId) -> Option<&LidarSensorProfile> {
+        self.frames
+            .get(&frame_id.to_string())
+            .and_then(|p| self.profiles.get(p))
+    }
+
+    pub fn profile_names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(|n| n.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_built_in_profiles() {
+        let registry = SensorProfileRegistry::default();
+
+        let profile = registry.get_profile("OS1-64").unwrap();
+        assert!((profile.elevation.resolution_deg - 45.0 / 63.0).abs() < 1e-12);
+        assert_eq!(profile.azimuth.resolution_deg, 0.3515625);
+        assert!(registry.profile_names().any(|n| n == "VLP-16"));
+    }
+
+    #[test]
+    fn test_built_in_channels_in_distinct_rows() {
+        let registry = SensorProfileRegistry::default();
+
+        for (name, elevation_min_deg, elevation_max_deg, number_of_channels) in [
+            ("VLP-16", -15.0, 15.0, 16),
+            ("HDL-32E", -30.67, 10.67, 32),
+            ("OS1-64", -22.5, 22.5, 64),
+            ("OS1-128", -22.5, 22.5, 128),
+            ("PandarXT32", -16.0, 15.0, 32),
+        ] {
+            let axis = registry
+                .get_profile(name)
+                .unwrap()
+                .elevation
+                .rasterization_axis();
+            let channel_spacing_deg =
+                (elevation_max_deg - elevation_min_deg) / (number_of_channels - 1) as f64;
+            let rows: Vec<i32> = (0..number_of_channels)
+                .map(|c| {
+                    let elevation_deg = elevation_min_deg + c as f64 * channel_spacing_deg;
+                    axis.transform_to_grid_cell_index(elevation_deg.to_radians())
+                })
+                .collect();
+
+            assert!(
+                rows.windows(2).all(|w| w[0] < w[1]),
+                "channels of {name} share rows: {rows:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_assign_unknown_profile() {
+        let mut registry = SensorProfileRegistry::default();
+
+        assert!(registry
+            .assign_frame("lidar_front_center", "VLP-64")
+            .is_err());
+        registry
+            .assign_frame("lidar_front_center", "VLP-16")
+            .unwrap();
+        assert_eq!(
+            registry
+                .get_frame_profile(&FrameId::from("lidar_front_center"))
+                .unwrap()
+                .name,
+            "VLP-16"
+        );
+    }
+
+    #[test]
+    fn test_add_toml_file() {
+        let path = std::env::temp_dir().join("sensor_data_analyzer_sensor_profiles.toml");
+        let mut file = fs::File::create(&path).unwrap();
+        write!(
+            file,
+            r#"
+[[profiles]]
+name = "OS1-64-2048"
+azimuth = {{ field_of_view_min_deg = -180.0, field_of_view_max_deg = 180.0, resolution_deg = 0.17578125 }}
+elevation = {{ field_of_view_min_deg = -22.5, field_of_view_max_deg = 22.5, resolution_deg = 0.714, offset_deg = 0.357 }}
+
+[frames]
+lidar_front_center = "OS1-64-2048"
+lidar_rear = "HDL-32E"
+"#
+        )
+        .unwrap();
+
+        let mut registry = SensorProfileRegistry::default();
+        registry.add_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let profile = registry
+            .get_frame_profile(&FrameId::from("lidar_front_center"))
+            .unwrap();
+        assert_eq!(profile.azimuth.offset_deg, 0.0);
+        assert_eq!(profile.elevation.offset_deg, 0.357);
+        assert_eq!(
+            registry
+                .get_frame_profile(&FrameId::from("lidar_rear"))
+                .unwrap()
+                .name,
+            "HDL-32E"
+        );
+    }
+
+    #[test]
+    fn test_add_json_file() {
+        let path = std::env::temp_dir().join("sensor_data_analyzer_sensor_profiles.json");
+        fs::write(&path, r#"{"frames": {"lidar_front_center": "PandarXT32"}}"#).unwrap();
+
+        let mut registry = SensorProfileRegistry::default();
+        registry.add_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let profile = registry
+            .get_frame_profile(&FrameId::from("lidar_front_center"))
+            .unwrap();
+        assert_eq!(profile.elevation.resolution_deg, 1.0);
+    }
+}
@@ -91,6 +91,14 @@ impl SphericalRasterizationAxis {
         }
     }
 
+    pub fn field_of_view_min(&self) -> f64 {
+        self.field_of_view_min
+    }
+
+    pub fn field_of_view_max(&self) -> f64 {
+        self.field_of_view_max
+    }
+
     pub fn offset(&self) -> f64 {
         self.offset
     }
@@ -112,7 +120,7 @@ impl SphericalRasterizationAxis {
     }*/
 
     pub fn transform_to_grid_cell_index(&self, value: f64) -> i32 {
-        ((value + self.offset) / self.resolution) as i32
+        ((value + self.offset) / self.resolution).floor() as i32
     }
 
     pub fn transform_to_continues_value_rad(&self, index: i32) -> f64 {
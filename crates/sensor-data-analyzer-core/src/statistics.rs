@@ -0,0 +1,391 @@
+use crate::error::Error;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::io::Write;
+
+/// Output format of the statistics and change reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum StatisticsFormat {
+    /// Human-readable table.
+    #[default]
+    Table,
+    /// One JSON object per dataset in an array.
+    Json,
+    /// One row per metric with the columns `dataset`, `metric`, `key` and `value`.
+    Csv,
+}
+
+/// Statistics on the uploaded and associated sensor data of a dataset.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DatasetStatistics {
+    pub dataset: String,
+    pub number_of_patches: i64,
+    pub number_of_beams: i64,
+    /// Number of beams associated with at least one feature by the point-model association
+    pub number_of_associated_beams: i64,
+    /// Share of associated beams in all beams
+    pub association_ratio: f64,
+    /// Number of intersections between beams and surfaces of the beam-model association
+    pub number_of_beam_intersections: i64,
+    /// Number of beams intersecting at least one surface
+    pub number_of_intersected_beams: i64,
+    pub start_date_time: Option<DateTime<Utc>>,
+    pub stop_date_time: Option<DateTime<Utc>>,
+    /// Area in square meters of the surface coverage cells with associated points, which is only
+    /// available after the surface coverage was computed
+    pub observed_surface_area: f64,
+    /// Area in square meters of the surfaces within the extent of the reflections
+    pub total_surface_area: f64,
+    /// Number of surfaces passed through by beams before their reflection
+    pub number_of_pass_through_conflicts: i64,
+    /// Number of beams passing through at least one surface
+    pub number_of_pass_through_beams: i64,
+    pub classes: Vec<ClassStatistics>,
+    pub features: Vec<FeatureStatistics>,
+    pub distance_histogram: Vec<DistanceHistogramBin>,
+}
+
+/// Associated points of all features of a CityGML class.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClassStatistics {
+    pub classname: String,
+    pub number_of_features: i64,
+    pub number_of_points: i64,
+}
+
+/// Associated points of a single feature.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FeatureStatistics {
+    pub gml_id: String,
+    pub classname: String,
+    pub number_of_points: i64,
+}
+
+/// Number of point-model associations with a surface distance within `[lower, upper)` meters.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DistanceHistogramBin {
+    pub lower: f64,
+    pub upper: f64,
+    pub number_of_points: i64,
+}
+
+/// Writes the statistics of the datasets in the given format.
+pub fn write_statistics(
+    statistics: &[DatasetStatistics],
+    format: StatisticsFormat,
+    mut writer: impl Write,
+) -> Result<(), Error> {
+    match format {
+        StatisticsFormat::Table => {
+            for current_statistics in statistics {
+                write!(writer, "{}", current_statistics.to_table())?;
+            }
+        }
+        StatisticsFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, statistics)?;
+            writeln!(writer)?;
+        }
+        StatisticsFormat::Csv => {
+            let mut csv_writer = csv::Writer::from_writer(writer);
+            csv_writer.write_record(["dataset", "metric", "key", "value"])?;
+            for current_statistics in statistics {
+                for record in current_statistics.to_records() {
+                    csv_writer.write_record(&record)?;
+                }
+            }
+            csv_writer.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+impl DatasetStatistics {
+    /// Derives the surface distance histogram from the number of points per bin index for bins of
+    /// the given width.
+    pub(crate) fn derive_distance_histogram(
+        bin_width: f64,
+        bin_counts: &[(i32, i64)],
+    ) -> Vec<DistanceHistogramBin> {
+        bin_counts
+            .iter()
+            .map(|(bin, number_of_points)| DistanceHistogramBin {
+                lower: round_micrometers(*bin as f64 * bin_width),
+                upper: round_micrometers((*bin + 1) as f64 * bin_width),
+                number_of_points: *number_of_points,
+            })
+            .collect()
+    }
+
+    fn to_records(&self) -> Vec<[String; 4]> {
+        let record = |metric: &str, key: &str, value: String| -> [String; 4] {
+            [
+                self.dataset.clone(),
+                metric.to_string(),
+                key.to_string(),
+                value,
+            ]
+        };
+        let optional_date_time = |date_time: Option<DateTime<Utc>>| -> String {
+            date_time.map(|d| d.to_rfc3339()).unwrap_or_default()
+        };
+
+        let mut records = vec![
+            record("number_of_patches", "", self.number_of_patches.to_string()),
+            record("number_of_beams", "", self.number_of_beams.to_string()),
+            record(
+                "number_of_associated_beams",
+                "",
+                self.number_of_associated_beams.to_string(),
+            ),
+            record("association_ratio", "", self.association_ratio.to_string()),
+            record(
+                "number_of_beam_intersections",
+                "",
+                self.number_of_beam_intersections.to_string(),
+            ),
+            record(
+                "number_of_intersected_beams",
+                "",
+                self.number_of_intersected_beams.to_string(),
+            ),
+            record(
+                "start_date_time",
+                "",
+                optional_date_time(self.start_date_time),
+            ),
+            record(
+                "stop_date_time",
+                "",
+                optional_date_time(self.stop_date_time),
+            ),
+            record(
+                "observed_surface_area",
+                "",
+                self.observed_surface_area.to_string(),
+            ),
+            record(
+                "total_surface_area",
+                "",
+                self.total_surface_area.to_string(),
+            ),
+            record(
+                "number_of_pass_through_conflicts",
+                "",
+                self.number_of_pass_through_conflicts.to_string(),
+            ),
+            record(
+                "number_of_pass_through_beams",
+                "",
+                self.number_of_pass_through_beams.to_string(),
+            ),
+        ];
+        for class in &self.classes {
+            records.push(record(
+                "class_number_of_features",
+                &class.classname,
+                class.number_of_features.to_string(),
+            ));
+            records.push(record(
+                "class_number_of_points",
+                &class.classname,
+                class.number_of_points.to_string(),
+            ));
+        }
+        for feature in &self.features {
+            records.push(record(
+                "feature_number_of_points",
+                &feature.gml_id,
+                feature.number_of_points.to_string(),
+            ));
+        }
+        for bin in &self.distance_histogram {
+            records.push(record(
+                "distance_histogram",
+                &format!("{}-{}", bin.lower, bin.upper),
+                bin.number_of_points.to_string(),
+            ));
+        }
+
+        records
+    }
+
+    fn to_table(&self) -> String {
+        let mut table = format!("Dataset {}\n", self.dataset);
+        let mut row = |name: &str, value: String| {
+            table.push_str(&format!("  {name:<32}{value}\n"));
+        };
+
+        row("Patches", self.number_of_patches.to_string());
+        row("Beams", self.number_of_beams.to_string());
+        row(
+            "Associated beams",
+            format!(
+                "{} ({:.2} %)",
+                self.number_of_associated_beams,
+                self.association_ratio * 100.0
+            ),
+        );
+        row(
+            "Beam intersections",
+            format!(
+                "{} (intersected beams: {})",
+                self.number_of_beam_intersections, self.number_of_intersected_beams
+            ),
+        );
+        row(
+            "Temporal coverage",
+            match (self.start_date_time, self.stop_date_time) {
+                (Some(start_date_time), Some(stop_date_time)) => format!(
+                    "{} - {} ({:.3} s)",
+                    start_date_time,
+                    stop_date_time,
+                    (stop_date_time - start_date_time).num_milliseconds() as f64 / 1000.0
+                ),
+                _ => "-".to_string(),
+            },
+        );
+        row(
+            "Observed surface area",
+            format!(
+                "{:.2} m² of {:.2} m²",
+                self.observed_surface_area, self.total_surface_area
+            ),
+        );
+        row(
+            "Pass-through conflicts",
+            format!(
+                "{} (passing beams: {})",
+                self.number_of_pass_through_conflicts, self.number_of_pass_through_beams
+            ),
+        );
+
+        row("Points per class", String::new());
+        for class in &self.classes {
+            row(
+                &format!("  {}", class.classname),
+                format!(
+                    "{} ({} features)",
+                    class.number_of_points, class.number_of_features
+                ),
+            );
+        }
+        row("Points per feature", String::new());
+        for feature in &self.features {
+            row(
+                &format!("  {}", feature.gml_id),
+                format!("{} ({})", feature.number_of_points, feature.classname),
+            );
+        }
+        row("Surface distance histogram", String::new());
+        for bin in &self.distance_histogram {
+            row(
+                &format!("  [{:.3} m, {:.3} m)", bin.lower, bin.upper),
+                bin.number_of_points.to_string(),
+            );
+        }
+
+        table
+    }
+}
+
+/// Rounds a distance in meters to micrometers to avoid floating point artifacts.
+fn round_micrometers(value: f64) -> f64 {
+    (value * 1.0e6).round() / 1.0e6
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn synthetic_statistics() -> DatasetStatistics {
+        DatasetStatistics {
+            dataset: "default".to_string(),
+            number_of_patches: 2,
+            number_of_beams: 100,
+            number_of_associated_beams: 25,
+            association_ratio: 0.25,
+            number_of_beam_intersections: 12,
+            number_of_intersected_beams: 10,
+            start_date_time: DateTime::from_timestamp(1605702866, 0),
+            stop_date_time: DateTime::from_timestamp(1605702867, 500_000_000),
+            observed_surface_area: 120.5,
+            total_surface_area: 1000.0,
+            number_of_pass_through_conflicts: 3,
+            number_of_pass_through_beams: 2,
+            classes: vec![ClassStatistics {
+                classname: "WallSurface".to_string(),
+                number_of_features: 1,
+                number_of_points: 25,
+            }],
+            features: vec![FeatureStatistics {
+                gml_id: "DEBY_LOD2_4959457".to_string(),
+                classname: "WallSurface".to_string(),
+                number_of_points: 25,
+            }],
+            distance_histogram: DatasetStatistics::derive_distance_histogram(
+                0.1,
+                &[(0, 20), (2, 5)],
+            ),
+        }
+    }
+
+    #[test]
+    fn test_distance_histogram() {
+        let histogram = DatasetStatistics::derive_distance_histogram(0.5, &[(0, 20), (3, 5)]);
+
+        assert_eq!(
+            histogram,
+            vec![
+                DistanceHistogramBin {
+                    lower: 0.0,
+                    upper: 0.5,
+                    number_of_points: 20
+                },
+                DistanceHistogramBin {
+                    lower: 1.5,
+                    upper: 2.0,
+                    number_of_points: 5
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let mut buffer: Vec<u8> = Vec::new();
+        write_statistics(
+            &[synthetic_statistics()],
+            StatisticsFormat::Csv,
+            &mut buffer,
+        )
+        .unwrap();
+        let csv = String::from_utf8(buffer).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines[0], "dataset,metric,key,value");
+        assert_eq!(lines[2], "default,number_of_beams,,100");
+        assert_eq!(
+            lines[7],
+            "default,start_date_time,,2020-11-18T12:34:26+00:00"
+        );
+        assert!(lines.contains(&"default,feature_number_of_points,DEBY_LOD2_4959457,25"));
+        assert!(lines.contains(&"default,distance_histogram,0.2-0.3,5"));
+    }
+
+    #[test]
+    fn test_write_json() {
+        let mut buffer: Vec<u8> = Vec::new();
+        write_statistics(
+            &[synthetic_statistics()],
+            StatisticsFormat::Json,
+            &mut buffer,
+        )
+        .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+
+        assert_eq!(json[0]["number_of_associated_beams"], 25);
+        assert_eq!(json[0]["classes"][0]["classname"], "WallSurface");
+        assert_eq!(json[0]["distance_histogram"][1]["number_of_points"], 5);
+    }
+}
@@ -0,0 +1,407 @@
+use crate::association::dataset_surface_condition;
+use crate::city_model_enrichment::SURFACE_DISTANCE_TOLERANCE;
+use crate::database_copy::copy_surface_coverage_cells;
+use crate::error::Error;
+use crate::models::exports::{SurfacePointEntry, SurfaceVertexEntry};
+use diesel::{PgConnection, RunQueryDsl};
+use egraphics::{Triangle, TriangleMesh, Vertex};
+use nalgebra::{Point2, Point3, Vector3};
+use std::collections::{BTreeMap, HashMap};
+
+/// Square cell of the raster of a surface polygon.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SurfaceCell {
+    pub column_index: i32,
+    pub row_index: i32,
+    /// Corners in counterclockwise order seen from the front of the surface
+    pub corners: [Point3<f64>; 4],
+    pub number_of_points: i32,
+}
+
+/// Raster of square cells in the plane of a planar surface polygon.
+///
+/// The rows run along the horizontal axis of the plane, so that the cells of facades are aligned
+/// with the ground. For horizontal surfaces, the rows run along the x-axis.
+#[derive(Debug, Clone)]
+pub(crate) struct SurfaceRaster {
+    origin: Point3<f64>,
+    u_axis: Vector3<f64>,
+    v_axis: Vector3<f64>,
+    cell_size: f64,
+    /// Rings of the polygon in plane coordinates relative to the origin
+    rings: Vec<Vec<Point2<f64>>>,
+    number_of_columns: i32,
+    number_of_rows: i32,
+}
+
+impl SurfaceRaster {
+    /// Creates the raster of a polygon given by its exterior ring followed by its interior rings.
+    ///
+    /// Returns `None` for degenerate polygons without a normal.
+    pub(crate) fn new(rings: &[Vec<Point3<f64>>], cell_size: f64) -> Option<Self> {
+        let exterior_ring = rings.first()?;
+        let normal = newell_normal(exterior_ring)?;
+
+        let u_axis = if normal.z.abs() < 0.9 {
+            Vector3::z().cross(&normal).normalize()
+        } else {
+            (Vector3::x() - normal * normal.x).normalize()
+        };
+        let v_axis = normal.cross(&u_axis);
+
+        let projected_rings: Vec<Vec<Point2<f64>>> = rings
+            .iter()
+            .map(|r| {
+                r.iter()
+                    .map(|p| Point2::new(p.coords.dot(&u_axis), p.coords.dot(&v_axis)))
+                    .collect()
+            })
+            .collect();
+        let (min_u, max_u, min_v, max_v) = projected_rings[0].iter().fold(
+            (f64::MAX, f64::MIN, f64::MAX, f64::MIN),
+            |(min_u, max_u, min_v, max_v), p| {
+                (
+                    min_u.min(p.x),
+                    max_u.max(p.x),
+                    min_v.min(p.y),
+                    max_v.max(p.y),
+                )
+            },
+        );
+
+        // the origin lies in the plane at the lower left corner of the bounding box
+        let plane_offset = exterior_ring[0].coords.dot(&normal);
+        let origin = Point3::from(u_axis * min_u + v_axis * min_v + normal * plane_offset);
+        let rings = projected_rings
+            .into_iter()
+            .map(|r| {
+                r.into_iter()
+                    .map(|p| Point2::new(p.x - min_u, p.y - min_v))
+                    .collect()
+            })
+            .collect();
+
+        Some(Self {
+            origin,
+            u_axis,
+            v_axis,
+            cell_size,
+            rings,
+            number_of_columns: ((max_u - min_u) / cell_size).ceil().max(1.0) as i32,
+            number_of_rows: ((max_v - min_v) / cell_size).ceil().max(1.0) as i32,
+        })
+    }
+
+    /// Returns the cells with their center inside the polygon together with the number of points
+    /// falling into them, where the points are projected onto the plane of the polygon.
+    pub(crate) fn derive_cells(&self, points: &[Point3<f64>]) -> Vec<SurfaceCell> {
+        let mut number_of_points: HashMap<(i32, i32), i32> = HashMap::new();
+        for point in points {
+            *number_of_points.entry(self.cell_index(point)).or_default() += 1;
+        }
+
+        let mut cells: Vec<SurfaceCell> = Vec::new();
+        for row_index in 0..self.number_of_rows {
+            for column_index in 0..self.number_of_columns {
+                let center = Point2::new(
+                    (column_index as f64 + 0.5) * self.cell_size,
+                    (row_index as f64 + 0.5) * self.cell_size,
+                );
+                if !contains(&self.rings, &center) {
+                    continue;
+                }
+
+                cells.push(SurfaceCell {
+                    column_index,
+                    row_index,
+                    corners: self.cell_corners(column_index, row_index),
+                    number_of_points: number_of_points
+                        .get(&(column_index, row_index))
+                        .copied()
+                        .unwrap_or_default(),
+                });
+            }
+        }
+        cells
+    }
+
+    fn cell_index(&self, point: &Point3<f64>) -> (i32, i32) {
+        let relative_point = point - self.origin;
+        (
+            (relative_point.dot(&self.u_axis) / self.cell_size).floor() as i32,
+            (relative_point.dot(&self.v_axis) / self.cell_size).floor() as i32,
+        )
+    }
+
+    fn cell_corners(&self, column_index: i32, row_index: i32) -> [Point3<f64>; 4] {
+        let corner = |column: i32, row: i32| -> Point3<f64> {
+            self.origin
+                + self.u_axis * (column as f64 * self.cell_size)
+                + self.v_axis * (row as f64 * self.cell_size)
+        };
+        [
+            corner(column_index, row_index),
+            corner(column_index + 1, row_index),
+            corner(column_index + 1, row_index + 1),
+            corner(column_index, row_index + 1),
+        ]
+    }
+}
+
+/// Returns the unit normal of a ring derived with Newell's method.
+fn newell_normal(ring: &[Point3<f64>]) -> Option<Vector3<f64>> {
+    let normal: Vector3<f64> = ring
+        .iter()
+        .zip(ring.iter().cycle().skip(1))
+        .map(|(current, next)| {
+            Vector3::new(
+                (current.y - next.y) * (current.z + next.z),
+                (current.z - next.z) * (current.x + next.x),
+                (current.x - next.x) * (current.y + next.y),
+            )
+        })
+        .sum();
+    normal.try_normalize(f64::EPSILON)
+}
+
+/// Point in polygon test with the even-odd rule, so that interior rings are excluded.
+fn contains(rings: &[Vec<Point2<f64>>], point: &Point2<f64>) -> bool {
+    let mut inside = false;
+    for ring in rings {
+        for (current, next) in ring.iter().zip(ring.iter().cycle().skip(1)) {
+            if (current.y > point.y) != (next.y > point.y)
+                && point.x
+                    < (next.x - current.x) * (point.y - current.y) / (next.y - current.y)
+                        + current.x
+            {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+/// Rasterizes the valid surfaces of all features with associated points of a dataset and stores
+/// the cells together with their number of associated reflections in `surface_coverage_cell`.
+///
+/// Each reflection is assigned to the surfaces of its feature, which are closest to it.
+///
+/// Returns the number of written cells.
+pub fn create_surface_coverage_cells(
+    connection: &mut PgConnection,
+    dataset_id: i32,
+    srid: i32,
+    cell_size: f64,
+) -> Result<usize, Error> {
+    let query =
+        format!("DELETE FROM sensor_data.surface_coverage_cell WHERE dataset_id = {dataset_id};");
+    diesel::sql_query(query).execute(connection)?;
+
+    let surface_condition = dataset_surface_condition("fgd", dataset_id);
+    let query = format!(
+        "SELECT
+    fgd.id AS feature_geometry_data_id,
+    fgd.feature_id,
+    (d.dump).path[1] AS ring,
+    ST_X((d.dump).geom) AS x,
+    ST_Y((d.dump).geom) AS y,
+    ST_Z((d.dump).geom) AS z
+FROM sensor_data.feature_geometry_data as fgd
+CROSS JOIN LATERAL (SELECT ST_DumpPoints(fgd.valid_geometry) AS dump) as d
+WHERE fgd.valid_geometry IS NOT NULL
+  AND {surface_condition}
+  AND fgd.feature_id IN (
+    SELECT DISTINCT feature_id
+    FROM sensor_data.association_point_model
+    WHERE dataset_id = {dataset_id})
+ORDER BY fgd.id, (d.dump).path[1], (d.dump).path[2];"
+    );
+    let vertices: Vec<SurfaceVertexEntry> = diesel::sql_query(query).load(connection)?;
+
+    let query = format!(
+        "SELECT
+    fgd.id AS feature_geometry_data_id,
+    ST_X(b.reflection) AS x,
+    ST_Y(b.reflection) AS y,
+    ST_Z(b.reflection) AS z
+FROM sensor_data.association_point_model as apm
+JOIN sensor_data.beam as b ON apm.beam_id = b.id
+JOIN sensor_data.feature_geometry_data as fgd
+  ON fgd.feature_id = apm.feature_id
+ AND fgd.valid_geometry IS NOT NULL
+ AND {surface_condition}
+ AND ST_3DDWithin(fgd.geometry, b.reflection, apm.distance + {SURFACE_DISTANCE_TOLERANCE})
+WHERE apm.dataset_id = {dataset_id};"
+    );
+    let points: Vec<SurfacePointEntry> = diesel::sql_query(query).load(connection)?;
+    let mut surface_points: HashMap<i64, Vec<Point3<f64>>> = HashMap::new();
+    for point in points {
+        surface_points
+            .entry(point.feature_geometry_data_id)
+            .or_default()
+            .push(Point3::new(point.x, point.y, point.z));
+    }
+
+    let mut surfaces: BTreeMap<i64, (i64, Vec<Vec<Point3<f64>>>)> = BTreeMap::new();
+    for vertex in vertices {
+        let (_, rings) = surfaces
+            .entry(vertex.feature_geometry_data_id)
+            .or_insert_with(|| (vertex.feature_id, Vec::new()));
+        if rings.len() < vertex.ring as usize {
+            rings.push(Vec::new());
+        }
+        rings
+            .last_mut()
+            .unwrap()
+            .push(Point3::new(vertex.x, vertex.y, vertex.z));
+    }
+
+    let mut cells: Vec<(i64, i64, SurfaceCell)> = Vec::new();
+    for (feature_geometry_data_id, (feature_id, rings)) in surfaces {
+        let Some(raster) = SurfaceRaster::new(&rings, cell_size) else {
+            continue;
+        };
+        let points = surface_points
+            .remove(&feature_geometry_data_id)
+            .unwrap_or_default();
+        cells.extend(
+            raster
+                .derive_cells(&points)
+                .into_iter()
+                .map(|c| (feature_geometry_data_id, feature_id, c)),
+        );
+    }
+
+    copy_surface_coverage_cells(connection, dataset_id, srid, cell_size, &cells)
+}
+
+/// Returns red for unobserved cells and a gradient from yellow to green for observed ones, which
+/// is saturated at `full_coverage_number_of_points`.
+pub(crate) fn coverage_color(
+    number_of_points: i32,
+    full_coverage_number_of_points: i32,
+) -> Point3<f32> {
+    if number_of_points <= 0 {
+        return Point3::new(1.0, 0.0, 0.0);
+    }
+    let ratio = (number_of_points as f32 / full_coverage_number_of_points.max(1) as f32).min(1.0);
+    Point3::new(1.0 - ratio, 1.0, 0.0)
+}
+
+/// Triangulates the coverage cells into a colored mesh.
+///
+/// Since the graphics use single precision, the vertices are translated by the returned offset.
+pub(crate) fn coverage_cells_to_mesh(
+    cells: &[([Point3<f64>; 4], i32)],
+    full_coverage_number_of_points: i32,
+) -> Result<(TriangleMesh, Vector3<f64>), Error> {
+    let offset: Vector3<f64> = cells
+        .iter()
+        .flat_map(|(corners, _)| corners.iter())
+        .fold(Vector3::repeat(f64::MAX), |offset, p| offset.inf(&p.coords))
+        .map(|c| c.floor());
+
+    let triangles: Vec<Triangle> = cells
+        .iter()
+        .flat_map(|(corners, number_of_points)| {
+            let color = coverage_color(*number_of_points, full_coverage_number_of_points);
+            let vertices: Vec<Vertex> = corners
+                .iter()
+                .map(|p| Vertex::new((p - offset).cast::<f32>(), color))
+                .collect();
+            [
+                vec![vertices[0], vertices[1], vertices[2]],
+                vec![vertices[0], vertices[2], vertices[3]],
+            ]
+        })
+        .map(Triangle::new)
+        .collect::<Result<Vec<Triangle>, egraphics::Error>>()?;
+
+    let mesh = TriangleMesh::new(triangles)?;
+    Ok((mesh, offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn facade() -> Vec<Point3<f64>> {
+        vec![
+            Point3::new(10.0, 5.0, 0.0),
+            Point3::new(12.0, 5.0, 0.0),
+            Point3::new(12.0, 5.0, 1.0),
+            Point3::new(10.0, 5.0, 1.0),
+            Point3::new(10.0, 5.0, 0.0),
+        ]
+    }
+
+    #[test]
+    fn test_facade_raster() {
+        let raster = SurfaceRaster::new(&[facade()], 0.5).unwrap();
+        let points = vec![
+            Point3::new(10.1, 5.02, 0.1),
+            Point3::new(10.2, 4.98, 0.2),
+            Point3::new(11.9, 5.0, 0.9),
+        ];
+
+        let cells = raster.derive_cells(&points);
+
+        assert_eq!(cells.len(), 8);
+        let observed_cells: Vec<&SurfaceCell> =
+            cells.iter().filter(|c| c.number_of_points > 0).collect();
+        assert_eq!(observed_cells.len(), 2);
+        assert_eq!(
+            observed_cells
+                .iter()
+                .map(|c| c.number_of_points)
+                .sum::<i32>(),
+            3
+        );
+        for cell in &cells {
+            for corner in &cell.corners {
+                assert!((corner.y - 5.0).abs() < 1.0e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_raster_excludes_holes() {
+        let hole = vec![
+            Point3::new(0.5, 0.5, 3.0),
+            Point3::new(0.5, 1.5, 3.0),
+            Point3::new(1.5, 1.5, 3.0),
+            Point3::new(1.5, 0.5, 3.0),
+            Point3::new(0.5, 0.5, 3.0),
+        ];
+        let roof = vec![
+            Point3::new(0.0, 0.0, 3.0),
+            Point3::new(2.0, 0.0, 3.0),
+            Point3::new(2.0, 2.0, 3.0),
+            Point3::new(0.0, 2.0, 3.0),
+            Point3::new(0.0, 0.0, 3.0),
+        ];
+        let raster = SurfaceRaster::new(&[roof, hole], 0.5).unwrap();
+
+        let cells = raster.derive_cells(&[]);
+
+        assert_eq!(cells.len(), 12);
+    }
+
+    #[test]
+    fn test_degenerate_polygon() {
+        let line = vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, 0.0),
+        ];
+
+        assert!(SurfaceRaster::new(&[line], 0.5).is_none());
+    }
+
+    #[test]
+    fn test_coverage_color() {
+        assert_eq!(coverage_color(0, 10), Point3::new(1.0, 0.0, 0.0));
+        assert_eq!(coverage_color(5, 10), Point3::new(0.5, 1.0, 0.0));
+        assert_eq!(coverage_color(20, 10), Point3::new(0.0, 1.0, 0.0));
+    }
+}
@@ -1,4 +1,13 @@
 //! `sensor-data-analyzer` is a tool for associating, analyzing, and enriching sensor data
 //! by leveraging semantic environment models.
 
-pub use sensor_data_analyzer_core::{extract_lidar_text_mesh, DatabaseManager};
+pub use sensor_data_analyzer_core::{
+    extract_lidar_meshes, write_change_report, write_statistics, AssociationMode, BoundingBox,
+    ChangeDetectionParameters, ChangeReport, ClassStatistics, CopyFormat, DatabaseManager, Dataset,
+    DatasetStatistics, DistanceHistogramBin, DistanceThresholds, DownloadChunking, DownloadFilter,
+    DownloadFormat, Endianess, FeatureObservationSummary, FeatureStatistics, GeometrySelection,
+    LidarSensorProfile, PatchPoint, PatchUncompressedBinary, PointCloudFormat, RangeImageFormat,
+    RangeImageMeshingParameters, SensorAxisProfile, SensorFrame, SensorProfileRegistry,
+    StatisticsFormat, UnexplainedCluster, UnobservedFeature, UploadMethod,
+    DEFAULT_DISTANCE_THRESHOLD, POINT_CLOUD_SCHEMA,
+};